@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use i8080_emu::difftest::Rng;
+use i8080_emu::invaders::SpaceInvaders;
+use i8080_emu::{emulate_8080_op, format_8080_op, NullBus, Runner, State8080, StopCondition};
+
+//Throughput of the cpu core, the Runner and the disassembler on fixed
+//workloads. "runner, watched" stops on a write to c000h, which none of them
+//touch, that puts the Sentinel between cpu and bus like watchpoints and
+//history do.
+//Each figure is the best of RUNS, noise on a busy machine only ever slows a
+//run down.
+//
+//  cargo bench --bench throughput -- --save <file>      keep the results
+//  cargo bench --bench throughput -- --baseline <file>  compare against them
+//
+//The attract mode workload needs the Space Invaders ROM, 8 KiB of invaders.h,
+//.g, .f and .e, in I8080_INVADERS_ROM or tests/roms/invaders.rom.
+
+const RUNS: usize = 5;
+const INSTRUCTIONS: u64 = 20_000_000;
+const ATTRACT_FRAMES: u64 = 600;
+const DISASSEMBLY_PASSES: usize = 20;
+
+//Adds, increments, XORs and ORs in a counted loop that never ends
+const ALU_LOOP: &[u8] = &[
+    0x3e, 0x00, //0000 MVI A,0
+    0x06, 0x00, //0002 MVI B,0
+    0x80, //0004 LOOP: ADD B
+    0x3c, //0005 INR A
+    0xa8, //0006 XRA B
+    0xb1, //0007 ORA C
+    0x05, //0008 DCR B
+    0xc2, 0x04, 0x00, //0009 JNZ LOOP
+    0xc3, 0x04, 0x00, //000c JMP LOOP
+];
+
+//Copies 4 KiB from 1000h to 2000h a byte at a time, over and over
+const MEMCOPY: &[u8] = &[
+    0x21, 0x00, 0x10, //0000 LXI H,1000h
+    0x11, 0x00, 0x20, //0003 LXI D,2000h
+    0x01, 0x00, 0x10, //0006 LXI B,1000h
+    0x7e, //0009 LOOP: MOV A,M
+    0x12, //000a STAX D
+    0x23, //000b INX H
+    0x13, //000c INX D
+    0x0b, //000d DCX B
+    0x78, //000e MOV A,B
+    0xb1, //000f ORA C
+    0xc2, 0x09, 0x00, //0010 JNZ LOOP
+    0xc3, 0x00, 0x00, //0013 JMP 0000
+];
+
+//Three levels of calls that save registers on the stack
+const CALLS: &[u8] = &[
+    0x31, 0x00, 0x00, //0000 LXI SP,0000
+    0xcd, 0x0a, 0x00, //0003 LOOP: CALL F1
+    0xc3, 0x03, 0x00, //0006 JMP LOOP
+    0x00, //0009
+    0xc5, //000a F1: PUSH B
+    0xcd, 0x12, 0x00, //000b CALL F2
+    0xc1, //000e POP B
+    0x0c, //000f INR C
+    0xc9, //0010 RET
+    0x00, //0011
+    0xe5, //0012 F2: PUSH H
+    0xcd, 0x19, 0x00, //0013 CALL F3
+    0xe1, //0016 POP H
+    0xc9, //0017 RET
+    0x00, //0018
+    0x23, //0019 F3: INX H
+    0xc9, //001a RET
+];
+
+fn load(program: &[u8]) -> Box<State8080> {
+    let mut state = Box::new(State8080::new());
+    state.memory[..program.len()].copy_from_slice(program);
+    state
+}
+
+//Best rate of RUNS runs of f, which returns how much work it did
+fn best<F: FnMut() -> u64>(mut f: F) -> f64 {
+    (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            let work = f();
+            work as f64 / start.elapsed().as_secs_f64()
+        })
+        .fold(0.0, f64::max)
+}
+
+fn core(program: &[u8]) -> u64 {
+    let mut state = load(program);
+    for _ in 0..INSTRUCTIONS {
+        emulate_8080_op(&mut state, &mut NullBus);
+    }
+    INSTRUCTIONS
+}
+
+fn runner(program: &[u8], mut conditions: Vec<StopCondition>) -> u64 {
+    let mut state = load(program);
+    conditions.push(StopCondition::Instructions(INSTRUCTIONS));
+    let mut runner = Runner::new(conditions);
+    runner.run(&mut state, &mut NullBus);
+    runner.instructions
+}
+
+//Runs the attract mode for ATTRACT_FRAMES frames with no input
+fn attract(rom: &[u8]) -> u64 {
+    let mut machine = SpaceInvaders::new(rom).expect("invalid Space Invaders ROM");
+    let mut runner = Runner::new(Vec::new());
+    for _ in 0..ATTRACT_FRAMES {
+        machine.run_frame(&mut runner);
+    }
+    runner.instructions
+}
+
+fn invaders_rom() -> Option<Vec<u8>> {
+    let path = env::var_os("I8080_INVADERS_ROM").map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/invaders.rom"));
+    fs::read(path).ok()
+}
+
+//Disassembles the whole image, returns the number of instructions
+fn disassemble(image: &[u8]) -> u64 {
+    let mut instructions = 0;
+    for _ in 0..DISASSEMBLY_PASSES {
+        let mut pc = 0;
+        while pc < image.len() {
+            let (text, length) = format_8080_op(image, pc);
+            std::hint::black_box(text);
+            pc += length;
+            instructions += 1;
+        }
+    }
+    instructions
+}
+
+//Results of an earlier --save, one "name<TAB>value" per line
+fn read_baseline(path: &str) -> HashMap<String, f64> {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("Error reading '{}': {}", path, e));
+    text.lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter_map(|(name, value)| Some((name.to_string(), value.parse().ok()?)))
+        .collect()
+}
+
+struct Report {
+    baseline: Option<HashMap<String, f64>>,
+    results: Vec<(String, f64)>,
+}
+
+impl Report {
+    fn section(&self, title: &str, unit: &str) {
+        println!();
+        match self.baseline {
+            _ if unit.is_empty() => println!("{}", title),
+            Some(_) => println!("{:<32} {:>12} {:>9}", title, unit, "change"),
+            None => println!("{:<32} {:>12}", title, unit),
+        }
+    }
+
+    fn result(&mut self, name: &str, value: f64) {
+        match self.baseline.as_ref().and_then(|b| b.get(name)) {
+            Some(before) => println!("{:<32} {:>12.1} {:>+8.1}%", name, value, (value / before - 1.0) * 100.0),
+            None => println!("{:<32} {:>12.1}", name, value),
+        }
+        self.results.push((name.to_string(), value));
+    }
+
+    fn skipped(&self, name: &str, why: &str) {
+        println!("{:<32} {:>12}", name, format!("skipped, {}", why));
+    }
+}
+
+fn main() {
+    let mut save = None;
+    let mut baseline = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--save" => save = args.next(),
+            "--baseline" => baseline = args.next().map(|path| read_baseline(&path)),
+            //cargo bench passes --bench, harness = false benchmarks ignore it
+            _ => {}
+        }
+    }
+    let mut report = Report { baseline, results: Vec::new() };
+
+    report.section("cpu", "M instr/s");
+    let workloads: [(&str, &[u8]); 3] = [("alu loop", ALU_LOOP), ("memcopy", MEMCOPY), ("calls", CALLS)];
+    for (name, program) in workloads.iter() {
+        report.result(&format!("{}, core", name), best(|| core(program)) / 1e6);
+        report.result(&format!("{}, runner", name), best(|| runner(program, Vec::new())) / 1e6);
+        report.result(&format!("{}, runner, watched", name),
+                      best(|| runner(program, vec![StopCondition::MemoryWrite(0xc000)])) / 1e6);
+    }
+    match invaders_rom() {
+        Some(rom) => report.result("invaders attract, runner", best(|| attract(&rom)) / 1e6),
+        None => report.skipped("invaders attract, runner", "no ROM"),
+    }
+
+    //Random bytes, so every opcode and operand shows up
+    let mut rng = Rng::new(50);
+    let image: Vec<u8> = (0..0x10000).map(|_| rng.next_u64() as u8).collect();
+    report.section("disassembler, 64 KiB", "M instr/s");
+    report.result("format_8080_op", best(|| disassemble(&image)) / 1e6);
+
+    report.section("assembler", "");
+    report.skipped("assemble", "no assembler in this crate");
+
+    if let Some(path) = save {
+        let text: String = report.results.iter().map(|(name, value)| format!("{}\t{}\n", name, value)).collect();
+        fs::write(&path, text).unwrap_or_else(|e| panic!("Error writing '{}': {}", path, e));
+    }
+}
@@ -0,0 +1,62 @@
+/* C API of the i8080-emu Intel 8080 emulator, see src/lib.rs */
+
+#ifndef I8080_H
+#define I8080_H
+
+/* Written by hand to match src/lib.rs, tests/header.rs checks that every
+   exported function is declared */
+
+#include <stdint.h>
+#include <stddef.h>
+
+typedef struct I8080 I8080;
+
+typedef struct I8080Registers {
+  uint8_t a;
+  uint8_t b;
+  uint8_t c;
+  uint8_t d;
+  uint8_t e;
+  uint8_t h;
+  uint8_t l;
+  uint8_t f;
+  uint16_t sp;
+  uint16_t pc;
+  uint8_t inte;
+  uint8_t halted;
+  uint64_t cycles;
+} I8080Registers;
+
+typedef uint8_t (*I8080ReadFn)(void *user, uint16_t addr);
+
+typedef void (*I8080WriteFn)(void *user, uint16_t addr, uint8_t value);
+
+typedef uint8_t (*I8080InFn)(void *user, uint8_t port);
+
+typedef void (*I8080OutFn)(void *user, uint8_t port, uint8_t value);
+
+struct I8080 *i8080_new(void);
+
+void i8080_free(struct I8080 *cpu);
+
+int32_t i8080_load(struct I8080 *cpu, uint16_t addr, const uint8_t *data, size_t len);
+
+uint8_t i8080_peek(const struct I8080 *cpu, uint16_t addr);
+
+void i8080_poke(struct I8080 *cpu, uint16_t addr, uint8_t value);
+
+void i8080_set_memory_callbacks(struct I8080 *cpu, I8080ReadFn read, I8080WriteFn write, void *user);
+
+void i8080_set_port_callbacks(struct I8080 *cpu, I8080InFn input, I8080OutFn output, void *user);
+
+uint32_t i8080_step(struct I8080 *cpu);
+
+uint64_t i8080_run(struct I8080 *cpu, uint64_t cycles);
+
+int32_t i8080_interrupt(struct I8080 *cpu, uint8_t num);
+
+void i8080_get_registers(const struct I8080 *cpu, struct I8080Registers *registers);
+
+void i8080_set_registers(struct I8080 *cpu, const struct I8080Registers *registers);
+
+#endif /* I8080_H */
@@ -0,0 +1,240 @@
+use std::os::raw::c_void;
+
+use i8080_emu::i8080cpu::{self, Bus, State8080};
+
+//C API over State8080, declared in include/i8080.h, which has to be updated
+//by hand along with this file. A cpu comes from i8080_new and goes back
+//through i8080_free. Passed a NULL cpu, functions do nothing and return 0,
+//i8080_load returns -1.
+
+pub type I8080ReadFn = extern "C" fn(user: *mut c_void, addr: u16) -> u8;
+pub type I8080WriteFn = extern "C" fn(user: *mut c_void, addr: u16, value: u8);
+pub type I8080InFn = extern "C" fn(user: *mut c_void, port: u8) -> u8;
+pub type I8080OutFn = extern "C" fn(user: *mut c_void, port: u8, value: u8);
+
+//Every register, the flags as the low byte of PSW
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct I8080Registers {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub f: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub inte: u8,
+    pub halted: u8,
+    pub cycles: u64,
+}
+
+//Memory goes to the cpu's own 64 KiB unless callbacks are set, ports read
+//0xff and drop writes unless callbacks are set. Instruction bytes are read
+//through the read callback too, each once.
+struct Callbacks {
+    read: Option<I8080ReadFn>,
+    write: Option<I8080WriteFn>,
+    memory_user: *mut c_void,
+    input: Option<I8080InFn>,
+    output: Option<I8080OutFn>,
+    port_user: *mut c_void,
+}
+
+impl Bus for Callbacks {
+    fn read(&mut self, memory: &[u8], addr: u16) -> u8 {
+        match self.read {
+            Some(read) => read(self.memory_user, addr),
+            None => memory[addr as usize],
+        }
+    }
+
+    fn fetch(&mut self, memory: &[u8], addr: u16) -> u8 {
+        self.read(memory, addr)
+    }
+
+    fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+        match self.write {
+            Some(write) => write(self.memory_user, addr, value),
+            None => memory[addr as usize] = value,
+        }
+    }
+
+    //The host's memory is not the machine's
+    fn write_target(&self, addr: u16) -> Option<u16> {
+        match self.write {
+            Some(_) => None,
+            None => Some(addr),
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        match self.input {
+            Some(input) => input(self.port_user, port),
+            None => 0xff,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if let Some(output) = self.output {
+            output(self.port_user, port, value);
+        }
+    }
+}
+
+pub struct I8080 {
+    state: State8080,
+    bus: Callbacks,
+}
+
+impl I8080 {
+    fn step(&mut self) -> u32 {
+        i8080cpu::emulate_8080_op(&mut self.state, &mut self.bus)
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_new() -> Box<I8080> {
+    Box::new(I8080 {
+        state: State8080::new(),
+        bus: Callbacks {
+            read: None,
+            write: None,
+            memory_user: std::ptr::null_mut(),
+            input: None,
+            output: None,
+            port_user: std::ptr::null_mut(),
+        },
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_free(cpu: Option<Box<I8080>>) {
+    drop(cpu);
+}
+
+//Copies len bytes from data into the cpu's memory at addr. Returns 0, or -1
+//when they don't fit below 0x10000. data must point at len readable bytes.
+#[no_mangle]
+#[allow(clippy::missing_safety_doc)]
+pub unsafe extern "C" fn i8080_load(cpu: Option<&mut I8080>, addr: u16, data: *const u8, len: usize) -> i32 {
+    let cpu = match cpu {
+        Some(cpu) => cpu,
+        None => return -1,
+    };
+    let start = addr as usize;
+    if start + len > cpu.state.memory.len() {
+        return -1;
+    }
+    if len > 0 {
+        let data = std::slice::from_raw_parts(data, len);
+        cpu.state.memory[start..start + len].copy_from_slice(data);
+    }
+    0
+}
+
+//The cpu's own memory, not going through the read and write callbacks.
+//With a read callback it holds the last fetched opcodes.
+#[no_mangle]
+pub extern "C" fn i8080_peek(cpu: Option<&I8080>, addr: u16) -> u8 {
+    cpu.map_or(0, |cpu| cpu.state.memory[addr as usize])
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_poke(cpu: Option<&mut I8080>, addr: u16, value: u8) {
+    if let Some(cpu) = cpu {
+        cpu.state.memory[addr as usize] = value;
+    }
+}
+
+//Routes memory accesses to read and write, user is passed back to both.
+//NULL callbacks go back to the cpu's own memory.
+#[no_mangle]
+pub extern "C" fn i8080_set_memory_callbacks(cpu: Option<&mut I8080>, read: Option<I8080ReadFn>,
+                                             write: Option<I8080WriteFn>, user: *mut c_void) {
+    if let Some(cpu) = cpu {
+        cpu.bus.read = read;
+        cpu.bus.write = write;
+        cpu.bus.memory_user = user;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_set_port_callbacks(cpu: Option<&mut I8080>, input: Option<I8080InFn>,
+                                           output: Option<I8080OutFn>, user: *mut c_void) {
+    if let Some(cpu) = cpu {
+        cpu.bus.input = input;
+        cpu.bus.output = output;
+        cpu.bus.port_user = user;
+    }
+}
+
+//Executes one instruction, returns its cycles. A halted cpu idles for 4.
+#[no_mangle]
+pub extern "C" fn i8080_step(cpu: Option<&mut I8080>) -> u32 {
+    cpu.map_or(0, |cpu| cpu.step())
+}
+
+//Executes instructions until at least cycles have passed, returns how many did
+#[no_mangle]
+pub extern "C" fn i8080_run(cpu: Option<&mut I8080>, cycles: u64) -> u64 {
+    let cpu = match cpu {
+        Some(cpu) => cpu,
+        None => return 0,
+    };
+    let mut ran = 0;
+    while ran < cycles {
+        ran += cpu.step() as u64;
+    }
+    ran
+}
+
+//Executes RST num when interrupts are enabled. Returns 1 when it was taken.
+#[no_mangle]
+pub extern "C" fn i8080_interrupt(cpu: Option<&mut I8080>, num: u8) -> i32 {
+    cpu.map_or(0, |cpu| i8080cpu::generate_interrupt(&mut cpu.state, &mut cpu.bus, num) as i32)
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_get_registers(cpu: Option<&I8080>, registers: Option<&mut I8080Registers>) {
+    if let (Some(cpu), Some(registers)) = (cpu, registers) {
+        let state = &cpu.state;
+        *registers = I8080Registers {
+            a: state.a,
+            b: state.b,
+            c: state.c,
+            d: state.d,
+            e: state.e,
+            h: state.h,
+            l: state.l,
+            f: state.cc.to_byte(),
+            sp: state.sp,
+            pc: state.pc,
+            inte: state.int_enable as u8,
+            halted: state.halted as u8,
+            cycles: state.cycles,
+        };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_set_registers(cpu: Option<&mut I8080>, registers: Option<&I8080Registers>) {
+    if let (Some(cpu), Some(registers)) = (cpu, registers) {
+        let state = &mut cpu.state;
+        state.a = registers.a;
+        state.b = registers.b;
+        state.c = registers.c;
+        state.d = registers.d;
+        state.e = registers.e;
+        state.h = registers.h;
+        state.l = registers.l;
+        state.cc.set_byte(registers.f);
+        state.sp = registers.sp;
+        state.pc = registers.pc;
+        state.int_enable = registers.inte != 0;
+        state.halted = registers.halted != 0;
+        state.cycles = registers.cycles;
+    }
+}
@@ -0,0 +1,118 @@
+/* Drives the cpu through include/i8080.h, exits non-zero on the first failed check */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "i8080.h"
+
+#define CHECK(cond) do { \
+        if (!(cond)) { \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__, __LINE__, #cond); \
+            exit(1); \
+        } \
+    } while (0)
+
+static uint8_t outputs[256];
+
+static uint8_t port_in(void *user, uint8_t port) {
+    CHECK(user == outputs);
+    return port == 0x10 ? 0x29 : 0xff;
+}
+
+static void port_out(void *user, uint8_t port, uint8_t value) {
+    ((uint8_t *)user)[port] = value;
+}
+
+static uint8_t ram[0x10000];
+
+static uint8_t ram_read(void *user, uint16_t addr) {
+    return ((uint8_t *)user)[addr];
+}
+
+static void ram_write(void *user, uint16_t addr, uint8_t value) {
+    ((uint8_t *)user)[addr] = value;
+}
+
+static void run_until_halted(I8080 *cpu) {
+    I8080Registers regs;
+    for (int i = 0; i < 100; i++) {
+        i8080_get_registers(cpu, &regs);
+        if (regs.halted)
+            return;
+        i8080_step(cpu);
+    }
+    CHECK(!"cpu did not halt");
+}
+
+int main(void) {
+    static const uint8_t program[] = {
+        0x31, 0x00, 0x01, /* 0000 LXI SP,0100 */
+        0xfb,             /* 0003 EI */
+        0xdb, 0x10,       /* 0004 IN 10h */
+        0x3c,             /* 0006 INR A */
+        0xd3, 0x11,       /* 0007 OUT 11h */
+        0x76,             /* 0009 HLT */
+        0x76,             /* 000a HLT */
+    };
+    static const uint8_t rst7[] = {
+        0x3e, 0x42,       /* 0038 MVI A,42h */
+        0xd3, 0x12,       /* 003a OUT 12h */
+        0xc9,             /* 003c RET */
+    };
+    I8080Registers regs;
+
+    I8080 *cpu = i8080_new();
+    CHECK(cpu != NULL);
+    CHECK(i8080_load(cpu, 0x0000, program, sizeof program) == 0);
+    CHECK(i8080_load(cpu, 0x0038, rst7, sizeof rst7) == 0);
+    CHECK(i8080_load(cpu, 0xffff, program, 2) == -1);
+    CHECK(i8080_peek(cpu, 0x0004) == 0xdb);
+    i8080_set_port_callbacks(cpu, port_in, port_out, outputs);
+
+    /* IN, INR and OUT through the port callbacks */
+    CHECK(i8080_step(cpu) == 10);
+    run_until_halted(cpu);
+    CHECK(outputs[0x11] == 0x2a);
+    i8080_get_registers(cpu, &regs);
+    CHECK(regs.a == 0x2a && regs.pc == 0x000a && regs.sp == 0x0100 && regs.inte == 1);
+    CHECK(regs.cycles == 10 + 4 + 10 + 5 + 10 + 7);
+
+    /* RST 7 wakes the cpu, the handler returns to the second HLT */
+    CHECK(i8080_interrupt(cpu, 7) == 1);
+    CHECK(i8080_run(cpu, 7 + 10 + 10) >= 27);
+    run_until_halted(cpu);
+    CHECK(outputs[0x12] == 0x42);
+    i8080_get_registers(cpu, &regs);
+    CHECK(regs.pc == 0x000b && regs.inte == 0);
+    CHECK(i8080_interrupt(cpu, 7) == 0);
+
+    /* Registers round trip, flags as the low byte of PSW */
+    regs.b = 0x12; regs.c = 0x34; regs.f = 0xd7; regs.pc = 0x0100; regs.halted = 0;
+    i8080_set_registers(cpu, &regs);
+    memset(&regs, 0, sizeof regs);
+    i8080_get_registers(cpu, &regs);
+    CHECK(regs.b == 0x12 && regs.c == 0x34 && regs.f == 0xd7 && regs.pc == 0x0100);
+
+    /* Memory callbacks replace the cpu's own memory */
+    ram[0x0100] = 0x3e; ram[0x0101] = 0x05;                     /* MVI A,5 */
+    ram[0x0102] = 0x32; ram[0x0103] = 0x00; ram[0x0104] = 0x20; /* STA 2000h */
+    ram[0x0105] = 0x76;                                         /* HLT */
+    i8080_set_memory_callbacks(cpu, ram_read, ram_write, ram);
+    run_until_halted(cpu);
+    CHECK(ram[0x2000] == 5);
+    CHECK(i8080_peek(cpu, 0x2000) == 0);
+    i8080_poke(cpu, 0x2000, 9);
+    CHECK(i8080_peek(cpu, 0x2000) == 9 && ram[0x2000] == 5);
+
+    /* NULL is ignored */
+    CHECK(i8080_step(NULL) == 0);
+    CHECK(i8080_run(NULL, 100) == 0);
+    CHECK(i8080_load(NULL, 0, program, 1) == -1);
+    i8080_get_registers(NULL, &regs);
+    i8080_free(NULL);
+
+    i8080_free(cpu);
+    printf("c api ok\n");
+    return 0;
+}
@@ -0,0 +1,43 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+//Builds the static library, compiles tests/c_api.c against it and
+//include/i8080.h and runs it. Needs a C compiler, cc or $CC.
+#[test]
+fn c_program() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    //cargo test only builds the rlib, the staticlib gets its own target dir
+    //so this doesn't wait on the build directory lock
+    let target = root.parent().unwrap().join("target/ffi");
+    let built = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .args(["build", "--offline", "--quiet", "--lib", "--manifest-path"])
+        .arg(root.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target)
+        .output()
+        .expect("failed to run cargo");
+    assert!(built.status.success(), "building libi8080.a failed:\n{}", String::from_utf8_lossy(&built.stderr));
+    let target = target.join("debug");
+    let exe = target.join(format!("c_api-{}", std::process::id()));
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+
+    let compiled = Command::new(&cc)
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(root.join("include"))
+        .arg(root.join("tests/c_api.c"))
+        .arg(target.join("libi8080.a"))
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&exe)
+        .output()
+        .unwrap_or_else(|e| panic!("{} not available ({}), set CC to a C compiler", cc, e));
+    assert!(compiled.status.success(), "compiling c_api.c failed:\n{}",
+            String::from_utf8_lossy(&compiled.stderr));
+
+    let output = Command::new(&exe).output().expect("failed to run c_api");
+    std::fs::remove_file(&exe).unwrap();
+    assert!(output.status.success(), "c_api failed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}
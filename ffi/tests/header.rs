@@ -0,0 +1,37 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+//Names after each occurrence of prefix, up to the first character that
+//can't be in an identifier
+fn names(text: &str, prefix: &str) -> BTreeSet<String> {
+    text.split(prefix)
+        .skip(1)
+        .map(|rest| rest.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect::<String>())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+//The header is kept by hand, so every function and type lib.rs exports has
+//to be declared in it and it can't declare functions lib.rs dropped
+#[test]
+fn header_matches_exports() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let source = fs::read_to_string(root.join("src/lib.rs")).unwrap();
+    let header = fs::read_to_string(root.join("include/i8080.h")).unwrap();
+
+    let exported = names(&source, "extern \"C\" fn ");
+    let declared: BTreeSet<String> = names(&header, " i8080_")
+        .into_iter()
+        .chain(names(&header, " *i8080_"))
+        .map(|name| format!("i8080_{}", name))
+        .collect();
+    assert_eq!(exported.len(), 12);
+    assert_eq!(exported, declared);
+
+    let types = names(&source, "pub type ").into_iter().chain(names(&source, "pub struct "));
+    for name in types {
+        assert!(header.contains(&format!("typedef struct {}", name)) || header.contains(&format!("(*{})", name)),
+                "include/i8080.h doesn't declare {}", name);
+    }
+}
@@ -0,0 +1,22 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use i8080_emu::disassembler;
+
+//Walks the whole input like the disassemble command does. Every instruction
+//is 1 to 3 bytes, and one cut off at the end of the input reads the same as
+//with zero operand bytes following it. There is no assembler yet to check a
+//disassemble and assemble round trip against.
+fuzz_target!(|data: &[u8]| {
+    let mut pc = 0;
+    while pc < data.len() {
+        let (text, length) = disassembler::format_8080_op(data, pc);
+        assert!((1..=3).contains(&length), "{:04x} {} is {} bytes", pc, text, length);
+        if pc + length > data.len() {
+            let mut padded = data[pc..].to_vec();
+            padded.resize(length, 0);
+            assert_eq!(disassembler::format_8080_op(&padded, 0), (text, length));
+        }
+        pc += length;
+    }
+});
@@ -0,0 +1,58 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use i8080_emu::{Bus, State8080};
+
+//Instructions run per input, enough to leave the first loaded page
+const STEPS: usize = 10_000;
+
+//Port reads cycle through the input, so IN sees arbitrary values too
+struct FuzzBus<'a> {
+    data: &'a [u8],
+    next: usize,
+}
+
+impl Bus for FuzzBus<'_> {
+    fn input(&mut self, _port: u8) -> u8 {
+        let value = self.data.get(self.next).copied().unwrap_or(0);
+        self.next = (self.next + 1) % self.data.len().max(1);
+        value
+    }
+}
+
+//Input is 13 register bytes (a b c d e h l f, sp, pc, inte) followed by a
+//memory image loaded at address 0, stepping must never panic whatever pc and
+//sp point at
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 13 {
+        return;
+    }
+    let (registers, image) = data.split_at(13);
+    let mut state = Box::new(State8080::new());
+    state.a = registers[0];
+    state.b = registers[1];
+    state.c = registers[2];
+    state.d = registers[3];
+    state.e = registers[4];
+    state.h = registers[5];
+    state.l = registers[6];
+    state.cc.set_byte(registers[7]);
+    state.sp = u16::from_le_bytes([registers[8], registers[9]]);
+    state.pc = u16::from_le_bytes([registers[10], registers[11]]);
+    state.int_enable = registers[12] & 1 != 0;
+    let len = image.len().min(state.memory.len());
+    state.memory[..len].copy_from_slice(&image[..len]);
+
+    let mut bus = FuzzBus { data: image, next: 0 };
+    for i in 0..STEPS {
+        if state.halted {
+            //Wake it with an RST chosen by the input, like an interrupt controller
+            if !state.int_enable {
+                break;
+            }
+            i8080_emu::generate_interrupt(&mut state, &mut bus, registers[12] >> 1);
+        }
+        let cycles = i8080_emu::emulate_8080_op(&mut state, &mut bus);
+        assert!((4..=18).contains(&cycles), "instruction {} took {} cycles", i, cycles);
+    }
+});
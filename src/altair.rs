@@ -0,0 +1,195 @@
+use std::ops::RangeInclusive;
+
+use crate::console::Console;
+use crate::disk::{Dcdd, SimpleDisk};
+use crate::i8080cpu::{Bus, State8080};
+use crate::loader;
+use crate::runner::{Runner, StopReason};
+use crate::savestate::{Device, Reader, Writer};
+
+//Typed on the host console, ends the emulation instead of reaching the machine
+pub const ESCAPE: u8 = 0x1d; //Ctrl-]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialCard {
+    //88-SIO on ports 0x00 (status) and 0x01 (data)
+    Sio,
+    //First port of an 88-2SIO on 0x10 (status/control) and 0x11 (data)
+    TwoSio,
+}
+
+//64 KiB of RAM, a serial card wired to the host console, the front panel
+//sense switches on port 0xff and optional disk controllers
+pub struct AltairBus<C: Console> {
+    pub console: C,
+    pub card: SerialCard,
+    pub sense_switches: u8,
+    //Memory that ignores writes, for monitor ROMs
+    pub rom: Option<RangeInclusive<u16>>,
+    pub dcdd: Option<Dcdd>,
+    pub simple_disk: Option<SimpleDisk>,
+    acia_control: u8,
+    pub quit: bool,
+}
+
+impl<C: Console> AltairBus<C> {
+    pub fn new(console: C, card: SerialCard) -> AltairBus<C> {
+        AltairBus {
+            console,
+            card,
+            sense_switches: 0,
+            rom: None,
+            dcdd: None,
+            simple_disk: None,
+            acia_control: 0,
+            quit: false,
+        }
+    }
+
+    //Whether a character is waiting. Closed input quits, as a program
+    //polling the status port would wait for it forever.
+    fn available(&mut self) -> bool {
+        if self.quit || self.console.status() {
+            return !self.quit;
+        }
+        self.quit = self.console.closed();
+        false
+    }
+
+    fn read_char(&mut self) -> u8 {
+        if !self.available() {
+            return 0;
+        }
+        match self.console.read() {
+            Some(ESCAPE) | None => {
+                self.quit = true;
+                0
+            },
+            Some(byte) => byte,
+        }
+    }
+}
+
+impl<C: Console> Bus for AltairBus<C> {
+    fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+        if let Some(addr) = self.write_target(addr) {
+            memory[addr as usize] = value;
+        }
+    }
+
+    fn write_target(&self, addr: u16) -> Option<u16> {
+        match &self.rom {
+            Some(rom) if rom.contains(&addr) => None,
+            _ => Some(addr),
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if let Some(value) = self.dcdd.as_mut().and_then(|d| d.input(port)) {
+            return value;
+        }
+        if let Some(value) = self.simple_disk.as_mut().and_then(|d| d.input(port)) {
+            return value;
+        }
+        match (self.card, port) {
+            //Status bits are active low: bit 0 input ready, bit 7 output ready
+            (SerialCard::Sio, 0x00) => if self.available() { 0x00 } else { 0x01 },
+            (SerialCard::Sio, 0x01) => self.read_char(),
+            //MC6850 ACIA: bit 0 receive data register full, bit 1 transmit
+            //data register empty. All bits are clear during master reset.
+            (SerialCard::TwoSio, 0x10) => if self.acia_control & 0x03 == 0x03 {
+                0x00
+            } else if self.available() {
+                0x03
+            } else {
+                0x02
+            },
+            (SerialCard::TwoSio, 0x11) => self.read_char(),
+            (_, 0xff) => self.sense_switches,
+            _ => 0xff,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if self.dcdd.as_mut().is_some_and(|d| d.output(port, value))
+            || self.simple_disk.as_mut().is_some_and(|d| d.output(port, value)) {
+            return;
+        }
+        match (self.card, port) {
+            (SerialCard::Sio, 0x01) | (SerialCard::TwoSio, 0x11) => self.console.write(value & 0x7f),
+            (SerialCard::TwoSio, 0x10) => self.acia_control = value,
+            _ => {},
+        }
+    }
+}
+
+//Sense switches, ACIA control and the disk controller. The serial card and
+//which controller is attached come from the command line and must match.
+impl<C: Console> Device for AltairBus<C> {
+    fn tag(&self) -> [u8; 4] {
+        *b"ALTR"
+    }
+
+    fn save(&self, out: &mut Writer) {
+        out.u8(self.sense_switches);
+        out.u8(self.acia_control);
+        match (&self.dcdd, &self.simple_disk) {
+            (Some(dcdd), _) => {
+                out.u8(1);
+                dcdd.save(out);
+            },
+            (None, Some(simple_disk)) => {
+                out.u8(2);
+                simple_disk.save(out);
+            },
+            (None, None) => out.u8(0),
+        }
+    }
+
+    fn load(&mut self, data: &mut Reader) -> Result<(), String> {
+        let sense_switches = data.u8()?;
+        let acia_control = data.u8()?;
+        match (data.u8()?, self.dcdd.as_mut(), self.simple_disk.as_mut()) {
+            (1, Some(dcdd), _) => dcdd.load(data)?,
+            (2, None, Some(simple_disk)) => simple_disk.load(data)?,
+            (0, None, None) => {},
+            _ => return Err("Save state has a different disk controller".to_string()),
+        }
+        self.sense_switches = sense_switches;
+        self.acia_control = acia_control;
+        Ok(())
+    }
+}
+
+pub struct Altair<C: Console> {
+    pub state: State8080,
+    pub bus: AltairBus<C>,
+}
+
+impl<C: Console> Altair<C> {
+    pub fn new(console: C, card: SerialCard) -> Altair<C> {
+        Altair {
+            state: State8080::new(),
+            bus: AltairBus::new(console, card),
+        }
+    }
+
+    //Loads image at addr and jumps there, rom makes the image read only
+    pub fn load(&mut self, image: &[u8], addr: u16, rom: bool) -> Result<(), String> {
+        loader::load_image(&mut self.state, image, addr)?;
+        if rom && !image.is_empty() {
+            self.bus.rom = Some(addr..=(addr as usize + image.len() - 1) as u16);
+        }
+        Ok(())
+    }
+
+    //Runs until the runner stops, or returns None when the user typed ESCAPE
+    pub fn run(&mut self, runner: &mut Runner) -> Option<StopReason> {
+        while !self.bus.quit {
+            if let Some(reason) = runner.step(&mut self.state, &mut self.bus) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+}
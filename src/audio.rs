@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+//A write to a sound port that changed its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SoundEvent {
+    pub cycle: u64,
+    pub port: u8,
+    pub value: u8,
+}
+
+//Collects sound port writes with the cycle they happened at. Writes that
+//don't change a port's value are dropped, so is the power-on state of 0.
+pub struct SoundRecorder {
+    pub events: Vec<SoundEvent>,
+    last: [u8; 256],
+}
+
+impl SoundRecorder {
+    pub fn new() -> SoundRecorder {
+        SoundRecorder {
+            events: Vec::new(),
+            last: [0; 256],
+        }
+    }
+
+    pub fn record(&mut self, cycle: u64, port: u8, value: u8) {
+        if self.last[port as usize] != value {
+            self.last[port as usize] = value;
+            self.events.push(SoundEvent { cycle, port, value });
+        }
+    }
+
+    //One event per line: cycle, port and value
+    pub fn write_log<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for event in &self.events {
+            writeln!(w, "{} {} {:02x}", event.cycle, event.port, event.value)?;
+        }
+        w.flush()
+    }
+}
+
+impl Default for SoundRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Sound played while a port bit is set. One shot samples start on the rising
+//edge and play to the end, looping ones repeat until the bit is cleared.
+pub struct Sample {
+    pub data: Vec<i16>,
+    pub looping: bool,
+}
+
+impl Sample {
+    //Loads a PCM WAV file, 8 or 16 bit. Stereo is mixed down and the sample
+    //resampled to SAMPLE_RATE.
+    pub fn load(path: &Path, looping: bool) -> io::Result<Sample> {
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        let data = parse_wav(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Sample { data, looping })
+    }
+}
+
+//Mixes the samples for each (port, bit) according to events into a mono
+//track of `cycles` cpu cycles at cpu_hz
+pub fn mix(events: &[SoundEvent], samples: &HashMap<(u8, u8), Sample>,
+           cpu_hz: u64, cycles: u64) -> Vec<i16> {
+    let to_sample = |cycle: u64| (cycle as u128 * SAMPLE_RATE as u128 / cpu_hz as u128) as usize;
+    let length = to_sample(cycles);
+    let mut track = vec![0i32; length];
+
+    for (&(port, bit), sample) in samples {
+        if sample.data.is_empty() {
+            continue;
+        }
+        let mut on = false;
+        let mut start = 0;
+        let edges = events.iter()
+            .filter(|e| e.port == port)
+            .map(|e| (to_sample(e.cycle), e.value & (1 << bit) != 0))
+            .chain(std::iter::once((length, false)));
+        for (at, set) in edges {
+            if set && !on {
+                start = at;
+                if !sample.looping {
+                    play(&mut track, &sample.data, start, start + sample.data.len());
+                }
+            } else if !set && on && sample.looping {
+                play(&mut track, &sample.data, start, at);
+            }
+            on = set;
+        }
+    }
+
+    track.iter().map(|s| (*s).clamp(i16::MIN as i32, i16::MAX as i32) as i16).collect()
+}
+
+//Adds data to track from start, repeating it until end
+fn play(track: &mut [i32], data: &[i16], start: usize, end: usize) {
+    let end = end.min(track.len());
+    for (i, t) in track.iter_mut().enumerate().take(end).skip(start) {
+        *t += data[(i - start) % data.len()] as i32;
+    }
+}
+
+pub fn write_wav<W: Write>(mut w: W, track: &[i16]) -> io::Result<()> {
+    let data_len = track.len() as u32 * 2;
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVEfmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?; //PCM
+    w.write_all(&1u16.to_le_bytes())?; //mono
+    w.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    w.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+    w.write_all(&2u16.to_le_bytes())?; //block align
+    w.write_all(&16u16.to_le_bytes())?; //bits per sample
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())?;
+    for s in track {
+        w.write_all(&s.to_le_bytes())?;
+    }
+    w.flush()
+}
+
+pub fn save_wav(path: &Path, track: &[i16]) -> io::Result<()> {
+    write_wav(BufWriter::new(File::create(path)?), track)
+}
+
+fn parse_wav(buf: &[u8]) -> Result<Vec<i16>, String> {
+    if buf.len() < 12 || &buf[0..4] != b"RIFF" || &buf[8..12] != b"WAVE" {
+        return Err("Not a WAV file".to_string());
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([buf[i], buf[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= buf.len() {
+        let id = &buf[pos..pos + 4];
+        let len = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+        if body + len > buf.len() {
+            return Err("Truncated WAV chunk".to_string());
+        }
+        if id == b"fmt " && len >= 16 {
+            //format tag, channels, rate, bits per sample
+            format = Some((u16_at(body), u16_at(body + 2), u32_at(body + 4), u16_at(body + 14)));
+        } else if id == b"data" {
+            let (tag, channels, rate, bits) = format.ok_or("WAV data before format")?;
+            if tag != 1 || channels == 0 || rate == 0 || (bits != 8 && bits != 16) {
+                return Err("Only 8 and 16 bit PCM WAV files are supported".to_string());
+            }
+            let data = &buf[body..body + len];
+            let frame = channels as usize * bits as usize / 8;
+            let mono: Vec<i32> = data.chunks_exact(frame).map(|f| {
+                let sum: i32 = f.chunks_exact(bits as usize / 8).map(|s| match bits {
+                    8 => (s[0] as i32 - 128) << 8,
+                    _ => i16::from_le_bytes([s[0], s[1]]) as i32,
+                }).sum();
+                sum / channels as i32
+            }).collect();
+            //Nearest neighbour resampling
+            let length = mono.len() as u64 * SAMPLE_RATE as u64 / rate as u64;
+            return Ok((0..length)
+                .map(|i| mono[(i * rate as u64 / SAMPLE_RATE as u64) as usize] as i16)
+                .collect());
+        }
+        pos = body + len + (len & 1);
+    }
+    Err("WAV file has no data".to_string())
+}
@@ -0,0 +1,466 @@
+use std::ops::RangeInclusive;
+
+use crate::i8080cpu::State8080;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    //pc reaches the address
+    Exec(u16),
+    //Data reads and writes in the range, instruction fetches don't count
+    Read(RangeInclusive<u16>),
+    Write(RangeInclusive<u16>),
+    Access(RangeInclusive<u16>),
+    In(u8),
+    Out(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    In,
+    Out,
+}
+
+//A memory or port access during an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    //Memory address or port
+    pub addr: u16,
+    pub value: u8,
+}
+
+pub struct Breakpoint {
+    pub id: usize,
+    pub kind: Kind,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    //Times the location was reached, whether the condition held or not
+    pub hits: u64,
+}
+
+impl Breakpoint {
+    fn matches(&self, access: &Access) -> bool {
+        match (&self.kind, access.kind) {
+            (Kind::Read(range), AccessKind::Read)
+            | (Kind::Write(range), AccessKind::Write)
+            | (Kind::Access(range), AccessKind::Read)
+            | (Kind::Access(range), AccessKind::Write) => range.contains(&access.addr),
+            (Kind::In(port), AccessKind::In) | (Kind::Out(port), AccessKind::Out) => {
+                *port as u16 == access.addr
+            },
+            _ => false,
+        }
+    }
+}
+
+const EXEC: u8 = 0x01;
+const READ: u8 = 0x02;
+const WRITE: u8 = 0x04;
+const IN: u8 = 0x01;
+const OUT: u8 = 0x02;
+
+//Breakpoints and watchpoints checked by the Runner. Which addresses and
+//ports are covered is kept in lookup tables, so instructions that touch
+//nothing of interest cost a table lookup, and nothing at all while the set
+//is empty.
+#[derive(Default)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    next_id: usize,
+    memory: Vec<u8>,
+    ports: Vec<u8>,
+    watching: bool,
+}
+
+impl Breakpoints {
+    pub fn new() -> Breakpoints {
+        Breakpoints::default()
+    }
+
+    //Adds a breakpoint and returns its id, ids count from 1
+    pub fn add(&mut self, kind: Kind, condition: Option<Condition>) -> usize {
+        self.next_id += 1;
+        self.list.push(Breakpoint {
+            id: self.next_id,
+            kind,
+            condition,
+            enabled: true,
+            hits: 0,
+        });
+        self.update();
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: usize) -> bool {
+        let len = self.list.len();
+        self.list.retain(|b| b.id != id);
+        self.update();
+        self.list.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.update();
+    }
+
+    //Changes to enabled or kind take effect with the next update
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.list.iter_mut().find(|b| b.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    //Whether memory accesses and port I/O have to be watched at all
+    pub fn watching(&self) -> bool {
+        self.watching
+    }
+
+    //Rebuilds the lookup tables after breakpoints were changed
+    pub fn update(&mut self) {
+        self.memory.clear();
+        self.ports.clear();
+        self.watching = false;
+        for b in self.list.iter().filter(|b| b.enabled) {
+            if self.memory.is_empty() {
+                self.memory.resize(0x10000, 0);
+                self.ports.resize(0x100, 0);
+            }
+            let (range, flags) = match &b.kind {
+                Kind::Exec(addr) => (*addr..=*addr, EXEC),
+                Kind::Read(range) => (range.clone(), READ),
+                Kind::Write(range) => (range.clone(), WRITE),
+                Kind::Access(range) => (range.clone(), READ | WRITE),
+                Kind::In(port) => {
+                    self.ports[*port as usize] |= IN;
+                    self.watching = true;
+                    continue;
+                },
+                Kind::Out(port) => {
+                    self.ports[*port as usize] |= OUT;
+                    self.watching = true;
+                    continue;
+                },
+            };
+            for addr in range {
+                self.memory[addr as usize] |= flags;
+            }
+            self.watching |= flags != EXEC;
+        }
+    }
+
+    //Whether an access has to be checked after the instruction
+    pub fn watches(&self, access: &Access) -> bool {
+        if !self.watching {
+            return false;
+        }
+        let (table, flag) = match access.kind {
+            AccessKind::Read => (&self.memory, READ),
+            AccessKind::Write => (&self.memory, WRITE),
+            AccessKind::In => (&self.ports, IN),
+            AccessKind::Out => (&self.ports, OUT),
+        };
+        table.get(access.addr as usize).is_some_and(|f| f & flag != 0)
+    }
+
+    //Id of the first execution breakpoint at pc whose condition holds
+    pub fn check_exec(&mut self, state: &State8080) -> Option<usize> {
+        if self.memory.get(state.pc as usize).is_none_or(|f| f & EXEC == 0) {
+            return None;
+        }
+        self.hit(state, |b| b.kind == Kind::Exec(state.pc))
+    }
+
+    //Id of the first watchpoint for the access whose condition holds
+    pub fn check_access(&mut self, state: &State8080, access: &Access) -> Option<usize> {
+        self.hit(state, |b| b.matches(access))
+    }
+
+    //Like check_exec and check_access, but hit counts are left alone
+    pub fn find_exec(&self, state: &State8080) -> Option<usize> {
+        if self.memory.get(state.pc as usize).is_none_or(|f| f & EXEC == 0) {
+            return None;
+        }
+        self.find(state, |b| b.kind == Kind::Exec(state.pc))
+    }
+
+    pub fn find_access(&self, state: &State8080, access: &Access) -> Option<usize> {
+        if !self.watches(access) {
+            return None;
+        }
+        self.find(state, |b| b.matches(access))
+    }
+
+    fn find<F: Fn(&Breakpoint) -> bool>(&self, state: &State8080, at: F) -> Option<usize> {
+        self.list.iter()
+            .filter(|b| b.enabled && at(b))
+            .find(|b| b.condition.as_ref().is_none_or(|c| c.eval(state, b.hits) != 0))
+            .map(|b| b.id)
+    }
+
+    fn hit<F: Fn(&Breakpoint) -> bool>(&mut self, state: &State8080, at: F) -> Option<usize> {
+        let mut hit = None;
+        for b in self.list.iter_mut().filter(|b| b.enabled && at(b)) {
+            b.hits += 1;
+            let stop = match &b.condition {
+                Some(condition) => condition.eval(state, b.hits) != 0,
+                None => true,
+            };
+            if stop && hit.is_none() {
+                hit = Some(b.id);
+            }
+        }
+        hit
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    A, B, C, D, E, H, L,
+    Bc, De, Hl, Sp, Pc, Psw,
+    //Memory at HL, like the M register of the instruction set
+    M,
+    S, Z, Ac, P, Cy,
+    Inte,
+    Hits,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or, And,
+    Eq, Ne, Lt, Le, Gt, Ge,
+    BitOr, BitAnd, Add, Sub,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(u32),
+    Operand(Operand),
+    //Memory byte at the address
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+//Expression over registers, flags and memory that decides whether a
+//breakpoint stops, e.g. `A == 0x20 && HL > 0x2400`. Registers and register
+//pairs are named as in the instruction set, flags as s, z, ac, p and cy,
+//[addr] reads memory and hits is the breakpoint's hit count. Numbers are
+//decimal or 0x prefixed hex. Operators, loosest first: || && == != < <=
+//> >= | & + - and the prefix !.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Condition, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let expr = parser.binary(0)?;
+        if parser.pos != tokens.len() {
+            return Err(format!("Unexpected '{}' in condition", tokens[parser.pos]));
+        }
+        Ok(Condition {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn eval(&self, state: &State8080, hits: u64) -> u32 {
+        eval(&self.expr, state, hits)
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn eval(expr: &Expr, state: &State8080, hits: u64) -> u32 {
+    match expr {
+        Expr::Number(n) => *n,
+        Expr::Operand(operand) => match operand {
+            Operand::A => state.a as u32,
+            Operand::B => state.b as u32,
+            Operand::C => state.c as u32,
+            Operand::D => state.d as u32,
+            Operand::E => state.e as u32,
+            Operand::H => state.h as u32,
+            Operand::L => state.l as u32,
+            Operand::Bc => state.bc() as u32,
+            Operand::De => state.de() as u32,
+            Operand::Hl => state.hl() as u32,
+            Operand::Sp => state.sp as u32,
+            Operand::Pc => state.pc as u32,
+            Operand::Psw => state.psw() as u32,
+            Operand::M => state.memory[state.hl() as usize] as u32,
+            Operand::S => state.cc.s as u32,
+            Operand::Z => state.cc.z as u32,
+            Operand::Ac => state.cc.ac as u32,
+            Operand::P => state.cc.p as u32,
+            Operand::Cy => state.cc.cy as u32,
+            Operand::Inte => state.int_enable as u32,
+            Operand::Hits => hits.min(u32::MAX as u64) as u32,
+        },
+        Expr::Memory(addr) => state.memory[(eval(addr, state, hits) & 0xffff) as usize] as u32,
+        Expr::Not(e) => (eval(e, state, hits) == 0) as u32,
+        Expr::Binary(op, l, r) => {
+            let l = eval(l, state, hits);
+            //Short circuit so conditions stay cheap
+            match op {
+                Op::Or if l != 0 => return 1,
+                Op::And if l == 0 => return 0,
+                _ => {},
+            }
+            let r = eval(r, state, hits);
+            match op {
+                Op::Or | Op::And => (r != 0) as u32,
+                Op::Eq => (l == r) as u32,
+                Op::Ne => (l != r) as u32,
+                Op::Lt => (l < r) as u32,
+                Op::Le => (l <= r) as u32,
+                Op::Gt => (l > r) as u32,
+                Op::Ge => (l >= r) as u32,
+                Op::BitOr => l | r,
+                Op::BitAnd => l & r,
+                Op::Add => l.wrapping_add(r),
+                Op::Sub => l.wrapping_sub(r),
+            }
+        },
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() {
+            let start = i;
+            while i < chars.len() && chars[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+            if ["||", "&&", "==", "!=", "<=", ">="].contains(&two.as_str()) {
+                tokens.push(two);
+                i += 2;
+            } else if "<>|&+-!()[]".contains(c) {
+                tokens.push(c.to_string());
+                i += 1;
+            } else {
+                return Err(format!("Unexpected '{}' in condition", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+//Binary operators by binding strength, loosest first
+const LEVELS: [&[(&str, Op)]; 5] = [
+    &[("||", Op::Or)],
+    &[("&&", Op::And)],
+    &[("==", Op::Eq), ("!=", Op::Ne), ("<", Op::Lt), ("<=", Op::Le), (">", Op::Gt), (">=", Op::Ge)],
+    &[("|", Op::BitOr), ("&", Op::BitAnd)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+];
+
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.pos)?;
+        self.pos += 1;
+        Some(token)
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            Some(t) => Err(format!("Expected '{}', got '{}' in condition", token, t)),
+            None => Err(format!("Expected '{}' at end of condition", token)),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut expr = self.binary(level + 1)?;
+        while let Some(&(_, op)) = self.tokens.get(self.pos)
+            .and_then(|t| LEVELS[level].iter().find(|(s, _)| s == t)) {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.next().ok_or("Condition ends unexpectedly")?.to_string();
+        match token.as_str() {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            "[" => {
+                let expr = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Expr::Memory(Box::new(expr)))
+            },
+            _ if token.starts_with(|c: char| c.is_ascii_digit()) => {
+                let number = match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => token.parse(),
+                };
+                number.map(Expr::Number).map_err(|_| format!("Invalid number '{}' in condition", token))
+            },
+            _ => {
+                let operand = match token.to_lowercase().as_str() {
+                    "a" => Operand::A,
+                    "b" => Operand::B,
+                    "c" => Operand::C,
+                    "d" => Operand::D,
+                    "e" => Operand::E,
+                    "h" => Operand::H,
+                    "l" => Operand::L,
+                    "bc" => Operand::Bc,
+                    "de" => Operand::De,
+                    "hl" => Operand::Hl,
+                    "sp" => Operand::Sp,
+                    "pc" => Operand::Pc,
+                    "psw" => Operand::Psw,
+                    "m" => Operand::M,
+                    "s" => Operand::S,
+                    "z" => Operand::Z,
+                    "ac" => Operand::Ac,
+                    "p" => Operand::P,
+                    "cy" => Operand::Cy,
+                    "inte" => Operand::Inte,
+                    "hits" => Operand::Hits,
+                    _ => return Err(format!("Unknown name '{}' in condition", token)),
+                };
+                Ok(Expr::Operand(operand))
+            },
+        }
+    }
+}
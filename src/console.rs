@@ -0,0 +1,189 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+//Character terminal as seen by the emulated machine
+pub trait Console {
+    fn write(&mut self, byte: u8);
+    //Blocks until a character is available, None once input is closed
+    fn read(&mut self) -> Option<u8>;
+    //Whether a character is waiting, closed input has none
+    fn status(&mut self) -> bool;
+    //Whether input is closed and read returns None, for machines that poll
+    //status and would otherwise wait forever
+    fn closed(&mut self) -> bool {
+        false
+    }
+}
+
+//Console on the host's stdin/stdout. Stdin is read on a separate thread so
+//status checks never block the emulation.
+pub struct HostConsole {
+    input: Receiver<u8>,
+    pending: Option<u8>,
+    closed: bool,
+    //Output written since the last flush
+    unflushed: bool,
+    //stty settings to restore when the terminal was put into raw mode
+    saved_tty: Option<String>,
+}
+
+impl HostConsole {
+    pub fn new() -> HostConsole {
+        HostConsole::with_input(io::stdin())
+    }
+
+    //Reads input from input instead of stdin, output still goes to stdout
+    pub fn with_input<R: Read + Send + 'static>(input: R) -> HostConsole {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::BufReader::new(input).bytes() {
+                match byte {
+                    Ok(byte) => if tx.send(byte).is_err() {
+                        break;
+                    },
+                    Err(_) => break,
+                }
+            }
+        });
+        HostConsole {
+            input: rx,
+            pending: None,
+            closed: false,
+            unflushed: false,
+            saved_tty: None,
+        }
+    }
+
+    //Passes every key straight to the machine without echo or line editing.
+    //Does nothing if stdin is not a terminal.
+    pub fn raw() -> HostConsole {
+        let mut console = HostConsole::new();
+        console.set_raw(true);
+        console
+    }
+
+    //Switches raw mode on or off, returns whether the terminal is in raw mode
+    pub fn set_raw(&mut self, raw: bool) -> bool {
+        let _ = io::stdout().flush();
+        if raw && self.saved_tty.is_none() {
+            let saved = stty(&["-g"]).filter(|_| stty(&["raw", "-echo"]).is_some());
+            self.saved_tty = saved.map(|s| s.trim().to_string());
+        } else if !raw {
+            if let Some(saved) = self.saved_tty.take() {
+                stty(&[saved.as_str()]);
+            }
+        }
+        self.saved_tty.is_some()
+    }
+
+    //Shows a prompt written without a newline before waiting for input
+    fn flush(&mut self) {
+        if self.unflushed {
+            let _ = io::stdout().flush();
+            self.unflushed = false;
+        }
+    }
+
+    //The next character without taking it, if one is available
+    pub fn peek(&mut self) -> Option<u8> {
+        if self.status() { self.pending } else { None }
+    }
+}
+
+impl Drop for HostConsole {
+    fn drop(&mut self) {
+        self.set_raw(false);
+    }
+}
+
+fn stty(args: &[&str]) -> Option<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+impl Default for HostConsole {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Console for HostConsole {
+    fn write(&mut self, byte: u8) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(&[byte]);
+        self.unflushed = byte != b'\n' && self.saved_tty.is_none();
+        if !self.unflushed {
+            let _ = stdout.flush();
+        }
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.flush();
+        if let Some(byte) = self.pending.take() {
+            return Some(byte);
+        }
+        match self.input.recv() {
+            Ok(byte) => Some(byte),
+            Err(_) => {
+                self.closed = true;
+                None
+            }
+        }
+    }
+
+    fn status(&mut self) -> bool {
+        if self.pending.is_some() {
+            return true;
+        }
+        if self.closed {
+            return false;
+        }
+        self.flush();
+        match self.input.try_recv() {
+            Ok(byte) => {
+                self.pending = Some(byte);
+                true
+            },
+            Err(TryRecvError::Empty) => false,
+            Err(TryRecvError::Disconnected) => {
+                self.closed = true;
+                false
+            },
+        }
+    }
+
+    fn closed(&mut self) -> bool {
+        !self.status() && self.closed
+    }
+}
+
+//Lets a machine and the debugger prompt share one console
+impl<C: Console> Console for Rc<RefCell<C>> {
+    fn write(&mut self, byte: u8) {
+        self.borrow_mut().write(byte)
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.borrow_mut().read()
+    }
+
+    fn status(&mut self) -> bool {
+        self.borrow_mut().status()
+    }
+
+    fn closed(&mut self) -> bool {
+        self.borrow_mut().closed()
+    }
+}
@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::console::Console;
+use crate::i8080cpu::{Bus, State8080};
+use crate::runner::{Runner, StopReason};
+use crate::savestate::{Device, Reader, Writer};
+
+//Transient programs are loaded here
+pub const TPA: u16 = 0x0100;
+//The BDOS entry the jump at 0x0005 leads to. Its address doubles as the top
+//of the TPA, which programs read from 0x0006 to set up their stack.
+pub const BDOS_ENTRY: u16 = 0xfe00;
+
+//Default file control blocks and the command tail in page zero
+const FCB1: u16 = 0x005c;
+const FCB2: u16 = 0x006c;
+const DEFAULT_DMA: u16 = 0x0080;
+
+const RECORD_SIZE: usize = 128;
+//Records per logical extent
+const EXTENT_RECORDS: usize = 128;
+
+//Minimal CP/M environment: page zero, a BDOS entry stub and the console and
+//file functions of the BDOS, serviced by the host whenever pc reaches
+//BDOS_ENTRY. Files live in a host directory that stands in for every drive.
+//Host files are visible under their uppercased name if it fits 8.3, files
+//created by the program get lowercase names.
+pub struct Cpm<C: Console> {
+    pub console: C,
+    pub dir: PathBuf,
+    dma: u16,
+    drive: u8,
+    files: HashMap<PathBuf, File>,
+    //Host files with a valid CP/M name sorted by name. Listed on first use
+    //and again after open, search first or a change to the directory, not
+    //on every record.
+    listing: Option<Vec<([u8; 11], PathBuf)>>,
+    //Remaining directory entries of search first
+    search: Vec<[u8; 11]>,
+}
+
+//DMA address and current drive. Open files are reopened from the host
+//directory on the next access and a running directory search is lost.
+impl<C: Console> Device for Cpm<C> {
+    fn tag(&self) -> [u8; 4] {
+        *b"CPM "
+    }
+
+    fn save(&self, out: &mut Writer) {
+        out.u16(self.dma);
+        out.u8(self.drive);
+    }
+
+    fn load(&mut self, data: &mut Reader) -> Result<(), String> {
+        self.dma = data.u16()?;
+        self.drive = data.u8()?;
+        self.files.clear();
+        self.listing = None;
+        self.search.clear();
+        Ok(())
+    }
+}
+
+impl<C: Console> Cpm<C> {
+    pub fn new(console: C, dir: PathBuf) -> Cpm<C> {
+        Cpm {
+            console,
+            dir,
+            dma: DEFAULT_DMA,
+            drive: 0,
+            files: HashMap::new(),
+            listing: None,
+            search: Vec::new(),
+        }
+    }
+
+    //Sets up page zero and the BDOS stub and loads program at the TPA.
+    //Returning from the program pops 0x0000, which is the exit address.
+    pub fn load(&self, state: &mut State8080, program: &[u8]) -> Result<(), String> {
+        let start = TPA as usize;
+        if start + program.len() > BDOS_ENTRY as usize {
+            return Err(format!("Program too large for the TPA: {} bytes", program.len()));
+        }
+        state.memory[start..start + program.len()].copy_from_slice(program);
+
+        state.memory[0x0000] = 0x76; //HLT, a jump to 0 is treated as exit anyway
+        state.memory[0x0005] = 0xc3; //JMP BDOS_ENTRY
+        state.memory[0x0006] = BDOS_ENTRY as u8;
+        state.memory[0x0007] = (BDOS_ENTRY >> 8) as u8;
+        state.memory[BDOS_ENTRY as usize] = 0xc9; //RET
+
+        state.pc = TPA;
+        state.sp = BDOS_ENTRY;
+        state.sp = state.sp.wrapping_sub(2);
+        state.memory[state.sp as usize] = 0x00;
+        state.memory[state.sp as usize + 1] = 0x00;
+        Ok(())
+    }
+
+    //Puts the command line arguments into the command tail at 0x0080 and
+    //parses the first two into the default FCBs, like the CCP does
+    pub fn set_command_tail(&self, state: &mut State8080, tail: &str) -> Result<(), String> {
+        let tail = tail.to_uppercase();
+        if tail.len() > 125 || !tail.is_ascii() {
+            return Err("Command tail must be at most 125 ASCII characters".to_string());
+        }
+        let tail = if tail.is_empty() { tail } else { format!(" {}", tail) };
+        let start = DEFAULT_DMA as usize;
+        state.memory[start] = tail.len() as u8;
+        state.memory[start + 1..start + 1 + tail.len()].copy_from_slice(tail.as_bytes());
+        state.memory[start + 1 + tail.len()] = 0x00;
+
+        let mut args = tail.split_whitespace();
+        for fcb in [FCB1, FCB2].iter() {
+            let (drive, name) = parse_fcb_name(args.next().unwrap_or(""));
+            let fcb = *fcb as usize;
+            state.memory[fcb] = drive;
+            state.memory[fcb + 1..fcb + 12].copy_from_slice(&name);
+            state.memory[fcb + 12..fcb + 16].fill(0);
+        }
+        state.memory[FCB1 as usize + 32] = 0;
+        Ok(())
+    }
+
+    //Runs until the runner stops, servicing BDOS calls on the way.
+    //Stopping at 0x0000 is left to the runner's conditions.
+    pub fn run<B: Bus + ?Sized>(&mut self, state: &mut State8080, bus: &mut B,
+                                runner: &mut Runner) -> StopReason {
+        loop {
+            if let Some(reason) = self.step(state, bus, runner) {
+                return reason;
+            }
+        }
+    }
+
+    //Executes one instruction, servicing the BDOS call first if pc is at
+    //the BDOS entry
+    pub fn step<B: Bus + ?Sized>(&mut self, state: &mut State8080, bus: &mut B,
+                                 runner: &mut Runner) -> Option<StopReason> {
+        if state.pc == BDOS_ENTRY {
+            self.bdos_call(state);
+        }
+        runner.step(state, bus)
+    }
+
+    //Services the BDOS function in C. Results are returned in A and HL with
+    //B = H and A = L, like the real BDOS does.
+    pub fn bdos_call(&mut self, state: &mut State8080) {
+        let result: u16 = match state.c {
+            0 => { //System reset
+                //Return straight to the exit address
+                state.memory[state.sp as usize] = 0x00;
+                state.memory[state.sp.wrapping_add(1) as usize] = 0x00;
+                0
+            },
+            1 => { //Console input
+                let byte = self.console.read().unwrap_or(0x1a);
+                self.console.write(byte);
+                byte as u16
+            },
+            2 => { //Console output
+                self.console.write(state.e);
+                0
+            },
+            6 => { //Direct console I/O
+                match state.e {
+                    0xff => if self.console.status() {
+                        self.console.read().unwrap_or(0x1a) as u16
+                    } else {
+                        0
+                    },
+                    0xfe => if self.console.status() { 0xff } else { 0 },
+                    byte => {
+                        self.console.write(byte);
+                        0
+                    },
+                }
+            },
+            9 => { //Print string
+                //At most all of memory, a string without '$' would loop forever
+                let de = state.de() as usize;
+                for i in 0..state.memory.len() {
+                    let byte = state.memory[(de + i) % state.memory.len()];
+                    if byte == b'$' {
+                        break;
+                    }
+                    self.console.write(byte);
+                }
+                0
+            },
+            11 => { //Console status
+                if self.console.status() { 0xff } else { 0 }
+            },
+            12 => 0x0022, //Version number: CP/M 2.2
+            13 => { //Reset disk system
+                self.dma = DEFAULT_DMA;
+                self.drive = 0;
+                self.listing = None;
+                0
+            },
+            14 => { //Select disk
+                self.drive = state.e & 0x0f;
+                0
+            },
+            15 => { //Open file
+                self.listing = None;
+                self.open(state)
+            },
+            16 => { //Close file
+                match self.host_path(&self.fcb_name(state)) {
+                    Some(path) => {
+                        self.files.remove(&path);
+                        0
+                    },
+                    None => 0xff,
+                }
+            },
+            17 => { //Search for first
+                let fcb = state.de() as usize;
+                let pattern = self.fcb_name(state);
+                let all = state.memory[fcb] == b'?';
+                self.listing = None;
+                self.search = self.entries().iter()
+                    .filter(|(name, _)| all || matches(&pattern, name))
+                    .map(|(name, _)| *name)
+                    .collect();
+                self.search.reverse();
+                self.search_next(state)
+            },
+            18 => self.search_next(state), //Search for next
+            19 => { //Delete file
+                let pattern = self.fcb_name(state);
+                let paths: Vec<PathBuf> = self.entries().iter()
+                    .filter(|(name, _)| matches(&pattern, name))
+                    .map(|(_, path)| path.clone())
+                    .collect();
+                let mut result = 0xff;
+                for path in paths {
+                    self.files.remove(&path);
+                    if fs::remove_file(&path).is_ok() {
+                        result = 0;
+                    }
+                }
+                self.listing = None;
+                result
+            },
+            20 => { //Read sequential
+                let record = fcb_record(state);
+                let result = self.read_record(state, record);
+                if result == 0 {
+                    set_fcb_record(state, record + 1);
+                }
+                result
+            },
+            21 => { //Write sequential
+                let record = fcb_record(state);
+                let result = self.write_record(state, record);
+                if result == 0 {
+                    set_fcb_record(state, record + 1);
+                }
+                result
+            },
+            22 => { //Make file
+                let name = self.fcb_name(state);
+                let path = self.host_path(&name).unwrap_or_else(|| self.dir.join(host_name(&name)));
+                self.files.remove(&path);
+                self.listing = None;
+                match OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&path) {
+                    Ok(file) => {
+                        self.files.insert(path, file);
+                        let fcb = state.de() as usize;
+                        for i in [12, 13, 14, 15, 32] {
+                            state.memory[(fcb + i) & 0xffff] = 0;
+                        }
+                        0
+                    },
+                    Err(_) => 0xff,
+                }
+            },
+            23 => { //Rename file
+                let fcb = state.de() as usize;
+                let old = self.fcb_name(state);
+                let mut new = [0; 11];
+                for (i, byte) in new.iter_mut().enumerate() {
+                    *byte = (state.memory[(fcb + 17 + i) & 0xffff] & 0x7f).to_ascii_uppercase();
+                }
+                match self.host_path(&old) {
+                    Some(path) if self.host_path(&new).is_none() => {
+                        self.files.remove(&path);
+                        self.listing = None;
+                        match fs::rename(&path, self.dir.join(host_name(&new))) {
+                            Ok(()) => 0,
+                            Err(_) => 0xff,
+                        }
+                    },
+                    _ => 0xff,
+                }
+            },
+            24 => 0xffff, //Return login vector
+            25 => self.drive as u16, //Return current disk
+            26 => { //Set DMA address
+                self.dma = state.de();
+                0
+            },
+            //Write protect disk, get read only vector, set file attributes,
+            //get/set user code: everything is read/write user 0
+            28..=30 | 32 => 0,
+            33 | 34 | 40 => { //Read random, write random (with zero fill)
+                let record = match random_record(state) {
+                    Some(record) => record,
+                    None => return self.bdos_return(state, 6),
+                };
+                set_fcb_record(state, record);
+                if state.c == 33 {
+                    self.read_record(state, record)
+                } else {
+                    self.write_record(state, record)
+                }
+            },
+            35 => { //Compute file size
+                let records = match self.host_path(&self.fcb_name(state)) {
+                    Some(path) => file_records(&path),
+                    None => return self.bdos_return(state, 0xff),
+                };
+                set_random_record(state, records);
+                0
+            },
+            36 => { //Set random record
+                let record = fcb_record(state);
+                set_random_record(state, record);
+                0
+            },
+            function => {
+                eprintln!("\nUnsupported BDOS function {} at PC: {:04x}", function, state.pc);
+                0
+            },
+        };
+        self.bdos_return(state, result);
+    }
+
+    fn bdos_return(&self, state: &mut State8080, result: u16) {
+        state.set_hl(result);
+        state.a = state.l;
+        state.b = state.h;
+    }
+
+    //Name and type of the FCB at DE with attribute bits stripped
+    fn fcb_name(&self, state: &State8080) -> [u8; 11] {
+        let fcb = state.de() as usize;
+        let mut name = [0; 11];
+        for (i, byte) in name.iter_mut().enumerate() {
+            *byte = (state.memory[(fcb + 1 + i) & 0xffff] & 0x7f).to_ascii_uppercase();
+        }
+        name
+    }
+
+    //Host files that have a valid CP/M name, sorted by name
+    fn entries(&mut self) -> &[([u8; 11], PathBuf)] {
+        let dir = &self.dir;
+        self.listing.get_or_insert_with(|| list_dir(dir))
+    }
+
+    //First host file matching the possibly ambiguous name
+    fn host_path(&mut self, name: &[u8; 11]) -> Option<PathBuf> {
+        let entries = self.entries();
+        if !name.contains(&b'?') {
+            let i = entries.partition_point(|(entry, _)| entry < name);
+            return entries.get(i).filter(|(entry, _)| entry == name).map(|(_, path)| path.clone());
+        }
+        entries.iter()
+            .find(|(entry, _)| matches(name, entry))
+            .map(|(_, path)| path.clone())
+    }
+
+    fn open(&mut self, state: &mut State8080) -> u16 {
+        let path = match self.host_path(&self.fcb_name(state)) {
+            Some(path) => path,
+            None => return 0xff,
+        };
+        if self.file(&path).is_err() {
+            return 0xff;
+        }
+        let fcb = state.de() as usize;
+        let extent = state.memory[(fcb + 12) & 0xffff] as usize & 0x1f;
+        let records = file_records(&path) as usize;
+        let rc = records.saturating_sub(extent * EXTENT_RECORDS).min(EXTENT_RECORDS);
+        state.memory[(fcb + 14) & 0xffff] = 0;
+        state.memory[(fcb + 15) & 0xffff] = rc as u8;
+        state.memory[(fcb + 32) & 0xffff] = 0;
+        0
+    }
+
+    //Writes the next search result as the first directory entry of the
+    //DMA buffer
+    fn search_next(&mut self, state: &mut State8080) -> u16 {
+        let name = match self.search.pop() {
+            Some(name) => name,
+            None => return 0xff,
+        };
+        let records = self.host_path(&name).map_or(0, |path| file_records(&path)) as usize;
+        let extents = records.saturating_sub(1) / EXTENT_RECORDS;
+        let mut entry = [0u8; 32];
+        entry[1..12].copy_from_slice(&name);
+        entry[12] = (extents & 0x1f) as u8;
+        entry[14] = (extents >> 5) as u8;
+        entry[15] = (records - extents * EXTENT_RECORDS).min(EXTENT_RECORDS) as u8;
+        for (i, byte) in entry.iter().enumerate() {
+            state.memory[(self.dma as usize + i) & 0xffff] = *byte;
+        }
+        0
+    }
+
+    //Open host file for path, kept until the FCB is closed
+    fn file(&mut self, path: &Path) -> io::Result<&mut File> {
+        if !self.files.contains_key(path) {
+            let file = match OpenOptions::new().read(true).write(true).open(path) {
+                Ok(file) => file,
+                Err(_) => File::open(path)?,
+            };
+            self.files.insert(path.to_path_buf(), file);
+        }
+        Ok(self.files.get_mut(path).unwrap())
+    }
+
+    //Reads record into the DMA buffer, a partial last record is padded with
+    //^Z. Returns 0, or 1 when reading past the end of the file.
+    fn read_record(&mut self, state: &mut State8080, record: u32) -> u16 {
+        let path = match self.host_path(&self.fcb_name(state)) {
+            Some(path) => path,
+            None => return 1,
+        };
+        let mut buf = [0x1a; RECORD_SIZE];
+        let read = self.file(&path).and_then(|file| {
+            file.seek(SeekFrom::Start(record as u64 * RECORD_SIZE as u64))?;
+            let mut read = 0;
+            while read < RECORD_SIZE {
+                match file.read(&mut buf[read..])? {
+                    0 => break,
+                    n => read += n,
+                }
+            }
+            Ok(read)
+        });
+        match read {
+            Ok(read) if read > 0 => {
+                for (i, byte) in buf.iter().enumerate() {
+                    state.memory[(self.dma as usize + i) & 0xffff] = *byte;
+                }
+                0
+            },
+            _ => 1,
+        }
+    }
+
+    //Writes the DMA buffer to record. Returns 0, or 2 when the file can't
+    //be written.
+    fn write_record(&mut self, state: &mut State8080, record: u32) -> u16 {
+        let path = match self.host_path(&self.fcb_name(state)) {
+            Some(path) => path,
+            None => return 2,
+        };
+        let mut buf = [0; RECORD_SIZE];
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = state.memory[(self.dma as usize + i) & 0xffff];
+        }
+        let written = self.file(&path).and_then(|file| {
+            file.seek(SeekFrom::Start(record as u64 * RECORD_SIZE as u64))?;
+            file.write_all(&buf)
+        });
+        match written {
+            Ok(()) => 0,
+            Err(_) => 2,
+        }
+    }
+}
+
+//Sequential position of the FCB at DE from S2, extent and current record
+fn fcb_record(state: &State8080) -> u32 {
+    let fcb = state.de() as usize;
+    let extent = state.memory[(fcb + 12) & 0xffff] as u32 & 0x1f;
+    let s2 = state.memory[(fcb + 14) & 0xffff] as u32 & 0x3f;
+    let cr = state.memory[(fcb + 32) & 0xffff] as u32 & 0x7f;
+    (s2 << 12) | (extent << 7) | cr
+}
+
+fn set_fcb_record(state: &mut State8080, record: u32) {
+    let fcb = state.de() as usize;
+    state.memory[(fcb + 12) & 0xffff] = ((record >> 7) & 0x1f) as u8;
+    state.memory[(fcb + 14) & 0xffff] = ((record >> 12) & 0x3f) as u8;
+    state.memory[(fcb + 32) & 0xffff] = (record & 0x7f) as u8;
+}
+
+//Record in R0-R2 of the FCB at DE, None if out of range
+fn random_record(state: &State8080) -> Option<u32> {
+    let fcb = state.de() as usize;
+    let r0 = state.memory[(fcb + 33) & 0xffff] as u32;
+    let r1 = state.memory[(fcb + 34) & 0xffff] as u32;
+    let r2 = state.memory[(fcb + 35) & 0xffff];
+    if r2 != 0 {
+        return None;
+    }
+    Some(r0 | (r1 << 8))
+}
+
+fn set_random_record(state: &mut State8080, record: u32) {
+    let fcb = state.de() as usize;
+    state.memory[(fcb + 33) & 0xffff] = record as u8;
+    state.memory[(fcb + 34) & 0xffff] = (record >> 8) as u8;
+    state.memory[(fcb + 35) & 0xffff] = (record >> 16) as u8;
+}
+
+//Host files in dir that have a valid CP/M name, sorted by name
+fn list_dir(dir: &Path) -> Vec<([u8; 11], PathBuf)> {
+    let mut entries: Vec<([u8; 11], PathBuf)> = match fs::read_dir(dir) {
+        Ok(dir) => dir.filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !entry.file_type().ok()?.is_file() {
+                return None;
+            }
+            let name = cpm_name(entry.file_name().to_str()?)?;
+            Some((name, entry.path()))
+        }).collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+    entries
+}
+
+//Size of the host file in records, a partial last record counts
+fn file_records(path: &Path) -> u32 {
+    let size = fs::metadata(path).map_or(0, |m| m.len());
+    size.div_ceil(RECORD_SIZE as u64) as u32
+}
+
+//'?' in pattern matches any character
+fn matches(pattern: &[u8; 11], name: &[u8; 11]) -> bool {
+    pattern.iter().zip(name.iter()).all(|(p, n)| *p == b'?' || p == n)
+}
+
+//Name and type padded with spaces, None if the host name doesn't fit 8.3
+fn cpm_name(host: &str) -> Option<[u8; 11]> {
+    let (name, ext) = match host.rfind('.') {
+        Some(0) => return None,
+        Some(dot) => (&host[..dot], &host[dot + 1..]),
+        None => (host, ""),
+    };
+    let valid = |s: &str, len: usize| {
+        !s.is_empty() && s.len() <= len
+            && s.bytes().all(|b| b.is_ascii_graphic() && !b"<>.,;:=?*[]".contains(&b))
+    };
+    if !valid(name, 8) || !(ext.is_empty() || valid(ext, 3)) {
+        return None;
+    }
+    let mut fcb = [b' '; 11];
+    fcb[..name.len()].copy_from_slice(name.to_ascii_uppercase().as_bytes());
+    fcb[8..8 + ext.len()].copy_from_slice(ext.to_ascii_uppercase().as_bytes());
+    Some(fcb)
+}
+
+//Lowercase host name for a new file
+fn host_name(name: &[u8; 11]) -> String {
+    let part = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim_end().to_ascii_lowercase();
+    let (name, ext) = (part(&name[..8]), part(&name[8..]));
+    if ext.is_empty() { name } else { format!("{}.{}", name, ext) }
+}
+
+//Parses a command line argument like B:NAME.TYP into the drive byte and the
+//padded name, '*' fills the rest of the name or type with '?'
+fn parse_fcb_name(arg: &str) -> (u8, [u8; 11]) {
+    let bytes = arg.as_bytes();
+    let (drive, arg) = if bytes.len() >= 2 && bytes[1] == b':' {
+        (bytes[0].wrapping_sub(b'A').wrapping_add(1) & 0x1f, &arg[2..])
+    } else {
+        (0, arg)
+    };
+    let (name, ext) = arg.split_once('.').unwrap_or((arg, ""));
+    let mut fcb = [b' '; 11];
+    let (name_field, ext_field) = fcb.split_at_mut(8);
+    for (field, part) in [(name_field, name), (ext_field, ext)].iter_mut() {
+        for (i, byte) in part.bytes().take(field.len()).enumerate() {
+            if byte == b'*' {
+                field[i..].fill(b'?');
+                break;
+            }
+            field[i] = byte;
+        }
+    }
+    (drive, fcb)
+}
@@ -0,0 +1,276 @@
+use crate::altair::ESCAPE;
+use crate::console::Console;
+use crate::disk::{DiskImage, Geometry};
+use crate::i8080cpu::{NullBus, State8080};
+use crate::runner::{Runner, StopReason};
+use crate::savestate::{Device, Reader, Writer};
+
+pub const DRIVES: usize = 4;
+
+//CCP and BDOS together, as stored on the system tracks
+const SYSTEM_SIZE: usize = 0x1600;
+//The CCP starts with JMP to this offset from its base
+const CCP_ENTRY: u16 = 0x035c;
+const BDOS_OFFSET: u16 = 0x0806;
+const BIOS_OFFSET: u16 = 0x1600;
+const BIOS_ENTRIES: u16 = 17;
+//Jump table and disk tables
+const BIOS_SIZE: usize = 0x200;
+
+//Sector skew of the IBM 3740 format
+const XLT: [u8; 26] = [
+    1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21,
+    2, 8, 14, 20, 26, 6, 12, 18, 24, 4, 10, 16, 22,
+];
+
+//Disk parameter block for IBM 3740 8" disks: 26 sectors per track, 1 KiB
+//blocks, 243 blocks, 64 directory entries and 2 system tracks
+const DPB: [u8; 15] = [
+    26, 0, //SPT
+    3, //BSH
+    7, //BLM
+    0, //EXM
+    242, 0, //DSM
+    63, 0, //DRM
+    0xc0, 0x00, //AL0, AL1
+    16, 0, //CKS
+    2, 0, //OFF
+];
+const CSV_SIZE: u16 = 16;
+const ALV_SIZE: u16 = 31;
+
+//Unmodified CP/M 2.2 booted from the system tracks of drive A. The BIOS is
+//implemented by the host: its jump table only holds RETs, and whenever pc
+//reaches one of them the function is serviced before the RET executes.
+pub struct Cpm22<C: Console> {
+    pub console: C,
+    pub drives: Vec<Option<DiskImage>>,
+    //Address the CCP and BDOS on the disk were built for
+    pub ccp: u16,
+    system: Vec<u8>,
+    disk: usize,
+    track: u16,
+    sector: u16,
+    dma: u16,
+    pub quit: bool,
+}
+
+impl<C: Console> Cpm22<C> {
+    //Reads the CCP and BDOS off drive A. The CCP base is taken from the jump
+    //the CCP starts with, unless ccp is given.
+    pub fn new(console: C, drive_a: DiskImage, ccp: Option<u16>) -> Result<Cpm22<C>, String> {
+        if drive_a.geometry != Geometry::IBM_3740 {
+            return Err("Drive A must be an IBM 3740 image".to_string());
+        }
+        //Track 0 sector 1 holds the cold start loader, the system follows
+        let system = drive_a.data[128..128 + SYSTEM_SIZE].to_vec();
+        let ccp = match ccp {
+            Some(ccp) => ccp,
+            None => {
+                if system[0] != 0xc3 {
+                    return Err("No CP/M system on drive A, use --cpm-base".to_string());
+                }
+                u16::from_le_bytes([system[1], system[2]]).wrapping_sub(CCP_ENTRY)
+            },
+        };
+        if ccp as usize + BIOS_OFFSET as usize + BIOS_SIZE > 0x10000 {
+            return Err(format!("CCP base {:04x} leaves no room for the BIOS", ccp));
+        }
+
+        let mut drives: Vec<Option<DiskImage>> = (0..DRIVES).map(|_| None).collect();
+        drives[0] = Some(drive_a);
+        Ok(Cpm22 {
+            console,
+            drives,
+            ccp,
+            system,
+            disk: 0,
+            track: 0,
+            sector: 1,
+            dma: 0x0080,
+            quit: false,
+        })
+    }
+
+    pub fn insert(&mut self, drive: usize, image: DiskImage) -> Result<(), String> {
+        if drive >= DRIVES {
+            return Err(format!("Only drives A: to {}: are supported", (b'A' + DRIVES as u8 - 1) as char));
+        }
+        if image.geometry != Geometry::IBM_3740 {
+            return Err("Only IBM 3740 images are supported".to_string());
+        }
+        self.drives[drive] = Some(image);
+        Ok(())
+    }
+
+    fn bios(&self) -> u16 {
+        self.ccp + BIOS_OFFSET
+    }
+
+    //Disk parameter headers follow the jump table, then the shared
+    //directory buffer, DPB and skew table, then per drive CSV and ALV
+    fn dph(&self, drive: u16) -> u16 {
+        self.bios() + BIOS_ENTRIES * 3 + drive * 16
+    }
+
+    //Installs the BIOS tables and loads the system, ready for the cold boot
+    pub fn boot(&mut self, state: &mut State8080) {
+        let bios = self.bios();
+        for entry in 0..BIOS_ENTRIES {
+            state.memory[(bios + entry * 3) as usize] = 0xc9; //RET
+        }
+
+        let dirbuf = self.dph(DRIVES as u16);
+        let dpb = dirbuf + 128;
+        let xlt = dpb + DPB.len() as u16;
+        let mut scratch = xlt + XLT.len() as u16;
+        state.memory[dpb as usize..xlt as usize].copy_from_slice(&DPB);
+        state.memory[xlt as usize..(xlt as usize + XLT.len())].copy_from_slice(&XLT);
+        for drive in 0..DRIVES as u16 {
+            let csv = scratch;
+            let alv = csv + CSV_SIZE;
+            scratch = alv + ALV_SIZE;
+            let words = [xlt, 0, 0, 0, dirbuf, dpb, csv, alv];
+            let dph = self.dph(drive) as usize;
+            for (i, word) in words.iter().enumerate() {
+                state.memory[dph + i * 2..dph + i * 2 + 2].copy_from_slice(&word.to_le_bytes());
+            }
+        }
+
+        state.memory[0x0003] = 0x00; //IOBYTE
+        state.memory[0x0004] = 0x00; //drive A, user 0
+        self.warm_boot(state);
+    }
+
+    //Reloads CCP and BDOS, sets up page zero and enters the CCP
+    fn warm_boot(&mut self, state: &mut State8080) {
+        let ccp = self.ccp as usize;
+        state.memory[ccp..ccp + SYSTEM_SIZE].copy_from_slice(&self.system);
+
+        let wboot = self.bios() + 3;
+        let bdos = self.ccp + BDOS_OFFSET;
+        state.memory[0x0000] = 0xc3;
+        state.memory[0x0001..0x0003].copy_from_slice(&wboot.to_le_bytes());
+        state.memory[0x0005] = 0xc3;
+        state.memory[0x0006..0x0008].copy_from_slice(&bdos.to_le_bytes());
+
+        self.dma = 0x0080;
+        state.sp = 0x0080;
+        state.c = state.memory[0x0004];
+        state.pc = self.ccp;
+    }
+
+    pub fn run(&mut self, state: &mut State8080, runner: &mut Runner) -> Option<StopReason> {
+        let bios = self.bios();
+        let end = bios + BIOS_ENTRIES * 3;
+        while !self.quit {
+            if state.pc >= bios && state.pc < end && (state.pc - bios).is_multiple_of(3) {
+                self.bios_call(state, (state.pc - bios) / 3);
+            }
+            if let Some(reason) = runner.step(state, &mut NullBus) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    fn read_console(&mut self) -> u8 {
+        match self.console.read() {
+            Some(ESCAPE) | None => {
+                self.quit = true;
+                0x1a
+            },
+            Some(b'\n') => b'\r',
+            Some(byte) => byte & 0x7f,
+        }
+    }
+
+    //Services BIOS function number function. Except for the boots, the RET
+    //at the entry returns to the caller afterwards.
+    fn bios_call(&mut self, state: &mut State8080, function: u16) {
+        match function {
+            0 => self.boot(state), //BOOT
+            1 => self.warm_boot(state), //WBOOT
+            2 => state.a = if self.console.status() { 0xff } else { 0x00 }, //CONST
+            3 => state.a = self.read_console(), //CONIN
+            4 => self.console.write(state.c & 0x7f), //CONOUT
+            5 | 6 => {}, //LIST, PUNCH
+            7 => state.a = 0x1a, //READER
+            8 => self.track = 0, //HOME
+            9 => { //SELDSK
+                let drive = state.c as usize;
+                if drive < DRIVES && self.drives[drive].is_some() {
+                    self.disk = drive;
+                    state.set_hl(self.dph(drive as u16));
+                } else {
+                    state.set_hl(0);
+                }
+            },
+            10 => self.track = state.bc(), //SETTRK
+            11 => self.sector = state.bc(), //SETSEC
+            12 => self.dma = state.bc(), //SETDMA
+            13 | 14 => state.a = self.transfer(state, function == 14), //READ, WRITE
+            15 => state.a = 0xff, //LISTST
+            16 => { //SECTRAN
+                let sector = if state.de() == 0 {
+                    state.bc() + 1
+                } else {
+                    state.memory[state.de().wrapping_add(state.bc()) as usize] as u16
+                };
+                state.set_hl(sector);
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    //Moves the selected sector between disk and DMA buffer, returns the
+    //BIOS status: 0 ok, 1 error
+    fn transfer(&mut self, state: &mut State8080, write: bool) -> u8 {
+        let (track, sector) = (self.track as usize, self.sector as usize);
+        let dma = self.dma as usize;
+        let image = match self.drives[self.disk].as_mut() {
+            Some(image) => image,
+            None => return 1,
+        };
+        if sector == 0 || dma + 128 > state.memory.len() {
+            return 1;
+        }
+        if write {
+            match image.write_sector(track, sector - 1, &state.memory[dma..dma + 128]) {
+                Ok(()) => 0,
+                Err(_) => 1,
+            }
+        } else if image.read_sector(track, sector - 1, &mut state.memory[dma..dma + 128]) {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+//BIOS disk selection and DMA address. The system and the disk images stay
+//with the machine.
+impl<C: Console> Device for Cpm22<C> {
+    fn tag(&self) -> [u8; 4] {
+        *b"CP22"
+    }
+
+    fn save(&self, out: &mut Writer) {
+        out.u16(self.ccp);
+        out.u8(self.disk as u8);
+        out.u16(self.track);
+        out.u16(self.sector);
+        out.u16(self.dma);
+    }
+
+    fn load(&mut self, data: &mut Reader) -> Result<(), String> {
+        if data.u16()? != self.ccp {
+            return Err("Save state is for a different CCP base".to_string());
+        }
+        self.disk = data.u8()? as usize % DRIVES;
+        self.track = data.u16()?;
+        self.sector = data.u16()?;
+        self.dma = data.u16()?;
+        Ok(())
+    }
+}
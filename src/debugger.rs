@@ -0,0 +1,675 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::breakpoints::{AccessKind, Condition, Kind};
+use crate::console::{Console, HostConsole};
+use crate::cpm::Cpm;
+use crate::disassembler;
+use crate::history::History;
+use crate::i8080cpu::{NullBus, State8080};
+use crate::invaders::SpaceInvaders;
+use crate::runner::{Runner, StopReason};
+use crate::savestate::{self, SaveState};
+
+//Instructions between checks for a key press that interrupts a run
+const POLL_INTERVAL: u64 = 0x4000;
+const ESC: u8 = 0x1b;
+const CTRL_C: u8 = 0x03;
+const HISTORY_OFF: &str = "History is off, start it with record <n>";
+
+//A machine the debugger can drive one instruction at a time
+pub trait Target {
+    fn state(&mut self) -> &mut State8080;
+    //Executes one instruction including the machine's traps and interrupts
+    fn step(&mut self, runner: &mut Runner) -> Option<StopReason>;
+    //Whether the program reads the console itself, key presses then don't
+    //interrupt a run
+    fn uses_console(&self) -> bool {
+        false
+    }
+    //Writes the CPU, memory and device state to a save state file
+    fn save_state(&mut self, path: &Path) -> io::Result<()> {
+        savestate::save(path, self.state(), None)
+    }
+    fn load_state(&mut self, path: &Path) -> Result<(), String> {
+        savestate::load(path, self.state(), None)
+    }
+}
+
+//Plain memory image without any devices
+pub struct Bare {
+    pub state: State8080,
+}
+
+impl Target for Bare {
+    fn state(&mut self) -> &mut State8080 {
+        &mut self.state
+    }
+
+    fn step(&mut self, runner: &mut Runner) -> Option<StopReason> {
+        runner.step(&mut self.state, &mut NullBus)
+    }
+}
+
+pub struct CpmProgram<C: Console> {
+    pub state: State8080,
+    pub env: Cpm<C>,
+}
+
+impl<C: Console> Target for CpmProgram<C> {
+    fn state(&mut self) -> &mut State8080 {
+        &mut self.state
+    }
+
+    fn step(&mut self, runner: &mut Runner) -> Option<StopReason> {
+        self.env.step(&mut self.state, &mut NullBus, runner)
+    }
+
+    fn uses_console(&self) -> bool {
+        true
+    }
+
+    fn save_state(&mut self, path: &Path) -> io::Result<()> {
+        savestate::save(path, &self.state, Some(&self.env))
+    }
+
+    fn load_state(&mut self, path: &Path) -> Result<(), String> {
+        savestate::load(path, &mut self.state, Some(&mut self.env))
+    }
+}
+
+impl Target for SpaceInvaders {
+    fn state(&mut self) -> &mut State8080 {
+        &mut self.state
+    }
+
+    fn step(&mut self, runner: &mut Runner) -> Option<StopReason> {
+        SpaceInvaders::step(self, runner)
+    }
+
+    fn save_state(&mut self, path: &Path) -> io::Result<()> {
+        savestate::save(path, &self.state, Some(self))
+    }
+
+    fn load_state(&mut self, path: &Path) -> Result<(), String> {
+        let saved = SaveState::read(path)?;
+        saved.load_device(Some(self))?;
+        saved.load_cpu(&mut self.state);
+        Ok(())
+    }
+}
+
+//Interactive monitor on the host terminal. Addresses and values are hex,
+//counts decimal.
+pub struct Debugger<T: Target> {
+    pub target: T,
+    pub runner: Runner,
+    console: Rc<RefCell<HostConsole>>,
+    history: Vec<String>,
+}
+
+impl<T: Target> Debugger<T> {
+    //console is the one the target's program uses, if any
+    pub fn new(target: T, runner: Runner, console: Rc<RefCell<HostConsole>>) -> Debugger<T> {
+        Debugger {
+            target,
+            runner,
+            console,
+            history: Vec::new(),
+        }
+    }
+
+    //Runs the command loop until quit or end of input
+    pub fn repl(&mut self) {
+        self.show();
+        while let Some(line) = self.read_line("(i8080) ") {
+            let line = line.trim().to_string();
+            let line = if line.is_empty() {
+                //Repeat the last command, like stepping again
+                match self.history.last() {
+                    Some(last) => last.clone(),
+                    None => continue,
+                }
+            } else if let Some(n) = line.strip_prefix('!') {
+                match n.parse::<usize>().ok().and_then(|n| self.history.get(n.wrapping_sub(1))) {
+                    Some(command) => {
+                        println!("{}", command);
+                        command.clone()
+                    },
+                    None => {
+                        println!("No command {} in history", n);
+                        continue;
+                    },
+                }
+            } else {
+                line
+            };
+            if self.history.last() != Some(&line) {
+                self.history.push(line.clone());
+            }
+            match self.command(&line) {
+                Ok(true) => {},
+                Ok(false) => break,
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+
+    //Executes one command line, returns false on quit
+    pub fn command(&mut self, line: &str) -> Result<bool, String> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match args.split_first() {
+            Some((command, args)) => (*command, args),
+            None => return Ok(true),
+        };
+        match command {
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(n) => n.parse::<u64>().map_err(|_| format!("Invalid count '{}'", n))?,
+                    None => 1,
+                };
+                let mut stop = None;
+                for _ in 0..count {
+                    stop = self.target.step(&mut self.runner).map(describe);
+                    if stop.is_some() {
+                        break;
+                    }
+                }
+                self.stopped(stop);
+            },
+            "n" | "next" => {
+                let state = self.target.state();
+                let (_, length) = disassemble(&state.memory, state.pc);
+                if is_call(state.memory[state.pc as usize]) {
+                    //Run until the call returns to the same stack level
+                    let ret = state.pc.wrapping_add(length as u16);
+                    let sp = state.sp;
+                    let stop = self.resume(|state| state.pc == ret && state.sp >= sp);
+                    self.stopped(stop);
+                } else {
+                    let stop = self.target.step(&mut self.runner).map(describe);
+                    self.stopped(stop);
+                }
+            },
+            "c" | "continue" => {
+                let stop = self.resume(|_| false);
+                self.stopped(stop);
+            },
+            "u" | "until" => {
+                let addr = parse_hex(args.first().ok_or("Usage: until <addr>")?)?;
+                let stop = self.resume(|state| state.pc == addr);
+                self.stopped(stop);
+            },
+            "rs" | "rstep" => {
+                let count = match args.first() {
+                    Some(n) => n.parse::<u64>().map_err(|_| format!("Invalid count '{}'", n))?,
+                    None => 1,
+                };
+                self.runner.history().ok_or(HISTORY_OFF)?;
+                let state = self.target.state();
+                for _ in 0..count {
+                    if self.runner.reverse_step(state).is_none() {
+                        println!("Reached the start of the history");
+                        break;
+                    }
+                }
+                self.show();
+            },
+            "rc" | "rcontinue" => {
+                self.runner.history().ok_or(HISTORY_OFF)?;
+                match self.runner.reverse_continue(self.target.state()) {
+                    Some(reason) => println!("Stopped: {}", describe(reason)),
+                    None => println!("Reached the start of the history"),
+                }
+                self.show();
+            },
+            "lastwrite" => {
+                let addr = parse_hex(args.first().ok_or("Usage: lastwrite <addr>")?)?;
+                let history = self.runner.history().ok_or(HISTORY_OFF)?;
+                match history.last_write(addr) {
+                    //Only the writes are recorded, not the instruction bytes
+                    Some((record, write)) => {
+                        let (text, _) = disassemble(&self.target.state().memory, record.before.pc);
+                        println!("Instruction {} at {:04x} wrote {:02x} (was {:02x}), {:04x} now holds {}",
+                                 record.instruction, record.before.pc, write.value, write.old,
+                                 record.before.pc, text);
+                    },
+                    None => println!("No write to {:04x} in the last {} instructions", addr, history.len()),
+                }
+            },
+            "record" => match args {
+                [] => match self.runner.history() {
+                    Some(history) => println!("Recording up to {} instructions, {} recorded",
+                                              history.capacity(), history.len()),
+                    None => println!("History is off"),
+                },
+                ["off"] => self.runner.set_history(None),
+                [n] => {
+                    let capacity = n.parse::<usize>().map_err(|_| format!("Invalid count '{}'", n))?;
+                    match self.runner.history_mut() {
+                        Some(history) => history.set_capacity(capacity),
+                        None => self.runner.set_history(Some(History::new(capacity))),
+                    }
+                },
+                _ => return Err("Usage: record [<n>|off]".to_string()),
+            },
+            "save" => match args {
+                [path] => {
+                    self.target.save_state(Path::new(path))
+                        .map_err(|e| format!("Error writing save state '{}': {}", path, e))?;
+                    println!("Saved state to {}", path);
+                },
+                _ => return Err("Usage: save <file>".to_string()),
+            },
+            "load" => match args {
+                [path] => {
+                    self.target.load_state(Path::new(path))?;
+                    //The recorded instructions led to the replaced state
+                    if let Some(history) = self.runner.history_mut() {
+                        *history = History::new(history.capacity());
+                    }
+                    self.show();
+                },
+                _ => return Err("Usage: load <file>".to_string()),
+            },
+            "r" | "regs" => println!("{}", registers(self.target.state())),
+            "set" => {
+                if args.len() != 2 {
+                    return Err("Usage: set <reg|flag> <value>".to_string());
+                }
+                set_register(self.target.state(), &args[0].to_lowercase(), parse_hex(args[1])?)?;
+                println!("{}", registers(self.target.state()));
+            },
+            "m" | "mem" => {
+                let addr = match args.first() {
+                    Some(addr) => parse_hex(addr)?,
+                    None => self.target.state().pc,
+                };
+                let length = match args.get(1) {
+                    Some(length) => parse_hex(length)?,
+                    None => 0x40,
+                };
+                dump(&self.target.state().memory, addr, length);
+            },
+            "w" | "deposit" => {
+                if args.len() < 2 {
+                    return Err("Usage: deposit <addr> <byte>...".to_string());
+                }
+                let addr = parse_hex(args[0])?;
+                let state = self.target.state();
+                for (i, byte) in args[1..].iter().enumerate() {
+                    let value = parse_hex(byte)?;
+                    if value > 0xff {
+                        return Err(format!("Not a byte: '{}'", byte));
+                    }
+                    state.memory[addr.wrapping_add(i as u16) as usize] = value as u8;
+                }
+                //Undoing past the deposit would leave its bytes in place
+                if let Some(history) = self.runner.history_mut() {
+                    if !history.is_empty() {
+                        *history = History::new(history.capacity());
+                        println!("History cleared");
+                    }
+                }
+            },
+            "l" | "dis" => {
+                let state = self.target.state();
+                let mut addr = match args.first() {
+                    Some(addr) => parse_hex(addr)?,
+                    None => state.pc,
+                };
+                let count = match args.get(1) {
+                    Some(n) => n.parse::<u32>().map_err(|_| format!("Invalid count '{}'", n))?,
+                    None => 10,
+                };
+                for _ in 0..count {
+                    let (text, length) = disassemble(&state.memory, addr);
+                    println!("{}{:04x} {}", if addr == state.pc { "=>" } else { "  " }, addr, text);
+                    addr = addr.wrapping_add(length as u16);
+                }
+            },
+            "b" | "break" => {
+                let (args, condition) = split_condition(args)?;
+                if args.len() != 1 {
+                    return Err("Usage: break <addr> [if <condition>]".to_string());
+                }
+                let id = self.runner.breakpoints_mut().add(Kind::Exec(parse_hex(args[0])?), condition);
+                println!("Breakpoint {} at {:04x}", id, parse_hex(args[0])?);
+            },
+            "watch" => {
+                let (args, condition) = split_condition(args)?;
+                let kind = match args {
+                    ["in", port] | ["out", port] => {
+                        let port = parse_hex(port)?;
+                        if port > 0xff {
+                            return Err(format!("Port out of range: {:x}", port));
+                        }
+                        if args[0] == "in" { Kind::In(port as u8) } else { Kind::Out(port as u8) }
+                    },
+                    [range] | ["w", range] => Kind::Write(parse_range(range)?),
+                    ["r", range] => Kind::Read(parse_range(range)?),
+                    ["rw", range] => Kind::Access(parse_range(range)?),
+                    _ => return Err("Usage: watch [r|w|rw] <addr>[-<end>] [if <condition>]\n       \
+                                     watch in|out <port> [if <condition>]".to_string()),
+                };
+                let id = self.runner.breakpoints_mut().add(kind, condition);
+                println!("Watchpoint {}", id);
+            },
+            "i" | "info" => {
+                if self.runner.breakpoints().is_empty() {
+                    println!("No breakpoints");
+                }
+                for b in self.runner.breakpoints().iter() {
+                    let kind = match &b.kind {
+                        Kind::Exec(addr) => format!("break {:04x}", addr),
+                        Kind::Read(range) => format!("watch r {:04x}-{:04x}", range.start(), range.end()),
+                        Kind::Write(range) => format!("watch w {:04x}-{:04x}", range.start(), range.end()),
+                        Kind::Access(range) => format!("watch rw {:04x}-{:04x}", range.start(), range.end()),
+                        Kind::In(port) => format!("watch in {:02x}", port),
+                        Kind::Out(port) => format!("watch out {:02x}", port),
+                    };
+                    let condition = b.condition.as_ref().map(|c| format!(" if {}", c)).unwrap_or_default();
+                    println!("{:3} {}{}{} hits: {}", b.id, kind, condition,
+                             if b.enabled { "" } else { " (disabled)" }, b.hits);
+                }
+            },
+            "delete" => match args {
+                ["all"] => self.runner.breakpoints_mut().clear(),
+                [id] => {
+                    if !self.runner.breakpoints_mut().remove(parse_id(id)?) {
+                        return Err(format!("No breakpoint {}", id));
+                    }
+                },
+                _ => return Err("Usage: delete <id>|all".to_string()),
+            },
+            "enable" | "disable" => {
+                let id = parse_id(args.first().ok_or("Usage: enable|disable <id>")?)?;
+                let b = self.runner.breakpoints_mut().get_mut(id).ok_or(format!("No breakpoint {}", id))?;
+                b.enabled = command == "enable";
+                self.runner.breakpoints_mut().update();
+            },
+            "cond" => {
+                let id = parse_id(args.first().ok_or("Usage: cond <id> [<condition>]")?)?;
+                let condition = match args.len() {
+                    1 => None,
+                    _ => Some(Condition::parse(&args[1..].join(" "))?),
+                };
+                let b = self.runner.breakpoints_mut().get_mut(id).ok_or(format!("No breakpoint {}", id))?;
+                b.condition = condition;
+            },
+            "history" => {
+                for (i, line) in self.history.iter().enumerate() {
+                    println!("{:4} {}", i + 1, line);
+                }
+            },
+            "h" | "help" | "?" => help(),
+            "q" | "quit" => return Ok(false),
+            _ => return Err(format!("Unknown command '{}', try help", command)),
+        }
+        Ok(true)
+    }
+
+    //Steps until stop returns true, the runner stops or Ctrl-C is pressed.
+    //The first instruction always executes, so runs can leave a stop address.
+    fn resume<F: Fn(&State8080) -> bool>(&mut self, stop: F) -> Option<String> {
+        //The terminal is raw while running so Ctrl-C arrives as a character,
+        //unless the program itself uses the console
+        let poll = !self.target.uses_console();
+        let raw = poll && self.console.borrow_mut().set_raw(true);
+        let mut executed = 0u64;
+        let reason = loop {
+            if let Some(reason) = self.target.step(&mut self.runner) {
+                break Some(describe(reason));
+            }
+            if stop(self.target.state()) {
+                break None;
+            }
+            executed += 1;
+            if poll && executed.is_multiple_of(POLL_INTERVAL) {
+                let mut console = self.console.borrow_mut();
+                if console.peek() == Some(CTRL_C) {
+                    console.read();
+                    break Some("Interrupted".to_string());
+                }
+            }
+        };
+        if raw {
+            self.console.borrow_mut().set_raw(false);
+        }
+        reason
+    }
+
+    fn stopped(&mut self, reason: Option<String>) {
+        if let Some(reason) = reason {
+            println!("Stopped: {}", reason);
+        }
+        self.show();
+    }
+
+    //Registers and the next instruction
+    fn show(&mut self) {
+        let state = self.target.state();
+        let (text, _) = disassemble(&state.memory, state.pc);
+        println!("{}", registers(state));
+        println!("=>{:04x} {}", state.pc, text);
+    }
+
+    //Reads a line with up/down arrow history on a terminal, plain lines
+    //otherwise. None when input is closed.
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        print!("{}", prompt);
+        let _ = io::stdout().flush();
+        let mut console = self.console.borrow_mut();
+        if !console.set_raw(true) {
+            let mut line = Vec::new();
+            loop {
+                match console.read()? {
+                    b'\n' => return Some(String::from_utf8_lossy(&line).into_owned()),
+                    byte => line.push(byte),
+                }
+            }
+        }
+
+        let mut line = String::new();
+        let mut recalled = self.history.len();
+        let result = loop {
+            let byte = match console.read() {
+                Some(byte) => byte,
+                None => break None,
+            };
+            match byte {
+                b'\r' | b'\n' => break Some(line),
+                0x04 if line.is_empty() => break None, //Ctrl-D
+                CTRL_C => { //Drops the line
+                    line.clear();
+                    print!("^C\r\n{}", prompt);
+                },
+                0x08 | 0x7f if line.pop().is_some() => print!("\x08 \x08"),
+                ESC => {
+                    if console.read() != Some(b'[') {
+                        continue;
+                    }
+                    let entry = match console.read() {
+                        Some(b'A') if recalled > 0 => recalled - 1,
+                        Some(b'B') if recalled < self.history.len() => recalled + 1,
+                        _ => continue,
+                    };
+                    recalled = entry;
+                    line = self.history.get(entry).cloned().unwrap_or_default();
+                    print!("\r\x1b[K{}{}", prompt, line);
+                },
+                byte if (0x20..0x7f).contains(&byte) => {
+                    line.push(byte as char);
+                    print!("{}", byte as char);
+                },
+                _ => {},
+            }
+            let _ = io::stdout().flush();
+        };
+        console.set_raw(false);
+        println!();
+        result
+    }
+}
+
+fn help() {
+    println!("step|s [n]               execute n instructions (default 1)");
+    println!("next|n                   step over CALL and RST");
+    println!("continue|c               run until stopped, Ctrl-C interrupts");
+    println!("until|u <addr>           run until pc reaches addr");
+    println!("rstep|rs [n]             undo n instructions (default 1)");
+    println!("rcontinue|rc             run backwards to a breakpoint or write watchpoint");
+    println!("lastwrite <addr>         show the last instruction that wrote addr,");
+    println!("                         disassembled from memory as it is now");
+    println!("record [<n>|off]         keep the last n instructions for the reverse");
+    println!("                         commands, off until started");
+    println!("save <file>              write a save state of the machine");
+    println!("load <file>              restore a save state, clears the history");
+    println!("regs|r                   show registers and flags");
+    println!("set <reg> <value>        set a, b, c, d, e, h, l, bc, de, hl, sp, pc, psw,");
+    println!("                         f, inte or the flags s, z, ac, p, cy");
+    println!("mem|m [addr] [len]       examine memory (default pc, 0x40 bytes)");
+    println!("deposit|w <addr> <b>...  write bytes to memory, clears the history");
+    println!("dis|l [addr] [n]         disassemble n instructions (default pc, 10)");
+    println!("break|b <addr> [if <c>]  stop when pc reaches addr");
+    println!("watch [r|w|rw] <addr>[-<end>] [if <c>]");
+    println!("                         stop on data reads and/or writes (default w)");
+    println!("watch in|out <port> [if <c>]");
+    println!("                         stop on port input or output");
+    println!("info|i                   list breakpoints and watchpoints with hit counts");
+    println!("delete <id>|all          remove breakpoints");
+    println!("enable|disable <id>      switch a breakpoint on or off");
+    println!("cond <id> [<c>]          set or clear the condition of a breakpoint");
+    println!("history                  list commands, !<n> repeats one");
+    println!("quit|q                   leave the debugger");
+    println!("Addresses and values are hex, counts decimal. An empty line repeats the");
+    println!("last command.");
+    println!("Conditions are expressions like 'A == 0x20 && HL > 0x2400' over registers,");
+    println!("flags (s z ac p cy), memory ([addr] or M) and the hit count (hits). Numbers in");
+    println!("conditions are decimal unless 0x prefixed.");
+}
+
+fn describe(reason: StopReason) -> String {
+    match reason {
+        StopReason::Breakpoint(id) => format!("Breakpoint {}", id),
+        StopReason::Watchpoint { id, access } => {
+            let what = match access.kind {
+                AccessKind::Read => format!("read {:04x}", access.addr),
+                AccessKind::Write => format!("write {:04x}", access.addr),
+                AccessKind::In => format!("in {:02x}", access.addr),
+                AccessKind::Out => format!("out {:02x}", access.addr),
+            };
+            format!("Watchpoint {}: {} = {:02x}", id, what, access.value)
+        },
+        reason => format!("{:?}", reason),
+    }
+}
+
+//Splits off a trailing `if <condition>`
+fn split_condition<'a>(args: &'a [&'a str]) -> Result<(&'a [&'a str], Option<Condition>), String> {
+    match args.iter().position(|a| *a == "if") {
+        Some(i) => Ok((&args[..i], Some(Condition::parse(&args[i + 1..].join(" "))?))),
+        None => Ok((args, None)),
+    }
+}
+
+fn parse_id(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("Invalid breakpoint id '{}'", s))
+}
+
+//<addr> or <start>-<end>, both hex
+fn parse_range(s: &str) -> Result<std::ops::RangeInclusive<u16>, String> {
+    match s.split_once('-') {
+        Some((start, end)) => {
+            let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+            if start > end {
+                return Err(format!("Empty range '{}'", s));
+            }
+            Ok(start..=end)
+        },
+        None => {
+            let addr = parse_hex(s)?;
+            Ok(addr..=addr)
+        },
+    }
+}
+
+//Disassembles the instruction at addr, reading past the end of memory wraps
+pub fn disassemble(memory: &[u8], addr: u16) -> (String, usize) {
+    let bytes: Vec<u8> = (0..3).map(|i| memory[addr.wrapping_add(i) as usize]).collect();
+    disassembler::format_8080_op(&bytes, 0)
+}
+
+//CALL, conditional calls and RST, including the undocumented CALL aliases
+pub fn is_call(opcode: u8) -> bool {
+    opcode & 0xc7 == 0xc4 || opcode & 0xc7 == 0xc7 || opcode & 0xcf == 0xcd
+}
+
+pub fn registers(state: &State8080) -> String {
+    let flag = |set: bool, name: &'static str| if set { name.to_string() } else { name.to_lowercase() };
+    format!("PC: {:04x} SP: {:04x} A: {:02x} BC: {:04x} DE: {:04x} HL: {:04x} F: {:02x} [{} {} {} {} {}] INTE: {}{}",
+            state.pc, state.sp, state.a, state.bc(), state.de(), state.hl(), state.cc.to_byte(),
+            flag(state.cc.s, "S"), flag(state.cc.z, "Z"), flag(state.cc.ac != 0, "AC"),
+            flag(state.cc.p != 0, "P"), flag(state.cc.cy != 0, "CY"), state.int_enable as u8,
+            if state.halted { " HALTED" } else { "" })
+}
+
+//Sets a register pair, register, flag or the interrupt enable by name
+pub fn set_register(state: &mut State8080, name: &str, value: u16) -> Result<(), String> {
+    let byte = || if value > 0xff {
+        Err(format!("Value out of range for register {}: {:04x}", name, value))
+    } else {
+        Ok(value as u8)
+    };
+    let bit = || if value > 1 {
+        Err(format!("Value out of range for {}: {:04x}", name, value))
+    } else {
+        Ok(value as u8)
+    };
+    match name {
+        "a" => state.a = byte()?,
+        "b" => state.b = byte()?,
+        "c" => state.c = byte()?,
+        "d" => state.d = byte()?,
+        "e" => state.e = byte()?,
+        "h" => state.h = byte()?,
+        "l" => state.l = byte()?,
+        "f" => state.cc.set_byte(byte()?),
+        "bc" => state.set_bc(value),
+        "de" => state.set_de(value),
+        "hl" => state.set_hl(value),
+        "psw" => state.set_psw(value),
+        "sp" => state.sp = value,
+        "pc" => state.pc = value,
+        "s" => state.cc.s = bit()? != 0,
+        "z" => state.cc.z = bit()? != 0,
+        "ac" => state.cc.ac = bit()?,
+        "p" => state.cc.p = bit()?,
+        "cy" => state.cc.cy = bit()?,
+        "inte" => state.int_enable = bit()? != 0,
+        _ => return Err(format!("Unknown register '{}'", name)),
+    }
+    Ok(())
+}
+
+//Hex digits with an optional 0x prefix
+pub fn parse_hex(s: &str) -> Result<u16, String> {
+    let digits = s.strip_prefix("0x").unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid hex number '{}'", s))
+}
+
+fn dump(memory: &[u8], addr: u16, length: u16) {
+    let mut line = String::new();
+    for i in 0..length {
+        let at = addr.wrapping_add(i);
+        if i % 16 == 0 {
+            line = format!("{:04x} ", at);
+        }
+        line += &format!("{:02x} ", memory[at as usize]);
+        if i % 16 == 15 || i + 1 == length {
+            println!("{}", line);
+        }
+    }
+}
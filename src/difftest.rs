@@ -0,0 +1,303 @@
+use crate::debugger;
+use crate::history::Registers;
+use crate::i8080cpu::{self, Bus, State8080};
+
+//A CPU implementation that can run in lock-step with another one
+pub trait Core {
+    //Executes one instruction like i8080cpu::emulate_8080_op, returns cycles
+    fn step(&mut self, state: &mut State8080, bus: &mut dyn Bus) -> u32;
+    //Drops anything the core remembers about memory, before a new start state
+    fn reset(&mut self) {}
+}
+
+//i8080cpu::emulate_8080_op, the implementation the others are checked against
+pub struct Reference;
+
+impl Core for Reference {
+    fn step(&mut self, state: &mut State8080, bus: &mut dyn Bus) -> u32 {
+        i8080cpu::emulate_8080_op(state, bus)
+    }
+}
+
+//The reference with a planted bug: ADC B leaves the carry in out of the
+//auxiliary carry. Checks that difftest finds and minimizes a divergence.
+pub struct FaultyAdc;
+
+impl Core for FaultyAdc {
+    fn step(&mut self, state: &mut State8080, bus: &mut dyn Bus) -> u32 {
+        let (opcode, a, b) = (state.memory[state.pc as usize], state.a, state.b);
+        let cycles = i8080cpu::emulate_8080_op(state, bus);
+        if opcode == 0x88 {
+            state.cc.ac = ((a & 0xf) + (b & 0xf) > 0xf) as u8;
+        }
+        cycles
+    }
+}
+
+pub const CORES: &[&str] = &["reference", "faulty-adc"];
+
+pub fn core(name: &str) -> Option<Box<dyn Core>> {
+    match name {
+        "reference" => Some(Box::new(Reference)),
+        "faulty-adc" => Some(Box::new(FaultyAdc)),
+        _ => None,
+    }
+}
+
+//Port reads return the same pseudo random values to both cores, writes are
+//collected so they can be compared
+struct DiffBus {
+    seed: u64,
+    reads: u64,
+    writes: Vec<(u8, u8)>,
+}
+
+impl DiffBus {
+    fn new(seed: u64) -> DiffBus {
+        DiffBus { seed, reads: 0, writes: Vec::new() }
+    }
+}
+
+impl Bus for DiffBus {
+    fn input(&mut self, port: u8) -> u8 {
+        self.reads += 1;
+        Rng::new(self.seed ^ self.reads.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ port as u64).next_u64() as u8
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.writes.push((port, value));
+    }
+}
+
+//xorshift64*, good enough for test programs
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x2545_f491_4f6c_dd1d) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+//Random memory and registers, a random instruction stream from pc
+pub fn random_state(rng: &mut Rng) -> State8080 {
+    let mut state = State8080::new();
+    for chunk in state.memory.chunks_mut(8) {
+        chunk.copy_from_slice(&rng.next_u64().to_le_bytes());
+    }
+    let r = rng.next_u64().to_le_bytes();
+    state.a = r[0];
+    state.b = r[1];
+    state.c = r[2];
+    state.d = r[3];
+    state.e = r[4];
+    state.h = r[5];
+    state.l = r[6];
+    state.cc.set_byte(r[7]);
+    let r = rng.next_u64();
+    state.sp = r as u16;
+    state.pc = (r >> 16) as u16;
+    state.int_enable = r & (1 << 32) != 0;
+    state
+}
+
+//The first instruction after which the cores disagreed
+pub struct Divergence {
+    //Number of the instruction, from 1
+    pub instruction: u64,
+    //State before it, as both cores had it
+    pub before: Box<State8080>,
+    pub differences: Vec<String>,
+    pub a: Box<State8080>,
+    pub b: Box<State8080>,
+}
+
+pub struct Harness {
+    pub a: Box<dyn Core>,
+    pub b: Box<dyn Core>,
+    pub names: (String, String),
+    //Seed of the port input values
+    pub seed: u64,
+}
+
+impl Harness {
+    pub fn new(a: &str, b: &str, seed: u64) -> Result<Harness, String> {
+        let unknown = |name: &str| format!("Unknown core '{}', available: {}", name, CORES.join(", "));
+        Ok(Harness {
+            a: core(a).ok_or_else(|| unknown(a))?,
+            b: core(b).ok_or_else(|| unknown(b))?,
+            names: (a.to_string(), b.to_string()),
+            seed,
+        })
+    }
+
+    //Runs both cores from start for up to steps instructions, comparing
+    //registers, cycles, memory and port writes after each. Stops early when
+    //both halted.
+    pub fn run(&mut self, start: &State8080, steps: u64) -> Option<Divergence> {
+        self.a.reset();
+        self.b.reset();
+        let mut a = Box::new(start.clone());
+        let mut b = Box::new(start.clone());
+        let mut bus_a = DiffBus::new(self.seed);
+        let mut bus_b = DiffBus::new(self.seed);
+        for instruction in 1..=steps {
+            if a.halted && b.halted {
+                break;
+            }
+            let cycles_a = self.a.step(&mut a, &mut bus_a);
+            let cycles_b = self.b.step(&mut b, &mut bus_b);
+            let mut differences = compare(&a, &b);
+            if cycles_a != cycles_b {
+                differences.push(format!("returned cycles: {} != {}", cycles_a, cycles_b));
+            }
+            if bus_a.writes != bus_b.writes {
+                differences.push(format!("port writes: {:02x?} != {:02x?}", bus_a.writes, bus_b.writes));
+            }
+            if !differences.is_empty() {
+                return Some(Divergence {
+                    instruction,
+                    before: self.state_at(start, instruction - 1),
+                    differences,
+                    a,
+                    b,
+                });
+            }
+            bus_a.writes.clear();
+            bus_b.writes.clear();
+        }
+        None
+    }
+
+    //State of core a after n instructions from start
+    fn state_at(&mut self, start: &State8080, n: u64) -> Box<State8080> {
+        self.a.reset();
+        let mut state = Box::new(start.clone());
+        let mut bus = DiffBus::new(self.seed);
+        for _ in 0..n {
+            self.a.step(&mut state, &mut bus);
+        }
+        state
+    }
+
+    //Smallest start state and instruction count found that still diverge:
+    //starts as late before the divergence as possible, then clears memory
+    //and registers the divergence does not depend on
+    pub fn minimize(&mut self, start: &State8080, divergence: &Divergence) -> (Box<State8080>, u64) {
+        let k = divergence.instruction;
+        //Start j instructions before the divergence, doubling j until the
+        //cores still disagree. Port inputs restart with the start state.
+        let mut best = (Box::new(start.clone()), k);
+        let mut back = 1;
+        while back < k {
+            let candidate = self.state_at(start, k - back);
+            if self.run(&candidate, back).is_some() {
+                best = (candidate, back);
+                break;
+            }
+            back *= 2;
+        }
+        let (mut state, steps) = best;
+
+        for size in [0x1000, 0x100, 0x10, 1].iter() {
+            if *size == 1 && steps > 64 {
+                break;
+            }
+            for chunk in (0..state.memory.len()).step_by(*size) {
+                let range = chunk..chunk + size;
+                if state.memory[range.clone()].iter().all(|b| *b == 0) {
+                    continue;
+                }
+                let mut candidate = state.clone();
+                candidate.memory[range].fill(0);
+                if self.run(&candidate, steps).is_some() {
+                    state = candidate;
+                }
+            }
+        }
+
+        let clears: [fn(&mut State8080); 8] = [
+            |s| s.a = 0, |s| s.b = 0, |s| s.c = 0, |s| s.d = 0,
+            |s| s.e = 0, |s| s.h = 0, |s| s.l = 0, |s| s.cc.set_byte(0),
+        ];
+        for clear in clears.iter() {
+            let mut candidate = state.clone();
+            clear(&mut candidate);
+            if self.run(&candidate, steps).is_some() {
+                state = candidate;
+            }
+        }
+        (state, steps)
+    }
+}
+
+fn compare(a: &State8080, b: &State8080) -> Vec<String> {
+    let mut differences = Vec::new();
+    let (ra, rb) = (Registers::save(a), Registers::save(b));
+    if ra != rb {
+        let pairs = [
+            ("a", ra.a as u64, rb.a as u64), ("b", ra.b as u64, rb.b as u64),
+            ("c", ra.c as u64, rb.c as u64), ("d", ra.d as u64, rb.d as u64),
+            ("e", ra.e as u64, rb.e as u64), ("h", ra.h as u64, rb.h as u64),
+            ("l", ra.l as u64, rb.l as u64), ("sp", ra.sp as u64, rb.sp as u64),
+            ("pc", ra.pc as u64, rb.pc as u64),
+            ("f", ra.cc.to_byte() as u64, rb.cc.to_byte() as u64),
+            ("inte", ra.int_enable as u64, rb.int_enable as u64),
+            ("halted", ra.halted as u64, rb.halted as u64),
+            ("cycles", ra.cycles, rb.cycles),
+        ];
+        for (name, x, y) in pairs.iter() {
+            if x != y {
+                differences.push(format!("{}: {:x} != {:x}", name, x, y));
+            }
+        }
+    }
+    if a.memory[..] != b.memory[..] {
+        let addrs: Vec<usize> = (0..a.memory.len()).filter(|i| a.memory[*i] != b.memory[*i]).collect();
+        for addr in addrs.iter().take(8) {
+            differences.push(format!("[{:04x}]: {:02x} != {:02x}", addr, a.memory[*addr], b.memory[*addr]));
+        }
+        if addrs.len() > 8 {
+            differences.push(format!("{} more memory differences", addrs.len() - 8));
+        }
+    }
+    differences
+}
+
+//Prints the diverging instruction and both resulting states
+pub fn report(divergence: &Divergence, names: &(String, String)) {
+    let before = &divergence.before;
+    let (text, _) = debugger::disassemble(&before.memory, before.pc);
+    println!("Divergence at instruction {}: {:04x} {}", divergence.instruction, before.pc, text);
+    println!("before:     {}", debugger::registers(before));
+    println!("{:<11} {}", format!("{}:", names.0), debugger::registers(&divergence.a));
+    println!("{:<11} {}", format!("{}:", names.1), debugger::registers(&divergence.b));
+    for difference in &divergence.differences {
+        println!("  {}", difference);
+    }
+}
+
+//Prints a start state as registers and its non-zero memory
+pub fn print_state(state: &State8080) {
+    println!("  {}", debugger::registers(state));
+    let mut addr = 0;
+    while addr < state.memory.len() {
+        if state.memory[addr] == 0 {
+            addr += 1;
+            continue;
+        }
+        let end = (addr..state.memory.len().min(addr + 16))
+            .take_while(|i| state.memory[*i] != 0)
+            .last()
+            .unwrap_or(addr) + 1;
+        let bytes: Vec<String> = state.memory[addr..end].iter().map(|b| format!("{:02x}", b)).collect();
+        println!("  {:04x}: {}", addr, bytes.join(" "));
+        addr = end;
+    }
+}
@@ -1,314 +1,321 @@
-pub fn disassemble_8080_op(buffer: &Vec<u8>, pc: usize) -> usize {
-    let mut opbytes:usize = 1;
+//Prints the instruction at pc and returns its length
+pub fn disassemble_8080_op(buffer: &[u8], pc: usize) -> usize {
+    let (text, opbytes) = format_8080_op(buffer, pc);
+    println!("{:04x} {}", pc, text);
+    opbytes
+}
 
-    //Print current pc
-    print!("{:04x} ", pc);
+//Instruction at pc as text and its length. Bytes past the end of buffer
+//read as 0, so an instruction cut off at the end still gets its full length.
+pub fn format_8080_op(buffer: &[u8], pc: usize) -> (String, usize) {
+    let byte = |offset: usize| buffer.get(pc + offset).copied().unwrap_or(0);
+    let mut opbytes:usize = 1;
 
-    match buffer[pc] {
-        0x00 => println!("NOP"),
-        0x01 => {println!("LXI    B,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes = 3},
-        0x02 => println!("STAX   B"),
-        0x03 => println!("INX    B"),
-        0x04 => println!("INR    B"),
-        0x05 => println!("DCR    B"),
-        0x06 => {println!("MVI    B,#${:02x}", buffer[pc + 1]); opbytes = 2},
-        0x07 => println!("RLC"),
-        0x08 => println!("NOP"),
-        0x09 => println!("DAD    B"),
-        0x0a => println!("LDAX   B"),
-        0x0b => println!("DCX    B"),
-        0x0c => println!("INR    C"),
-        0x0d => println!("DCR    C"),
-        0x0e => {println!("MVI    C,#${:02x}", buffer[pc + 1]); opbytes = 2},
-        0x0f => println!("RRC"),
+    let text = match byte(0) {
+        0x00 => "NOP".to_string(),
+        0x01 => {opbytes = 3;
+            format!("LXI    B,#${:02x}{:02x}", byte(2), byte(1))},
+        0x02 => "STAX   B".to_string(),
+        0x03 => "INX    B".to_string(),
+        0x04 => "INR    B".to_string(),
+        0x05 => "DCR    B".to_string(),
+        0x06 => {opbytes = 2; format!("MVI    B,#${:02x}", byte(1))},
+        0x07 => "RLC".to_string(),
+        0x08 => "NOP".to_string(),
+        0x09 => "DAD    B".to_string(),
+        0x0a => "LDAX   B".to_string(),
+        0x0b => "DCX    B".to_string(),
+        0x0c => "INR    C".to_string(),
+        0x0d => "DCR    C".to_string(),
+        0x0e => {opbytes = 2; format!("MVI    C,#${:02x}", byte(1))},
+        0x0f => "RRC".to_string(),
 
-        0x10 => println!("NOP"),
-        0x11 => {println!("LXI    D,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes = 3},
-        0x12 => println!("STAX   D"),
-        0x13 => println!("INX    D"),
-        0x14 => println!("INR    D"),
-        0x15 => println!("DCR    D"),
-        0x16 => {println!("MVI    D,#${:02x}", buffer[pc + 1]); opbytes = 2},
-        0x17 => println!("RAL"),
-        0x18 => println!("NOP"),
-        0x19 => println!("DAD    D"),
-        0x1a => println!("LDAX   D"),
-        0x1b => println!("DCX    D"),
-        0x1c => println!("INR    E"),
-        0x1d => println!("DCR    E"),
-        0x1e => {println!("MVI    E,#${:02x}", buffer[pc + 1]); opbytes = 2},
-        0x1f => println!("RAR"),
+        0x10 => "NOP".to_string(),
+        0x11 => {opbytes = 3;
+            format!("LXI    D,#${:02x}{:02x}", byte(2), byte(1))},
+        0x12 => "STAX   D".to_string(),
+        0x13 => "INX    D".to_string(),
+        0x14 => "INR    D".to_string(),
+        0x15 => "DCR    D".to_string(),
+        0x16 => {opbytes = 2; format!("MVI    D,#${:02x}", byte(1))},
+        0x17 => "RAL".to_string(),
+        0x18 => "NOP".to_string(),
+        0x19 => "DAD    D".to_string(),
+        0x1a => "LDAX   D".to_string(),
+        0x1b => "DCX    D".to_string(),
+        0x1c => "INR    E".to_string(),
+        0x1d => "DCR    E".to_string(),
+        0x1e => {opbytes = 2; format!("MVI    E,#${:02x}", byte(1))},
+        0x1f => "RAR".to_string(),
 
-        0x20 => println!("NOP"),
-        0x21 => {println!("LXI    H,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes=3},
-        0x22 => {println!("SHLD   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes=3},
-        0x23 => println!("INX    H"),
-        0x24 => println!("INR    H"),
-        0x25 => println!("DCR    H"),
-        0x26 => {println!("MVI    H,#${:02x}", buffer[pc + 1]); opbytes=2},
-        0x27 => println!("DAA"),
-        0x28 => println!("NOP"),
-        0x29 => println!("DAD    H"),
-        0x2a => {println!("LHLD   ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes=3},
-        0x2b => println!("DCX    H"),
-        0x2c => println!("INR    L"),
-        0x2d => println!("DCR    L"),
-        0x2e => {println!("MVI    L,#${:02x}", buffer[pc + 1]); opbytes = 2},
-        0x2f => println!("CMA"),
+        0x20 => "NOP".to_string(),
+        0x21 => {opbytes = 3;
+            format!("LXI    H,#${:02x}{:02x}", byte(2), byte(1))},
+        0x22 => {opbytes = 3;
+            format!("SHLD   ${:02x}{:02x}", byte(2), byte(1))},
+        0x23 => "INX    H".to_string(),
+        0x24 => "INR    H".to_string(),
+        0x25 => "DCR    H".to_string(),
+        0x26 => {opbytes = 2; format!("MVI    H,#${:02x}", byte(1))},
+        0x27 => "DAA".to_string(),
+        0x28 => "NOP".to_string(),
+        0x29 => "DAD    H".to_string(),
+        0x2a => {opbytes = 3;
+            format!("LHLD   ${:02x}{:02x}", byte(2), byte(1))},
+        0x2b => "DCX    H".to_string(),
+        0x2c => "INR    L".to_string(),
+        0x2d => "DCR    L".to_string(),
+        0x2e => {opbytes = 2; format!("MVI    L,#${:02x}", byte(1))},
+        0x2f => "CMA".to_string(),
 
-        0x30 => println!("NOP"),
-        0x31 => {println!("LXI    SP,#${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes=3},
-        0x32 => {println!("STA    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes=3},
-        0x33 => println!("INX    SP"),
-        0x34 => println!("INR    M"),
-        0x35 => println!("DCR    M"),
-        0x36 => {println!("MVI    M,#${:02x}", buffer[pc + 1]); opbytes=2},
-        0x37 => println!("STC"),
-        0x38 => println!("NOP"),
-        0x39 => println!("DAD    SP"),
-        0x3a => {println!("LDA    ${:02x}{:02x}", buffer[pc + 2], buffer[pc + 1]);
-            opbytes=3},
-        0x3b => println!("DCX    SP"),
-        0x3c => println!("INR    A"),
-        0x3d => println!("DCR    A"),
-        0x3e => {println!("MVI    A,#${:02x}", buffer[pc + 1]); opbytes = 2},
-        0x3f => println!("CMC"),
+        0x30 => "NOP".to_string(),
+        0x31 => {opbytes = 3;
+            format!("LXI    SP,#${:02x}{:02x}", byte(2), byte(1))},
+        0x32 => {opbytes = 3;
+            format!("STA    ${:02x}{:02x}", byte(2), byte(1))},
+        0x33 => "INX    SP".to_string(),
+        0x34 => "INR    M".to_string(),
+        0x35 => "DCR    M".to_string(),
+        0x36 => {opbytes = 2; format!("MVI    M,#${:02x}", byte(1))},
+        0x37 => "STC".to_string(),
+        0x38 => "NOP".to_string(),
+        0x39 => "DAD    SP".to_string(),
+        0x3a => {opbytes = 3;
+            format!("LDA    ${:02x}{:02x}", byte(2), byte(1))},
+        0x3b => "DCX    SP".to_string(),
+        0x3c => "INR    A".to_string(),
+        0x3d => "DCR    A".to_string(),
+        0x3e => {opbytes = 2; format!("MVI    A,#${:02x}", byte(1))},
+        0x3f => "CMC".to_string(),
 
-        0x40 => println!("MOV    B,B"),
-        0x41 => println!("MOV    B,C"),
-        0x42 => println!("MOV    B,D"),
-        0x43 => println!("MOV    B,E"),
-        0x44 => println!("MOV    B,H"),
-        0x45 => println!("MOV    B,L"),
-        0x46 => println!("MOV    B,M"),
-        0x47 => println!("MOV    B,A"),
-        0x48 => println!("MOV    C,B"),
-        0x49 => println!("MOV    C,C"),
-        0x4a => println!("MOV    C,D"),
-        0x4b => println!("MOV    C,E"),
-        0x4c => println!("MOV    C,H"),
-        0x4d => println!("MOV    C,L"),
-        0x4e => println!("MOV    C,M"),
-        0x4f => println!("MOV    C,A"),
+        0x40 => "MOV    B,B".to_string(),
+        0x41 => "MOV    B,C".to_string(),
+        0x42 => "MOV    B,D".to_string(),
+        0x43 => "MOV    B,E".to_string(),
+        0x44 => "MOV    B,H".to_string(),
+        0x45 => "MOV    B,L".to_string(),
+        0x46 => "MOV    B,M".to_string(),
+        0x47 => "MOV    B,A".to_string(),
+        0x48 => "MOV    C,B".to_string(),
+        0x49 => "MOV    C,C".to_string(),
+        0x4a => "MOV    C,D".to_string(),
+        0x4b => "MOV    C,E".to_string(),
+        0x4c => "MOV    C,H".to_string(),
+        0x4d => "MOV    C,L".to_string(),
+        0x4e => "MOV    C,M".to_string(),
+        0x4f => "MOV    C,A".to_string(),
 
-        0x50 => println!("MOV    D,B"),
-        0x51 => println!("MOV    D,C"),
-        0x52 => println!("MOV    D,D"),
-        0x53 => println!("MOV    D.E"),
-        0x54 => println!("MOV    D,H"),
-        0x55 => println!("MOV    D,L"),
-        0x56 => println!("MOV    D,M"),
-        0x57 => println!("MOV    D,A"),
-        0x58 => println!("MOV    E,B"),
-        0x59 => println!("MOV    E,C"),
-        0x5a => println!("MOV    E,D"),
-        0x5b => println!("MOV    E,E"),
-        0x5c => println!("MOV    E,H"),
-        0x5d => println!("MOV    E,L"),
-        0x5e => println!("MOV    E,M"),
-        0x5f => println!("MOV    E,A"),
+        0x50 => "MOV    D,B".to_string(),
+        0x51 => "MOV    D,C".to_string(),
+        0x52 => "MOV    D,D".to_string(),
+        0x53 => "MOV    D.E".to_string(),
+        0x54 => "MOV    D,H".to_string(),
+        0x55 => "MOV    D,L".to_string(),
+        0x56 => "MOV    D,M".to_string(),
+        0x57 => "MOV    D,A".to_string(),
+        0x58 => "MOV    E,B".to_string(),
+        0x59 => "MOV    E,C".to_string(),
+        0x5a => "MOV    E,D".to_string(),
+        0x5b => "MOV    E,E".to_string(),
+        0x5c => "MOV    E,H".to_string(),
+        0x5d => "MOV    E,L".to_string(),
+        0x5e => "MOV    E,M".to_string(),
+        0x5f => "MOV    E,A".to_string(),
 
-        0x60 => println!("MOV    H,B"),
-        0x61 => println!("MOV    H,C"),
-        0x62 => println!("MOV    H,D"),
-        0x63 => println!("MOV    H.E"),
-        0x64 => println!("MOV    H,H"),
-        0x65 => println!("MOV    H,L"),
-        0x66 => println!("MOV    H,M"),
-        0x67 => println!("MOV    H,A"),
-        0x68 => println!("MOV    L,B"),
-        0x69 => println!("MOV    L,C"),
-        0x6a => println!("MOV    L,D"),
-        0x6b => println!("MOV    L,E"),
-        0x6c => println!("MOV    L,H"),
-        0x6d => println!("MOV    L,L"),
-        0x6e => println!("MOV    L,M"),
-        0x6f => println!("MOV    L,A"),
+        0x60 => "MOV    H,B".to_string(),
+        0x61 => "MOV    H,C".to_string(),
+        0x62 => "MOV    H,D".to_string(),
+        0x63 => "MOV    H.E".to_string(),
+        0x64 => "MOV    H,H".to_string(),
+        0x65 => "MOV    H,L".to_string(),
+        0x66 => "MOV    H,M".to_string(),
+        0x67 => "MOV    H,A".to_string(),
+        0x68 => "MOV    L,B".to_string(),
+        0x69 => "MOV    L,C".to_string(),
+        0x6a => "MOV    L,D".to_string(),
+        0x6b => "MOV    L,E".to_string(),
+        0x6c => "MOV    L,H".to_string(),
+        0x6d => "MOV    L,L".to_string(),
+        0x6e => "MOV    L,M".to_string(),
+        0x6f => "MOV    L,A".to_string(),
 
-        0x70 => println!("MOV    M,B"),
-        0x71 => println!("MOV    M,C"),
-        0x72 => println!("MOV    M,D"),
-        0x73 => println!("MOV    M.E"),
-        0x74 => println!("MOV    M,H"),
-        0x75 => println!("MOV    M,L"),
-        0x76 => println!("HLT"),
-        0x77 => println!("MOV    M,A"),
-        0x78 => println!("MOV    A,B"),
-        0x79 => println!("MOV    A,C"),
-        0x7a => println!("MOV    A,D"),
-        0x7b => println!("MOV    A,E"),
-        0x7c => println!("MOV    A,H"),
-        0x7d => println!("MOV    A,L"),
-        0x7e => println!("MOV    A,M"),
-        0x7f => println!("MOV    A,A"),
+        0x70 => "MOV    M,B".to_string(),
+        0x71 => "MOV    M,C".to_string(),
+        0x72 => "MOV    M,D".to_string(),
+        0x73 => "MOV    M.E".to_string(),
+        0x74 => "MOV    M,H".to_string(),
+        0x75 => "MOV    M,L".to_string(),
+        0x76 => "HLT".to_string(),
+        0x77 => "MOV    M,A".to_string(),
+        0x78 => "MOV    A,B".to_string(),
+        0x79 => "MOV    A,C".to_string(),
+        0x7a => "MOV    A,D".to_string(),
+        0x7b => "MOV    A,E".to_string(),
+        0x7c => "MOV    A,H".to_string(),
+        0x7d => "MOV    A,L".to_string(),
+        0x7e => "MOV    A,M".to_string(),
+        0x7f => "MOV    A,A".to_string(),
 
-        0x80 => println!("ADD    B"),
-        0x81 => println!("ADD    C"),
-        0x82 => println!("ADD    D"),
-        0x83 => println!("ADD    E"),
-        0x84 => println!("ADD    H"),
-        0x85 => println!("ADD    L"),
-        0x86 => println!("ADD    M"),
-        0x87 => println!("ADD    A"),
-        0x88 => println!("ADC    B"),
-        0x89 => println!("ADC    C"),
-        0x8a => println!("ADC    D"),
-        0x8b => println!("ADC    E"),
-        0x8c => println!("ADC    H"),
-        0x8d => println!("ADC    L"),
-        0x8e => println!("ADC    M"),
-        0x8f => println!("ADC    A"),
+        0x80 => "ADD    B".to_string(),
+        0x81 => "ADD    C".to_string(),
+        0x82 => "ADD    D".to_string(),
+        0x83 => "ADD    E".to_string(),
+        0x84 => "ADD    H".to_string(),
+        0x85 => "ADD    L".to_string(),
+        0x86 => "ADD    M".to_string(),
+        0x87 => "ADD    A".to_string(),
+        0x88 => "ADC    B".to_string(),
+        0x89 => "ADC    C".to_string(),
+        0x8a => "ADC    D".to_string(),
+        0x8b => "ADC    E".to_string(),
+        0x8c => "ADC    H".to_string(),
+        0x8d => "ADC    L".to_string(),
+        0x8e => "ADC    M".to_string(),
+        0x8f => "ADC    A".to_string(),
 
-        0x90 => println!("SUB    B"),
-        0x91 => println!("SUB    C"),
-        0x92 => println!("SUB    D"),
-        0x93 => println!("SUB    E"),
-        0x94 => println!("SUB    H"),
-        0x95 => println!("SUB    L"),
-        0x96 => println!("SUB    M"),
-        0x97 => println!("SUB    A"),
-        0x98 => println!("SBB    B"),
-        0x99 => println!("SBB    C"),
-        0x9a => println!("SBB    D"),
-        0x9b => println!("SBB    E"),
-        0x9c => println!("SBB    H"),
-        0x9d => println!("SBB    L"),
-        0x9e => println!("SBB    M"),
-        0x9f => println!("SBB    A"),
+        0x90 => "SUB    B".to_string(),
+        0x91 => "SUB    C".to_string(),
+        0x92 => "SUB    D".to_string(),
+        0x93 => "SUB    E".to_string(),
+        0x94 => "SUB    H".to_string(),
+        0x95 => "SUB    L".to_string(),
+        0x96 => "SUB    M".to_string(),
+        0x97 => "SUB    A".to_string(),
+        0x98 => "SBB    B".to_string(),
+        0x99 => "SBB    C".to_string(),
+        0x9a => "SBB    D".to_string(),
+        0x9b => "SBB    E".to_string(),
+        0x9c => "SBB    H".to_string(),
+        0x9d => "SBB    L".to_string(),
+        0x9e => "SBB    M".to_string(),
+        0x9f => "SBB    A".to_string(),
 
-        0xa0 => println!("ANA    B"),
-        0xa1 => println!("ANA    C"),
-        0xa2 => println!("ANA    D"),
-        0xa3 => println!("ANA    E"),
-        0xa4 => println!("ANA    H"),
-        0xa5 => println!("ANA    L"),
-        0xa6 => println!("ANA    M"),
-        0xa7 => println!("ANA    A"),
-        0xa8 => println!("XRA    B"),
-        0xa9 => println!("XRA    C"),
-        0xaa => println!("XRA    D"),
-        0xab => println!("XRA    E"),
-        0xac => println!("XRA    H"),
-        0xad => println!("XRA    L"),
-        0xae => println!("XRA    M"),
-        0xaf => println!("XRA    A"),
+        0xa0 => "ANA    B".to_string(),
+        0xa1 => "ANA    C".to_string(),
+        0xa2 => "ANA    D".to_string(),
+        0xa3 => "ANA    E".to_string(),
+        0xa4 => "ANA    H".to_string(),
+        0xa5 => "ANA    L".to_string(),
+        0xa6 => "ANA    M".to_string(),
+        0xa7 => "ANA    A".to_string(),
+        0xa8 => "XRA    B".to_string(),
+        0xa9 => "XRA    C".to_string(),
+        0xaa => "XRA    D".to_string(),
+        0xab => "XRA    E".to_string(),
+        0xac => "XRA    H".to_string(),
+        0xad => "XRA    L".to_string(),
+        0xae => "XRA    M".to_string(),
+        0xaf => "XRA    A".to_string(),
 
-        0xb0 => println!("ORA    B"),
-        0xb1 => println!("ORA    C"),
-        0xb2 => println!("ORA    D"),
-        0xb3 => println!("ORA    E"),
-        0xb4 => println!("ORA    H"),
-        0xb5 => println!("ORA    L"),
-        0xb6 => println!("ORA    M"),
-        0xb7 => println!("ORA    A"),
-        0xb8 => println!("CMP    B"),
-        0xb9 => println!("CMP    C"),
-        0xba => println!("CMP    D"),
-        0xbb => println!("CMP    E"),
-        0xbc => println!("CMP    H"),
-        0xbd => println!("CMP    L"),
-        0xbe => println!("CMP    M"),
-        0xbf => println!("CMP    A"),
+        0xb0 => "ORA    B".to_string(),
+        0xb1 => "ORA    C".to_string(),
+        0xb2 => "ORA    D".to_string(),
+        0xb3 => "ORA    E".to_string(),
+        0xb4 => "ORA    H".to_string(),
+        0xb5 => "ORA    L".to_string(),
+        0xb6 => "ORA    M".to_string(),
+        0xb7 => "ORA    A".to_string(),
+        0xb8 => "CMP    B".to_string(),
+        0xb9 => "CMP    C".to_string(),
+        0xba => "CMP    D".to_string(),
+        0xbb => "CMP    E".to_string(),
+        0xbc => "CMP    H".to_string(),
+        0xbd => "CMP    L".to_string(),
+        0xbe => "CMP    M".to_string(),
+        0xbf => "CMP    A".to_string(),
 
-        0xc0 => println!("RNZ"),
-        0xc1 => println!("POP    B"),
-        0xc2 => {println!("JNZ    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xc3 => {println!("JMP    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xc4 => {println!("CNZ    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xc5 => println!("PUSH   B"),
-        0xc6 => {println!("ADI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xc7 => println!("RST    0"),
-        0xc8 => println!("RZ"),
-        0xc9 => println!("RET"),
-        0xca => {println!("JZ     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xcb => {println!("JMP    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xcc => {println!("CZ     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xcd => {println!("CALL   ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xce => {println!("ACI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xcf => println!("RST    1"),
+        0xc0 => "RNZ".to_string(),
+        0xc1 => "POP    B".to_string(),
+        0xc2 => {opbytes = 3;
+            format!("JNZ    ${:02x}{:02x}",byte(2),byte(1))},
+        0xc3 => {opbytes = 3;
+            format!("JMP    ${:02x}{:02x}",byte(2),byte(1))},
+        0xc4 => {opbytes = 3;
+            format!("CNZ    ${:02x}{:02x}",byte(2),byte(1))},
+        0xc5 => "PUSH   B".to_string(),
+        0xc6 => {opbytes = 2; format!("ADI    #${:02x}",byte(1))},
+        0xc7 => "RST    0".to_string(),
+        0xc8 => "RZ".to_string(),
+        0xc9 => "RET".to_string(),
+        0xca => {opbytes = 3;
+            format!("JZ     ${:02x}{:02x}",byte(2),byte(1))},
+        0xcb => {opbytes = 3;
+            format!("JMP    ${:02x}{:02x}",byte(2),byte(1))},
+        0xcc => {opbytes = 3;
+            format!("CZ     ${:02x}{:02x}",byte(2),byte(1))},
+        0xcd => {opbytes = 3;
+            format!("CALL   ${:02x}{:02x}",byte(2),byte(1))},
+        0xce => {opbytes = 2; format!("ACI    #${:02x}",byte(1))},
+        0xcf => "RST    1".to_string(),
 
-        0xd0 => println!("RNC"),
-        0xd1 => println!("POP    D"),
-        0xd2 => {println!("JNC    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xd3 => {println!("OUT    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xd4 => {println!("CNC    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xd5 => println!("PUSH   D"),
-        0xd6 => {println!("SUI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xd7 => println!("RST    2"),
-        0xd8 => println!("RC"),
-        0xd9 => println!("RET"),
-        0xda => {println!("JC     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xdb => {println!("IN     #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xdc => {println!("CC     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xdd => {println!("CALL   ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xde => {println!("SBI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xdf => println!("RST    3"),
+        0xd0 => "RNC".to_string(),
+        0xd1 => "POP    D".to_string(),
+        0xd2 => {opbytes = 3;
+            format!("JNC    ${:02x}{:02x}",byte(2),byte(1))},
+        0xd3 => {opbytes = 2; format!("OUT    #${:02x}",byte(1))},
+        0xd4 => {opbytes = 3;
+            format!("CNC    ${:02x}{:02x}",byte(2),byte(1))},
+        0xd5 => "PUSH   D".to_string(),
+        0xd6 => {opbytes = 2; format!("SUI    #${:02x}",byte(1))},
+        0xd7 => "RST    2".to_string(),
+        0xd8 => "RC".to_string(),
+        0xd9 => "RET".to_string(),
+        0xda => {opbytes = 3;
+            format!("JC     ${:02x}{:02x}",byte(2),byte(1))},
+        0xdb => {opbytes = 2; format!("IN     #${:02x}",byte(1))},
+        0xdc => {opbytes = 3;
+            format!("CC     ${:02x}{:02x}",byte(2),byte(1))},
+        0xdd => {opbytes = 3;
+            format!("CALL   ${:02x}{:02x}",byte(2),byte(1))},
+        0xde => {opbytes = 2; format!("SBI    #${:02x}",byte(1))},
+        0xdf => "RST    3".to_string(),
 
-        0xe0 => println!("RPO"),
-        0xe1 => println!("POP    H"),
-        0xe2 => {println!("JPO    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xe3 => println!("XTHL"),
-        0xe4 => {println!("CPO    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xe5 => println!("PUSH   H"),
-        0xe6 => {println!("ANI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xe7 => println!("RST    4"),
-        0xe8 => println!("RPE"),
-        0xe9 => println!("PCHL"),
-        0xea => {println!("JPE    ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xeb => println!("XCHG"),
-        0xec => {println!("CPE     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xed => {println!("CALL   ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xee => {println!("XRI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xef => println!("RST    5"),
+        0xe0 => "RPO".to_string(),
+        0xe1 => "POP    H".to_string(),
+        0xe2 => {opbytes = 3;
+            format!("JPO    ${:02x}{:02x}",byte(2),byte(1))},
+        0xe3 => "XTHL".to_string(),
+        0xe4 => {opbytes = 3;
+            format!("CPO    ${:02x}{:02x}",byte(2),byte(1))},
+        0xe5 => "PUSH   H".to_string(),
+        0xe6 => {opbytes = 2; format!("ANI    #${:02x}",byte(1))},
+        0xe7 => "RST    4".to_string(),
+        0xe8 => "RPE".to_string(),
+        0xe9 => "PCHL".to_string(),
+        0xea => {opbytes = 3;
+            format!("JPE    ${:02x}{:02x}",byte(2),byte(1))},
+        0xeb => "XCHG".to_string(),
+        0xec => {opbytes = 3;
+            format!("CPE     ${:02x}{:02x}",byte(2),byte(1))},
+        0xed => {opbytes = 3;
+            format!("CALL   ${:02x}{:02x}",byte(2),byte(1))},
+        0xee => {opbytes = 2; format!("XRI    #${:02x}",byte(1))},
+        0xef => "RST    5".to_string(),
 
-        0xf0 => println!("RP"),
-        0xf1 => println!("POP    PSW"),
-        0xf2 => {println!("JP     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xf3 => println!("DI"),
-        0xf4 => {println!("CP     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xf5 => println!("PUSH   PSW"),
-        0xf6 => {println!("ORI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xf7 => println!("RST    6"),
-        0xf8 => println!("RM"),
-        0xf9 => println!("SPHL"),
-        0xfa => {println!("JM     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xfb => println!("EI"),
-        0xfc => {println!("CM     ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xfd => {println!("CALL   ${:02x}{:02x}",buffer[pc + 2],buffer[pc + 1]);
-            opbytes = 3},
-        0xfe => {println!("CPI    #${:02x}",buffer[pc + 1]); opbytes = 2},
-        0xff => println!("RST    7"),
-    }
+        0xf0 => "RP".to_string(),
+        0xf1 => "POP    PSW".to_string(),
+        0xf2 => {opbytes = 3;
+            format!("JP     ${:02x}{:02x}",byte(2),byte(1))},
+        0xf3 => "DI".to_string(),
+        0xf4 => {opbytes = 3;
+            format!("CP     ${:02x}{:02x}",byte(2),byte(1))},
+        0xf5 => "PUSH   PSW".to_string(),
+        0xf6 => {opbytes = 2; format!("ORI    #${:02x}",byte(1))},
+        0xf7 => "RST    6".to_string(),
+        0xf8 => "RM".to_string(),
+        0xf9 => "SPHL".to_string(),
+        0xfa => {opbytes = 3;
+            format!("JM     ${:02x}{:02x}",byte(2),byte(1))},
+        0xfb => "EI".to_string(),
+        0xfc => {opbytes = 3;
+            format!("CM     ${:02x}{:02x}",byte(2),byte(1))},
+        0xfd => {opbytes = 3;
+            format!("CALL   ${:02x}{:02x}",byte(2),byte(1))},
+        0xfe => {opbytes = 2; format!("CPI    #${:02x}",byte(1))},
+        0xff => "RST    7".to_string(),
+    };
 
-    opbytes
+    (text, opbytes)
 }
 
 pub fn hexdump(buffer: Vec<u8>) {
@@ -318,7 +325,7 @@ pub fn hexdump(buffer: Vec<u8>) {
         }
         print!("{:02x} ", v);
         if i % 16 == 15 {
-            print!("\n");
+            println!();
         }
     }
 }
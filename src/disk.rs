@@ -0,0 +1,451 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::savestate::{Reader, Writer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub tracks: usize,
+    pub sectors: usize,
+    pub sector_size: usize,
+}
+
+impl Geometry {
+    //Altair 8" floppy as written by the 88-DCDD
+    pub const ALTAIR_8IN: Geometry = Geometry { tracks: 77, sectors: 32, sector_size: 137 };
+    //Single sided single density 8" IBM 3740 format used by CP/M
+    pub const IBM_3740: Geometry = Geometry { tracks: 77, sectors: 26, sector_size: 128 };
+
+    pub fn size(&self) -> usize {
+        self.tracks * self.sectors * self.sector_size
+    }
+
+    //Byte offset of a sector in the image, sectors counted from 0
+    pub fn offset(&self, track: usize, sector: usize) -> Option<usize> {
+        if track < self.tracks && sector < self.sectors {
+            Some((track * self.sectors + sector) * self.sector_size)
+        } else {
+            None
+        }
+    }
+}
+
+//Disk image held in memory. Written sectors go straight back to the image
+//file, write protected disks refuse writes.
+pub struct DiskImage {
+    file: File,
+    pub data: Vec<u8>,
+    pub geometry: Geometry,
+    pub read_only: bool,
+}
+
+impl DiskImage {
+    //Short images are padded to the full geometry. Files that can't be
+    //opened for writing are mounted write protected.
+    pub fn open(path: &Path, geometry: Geometry, read_only: bool) -> io::Result<DiskImage> {
+        let (mut file, read_only) = match read_only {
+            true => (File::open(path)?, true),
+            false => match OpenOptions::new().read(true).write(true).open(path) {
+                Ok(file) => (file, false),
+                Err(_) => (File::open(path)?, true),
+            },
+        };
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        if data.len() < geometry.size() {
+            data.resize(geometry.size(), 0xe5);
+        }
+        Ok(DiskImage {
+            file,
+            data,
+            geometry,
+            read_only,
+        })
+    }
+
+    pub fn read_sector(&self, track: usize, sector: usize, buf: &mut [u8]) -> bool {
+        match self.geometry.offset(track, sector) {
+            Some(offset) => {
+                let size = self.geometry.sector_size.min(buf.len());
+                buf[..size].copy_from_slice(&self.data[offset..offset + size]);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn write_sector(&mut self, track: usize, sector: usize, buf: &[u8]) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "disk is write protected"));
+        }
+        let offset = self.geometry.offset(track, sector)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no such sector"))?;
+        let size = self.geometry.sector_size.min(buf.len());
+        self.data[offset..offset + size].copy_from_slice(&buf[..size]);
+        self.file.seek(SeekFrom::Start(offset as u64))?;
+        self.file.write_all(&self.data[offset..offset + self.geometry.sector_size])
+    }
+}
+
+const DCDD_DRIVES: usize = 16;
+
+#[derive(Default)]
+struct DcddDrive {
+    image: Option<DiskImage>,
+    track: usize,
+    sector: usize,
+    byte: usize,
+    head_loaded: bool,
+    write_enabled: bool,
+    write_count: usize,
+    buf: Vec<u8>,
+}
+
+impl DcddDrive {
+    fn geometry(&self) -> Geometry {
+        self.image.as_ref().map_or(Geometry::ALTAIR_8IN, |image| image.geometry)
+    }
+}
+
+//MITS 88-DCDD floppy controller on ports 0x08 (select/status),
+//0x09 (control/sector position) and 0x0a (data). Each drive steps and
+//counts sectors by the geometry of its image, normally Geometry::ALTAIR_8IN.
+pub struct Dcdd {
+    drives: Vec<DcddDrive>,
+    selected: Option<usize>,
+    int_enable: bool,
+}
+
+impl Dcdd {
+    pub fn new() -> Dcdd {
+        Dcdd {
+            drives: (0..DCDD_DRIVES).map(|_| DcddDrive::default()).collect(),
+            selected: None,
+            int_enable: false,
+        }
+    }
+
+    pub fn insert(&mut self, drive: usize, image: DiskImage) -> Result<(), String> {
+        if drive >= DCDD_DRIVES {
+            return Err(format!("No drive {} on the 88-DCDD", drive));
+        }
+        self.drives[drive].image = Some(image);
+        Ok(())
+    }
+
+    fn drive(&mut self) -> Option<&mut DcddDrive> {
+        let drive = self.drives.get_mut(self.selected?)?;
+        drive.image.as_ref()?;
+        Some(drive)
+    }
+
+    //Status bits are active low
+    fn status(&mut self) -> u8 {
+        let int_enable = self.int_enable;
+        let drive = match self.drive() {
+            Some(drive) => drive,
+            None => return 0xff,
+        };
+        let mut status = 0xff;
+        if drive.write_enabled {
+            status &= !0x01; //ready for new write data
+        }
+        status &= !0x02; //head can move
+        if drive.head_loaded {
+            status &= !0x04;
+            status &= !0x80; //new read data available
+        }
+        if int_enable {
+            status &= !0x20;
+        }
+        if drive.track == 0 {
+            status &= !0x40;
+        }
+        status
+    }
+
+    fn flush_write(drive: &mut DcddDrive) {
+        if drive.write_enabled && drive.write_count > 0 {
+            if let Some(image) = drive.image.as_mut() {
+                if let Err(e) = image.write_sector(drive.track, drive.sector, &drive.buf) {
+                    eprintln!("88-DCDD: write to track {} sector {} failed: {}",
+                              drive.track, drive.sector, e);
+                }
+            }
+        }
+        drive.write_enabled = false;
+        drive.write_count = 0;
+    }
+
+    pub fn input(&mut self, port: u8) -> Option<u8> {
+        match port {
+            0x08 => Some(self.status()),
+            0x09 => {
+                //The disk spins a sector further on each poll
+                let drive = match self.drive() {
+                    Some(drive) if drive.head_loaded => drive,
+                    _ => return Some(0xff),
+                };
+                Dcdd::flush_write(drive);
+                drive.sector = (drive.sector + 1) % drive.geometry().sectors;
+                drive.byte = 0;
+                Some(0xc0 | (drive.sector << 1) as u8)
+            },
+            0x0a => {
+                let drive = match self.drive() {
+                    Some(drive) => drive,
+                    None => return Some(0x00),
+                };
+                if drive.byte == 0 {
+                    let (track, sector) = (drive.track, drive.sector);
+                    drive.buf.resize(drive.geometry().sector_size, 0);
+                    if let Some(image) = drive.image.as_ref() {
+                        image.read_sector(track, sector, &mut drive.buf);
+                    }
+                }
+                let byte = drive.buf.get(drive.byte).copied().unwrap_or(0);
+                drive.byte += 1;
+                Some(byte)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn output(&mut self, port: u8, value: u8) -> bool {
+        match port {
+            0x08 => {
+                if let Some(drive) = self.drive() {
+                    Dcdd::flush_write(drive);
+                }
+                self.selected = if value & 0x80 != 0 {
+                    None
+                } else {
+                    Some((value & 0x0f) as usize)
+                };
+            },
+            0x09 => {
+                if value & 0x10 != 0 {
+                    self.int_enable = true;
+                }
+                if value & 0x20 != 0 {
+                    self.int_enable = false;
+                }
+                let drive = match self.drive() {
+                    Some(drive) => drive,
+                    None => return true,
+                };
+                if value & 0x03 != 0 {
+                    Dcdd::flush_write(drive);
+                }
+                if value & 0x01 != 0 && drive.track + 1 < drive.geometry().tracks {
+                    drive.track += 1;
+                }
+                if value & 0x02 != 0 && drive.track > 0 {
+                    drive.track -= 1;
+                }
+                if value & 0x04 != 0 {
+                    drive.head_loaded = true;
+                }
+                if value & 0x08 != 0 {
+                    drive.head_loaded = false;
+                }
+                if value & 0x80 != 0 {
+                    drive.write_enabled = true;
+                    drive.write_count = 0;
+                    drive.buf = vec![0; drive.geometry().sector_size];
+                }
+            },
+            0x0a => {
+                if let Some(drive) = self.drive() {
+                    if drive.write_enabled && drive.write_count < drive.buf.len() {
+                        drive.buf[drive.write_count] = value;
+                        drive.write_count += 1;
+                        if drive.write_count == drive.buf.len() {
+                            Dcdd::flush_write(drive);
+                        }
+                    }
+                }
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    //Controller and drive registers for save states, without the images
+    pub fn save(&self, out: &mut Writer) {
+        out.u8(self.selected.map_or(0xff, |drive| drive as u8));
+        out.bool(self.int_enable);
+        for drive in &self.drives {
+            out.u8(drive.track as u8);
+            out.u8(drive.sector as u8);
+            out.u16(drive.byte as u16);
+            out.bool(drive.head_loaded);
+            out.bool(drive.write_enabled);
+            out.u16(drive.write_count as u16);
+            out.u16(drive.buf.len() as u16);
+            out.data.extend_from_slice(&drive.buf);
+        }
+    }
+
+    pub fn load(&mut self, data: &mut Reader) -> Result<(), String> {
+        self.selected = match data.u8()? {
+            0xff => None,
+            drive => Some(drive as usize % DCDD_DRIVES),
+        };
+        self.int_enable = data.bool()?;
+        for drive in self.drives.iter_mut() {
+            drive.track = data.u8()? as usize;
+            drive.sector = data.u8()? as usize;
+            drive.byte = data.u16()? as usize;
+            drive.head_loaded = data.bool()?;
+            drive.write_enabled = data.bool()?;
+            drive.write_count = data.u16()? as usize;
+            let len = data.u16()? as usize;
+            drive.buf = data.bytes(len)?.to_vec();
+        }
+        Ok(())
+    }
+}
+
+impl Default for Dcdd {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub const SIMPLE_DISK_PORT: u8 = 0x20;
+
+//Status codes of the simple disk controller
+pub const DISK_OK: u8 = 0;
+pub const DISK_BAD_DRIVE: u8 = 1;
+pub const DISK_BAD_TRACK: u8 = 2;
+pub const DISK_BAD_SECTOR: u8 = 3;
+pub const DISK_WRITE_PROTECTED: u8 = 4;
+pub const DISK_IO_ERROR: u8 = 5;
+
+//Generic disk controller that moves whole 128 byte sectors, programmed
+//through six consecutive ports starting at SIMPLE_DISK_PORT:
+//  +0 drive, +1 track, +2 sector (starting at 1), +3 command (0 read,
+//  1 write), +4 status of the last command, +5 sector data
+//Sector data is read byte by byte after a read command and filled byte by
+//byte before a write command.
+pub struct SimpleDisk {
+    pub drives: Vec<Option<DiskImage>>,
+    drive: usize,
+    track: usize,
+    sector: usize,
+    status: u8,
+    buf: [u8; 128],
+    pos: usize,
+}
+
+impl SimpleDisk {
+    pub fn new() -> SimpleDisk {
+        SimpleDisk {
+            drives: (0..16).map(|_| None).collect(),
+            drive: 0,
+            track: 0,
+            sector: 1,
+            status: DISK_OK,
+            buf: [0; 128],
+            pos: 0,
+        }
+    }
+
+    pub fn insert(&mut self, drive: usize, image: DiskImage) -> Result<(), String> {
+        match self.drives.get_mut(drive) {
+            Some(slot) => {
+                *slot = Some(image);
+                Ok(())
+            },
+            None => Err(format!("No drive {} on the disk controller", drive)),
+        }
+    }
+
+    //Runs a read or write on the selected sector and returns the status
+    pub fn command(&mut self, write: bool) -> u8 {
+        self.pos = 0;
+        let (track, sector) = (self.track, self.sector);
+        let image = match self.drives.get_mut(self.drive).and_then(|d| d.as_mut()) {
+            Some(image) => image,
+            None => return DISK_BAD_DRIVE,
+        };
+        if track >= image.geometry.tracks {
+            return DISK_BAD_TRACK;
+        }
+        if sector == 0 || sector > image.geometry.sectors {
+            return DISK_BAD_SECTOR;
+        }
+        if write {
+            if image.read_only {
+                return DISK_WRITE_PROTECTED;
+            }
+            match image.write_sector(track, sector - 1, &self.buf) {
+                Ok(()) => DISK_OK,
+                Err(_) => DISK_IO_ERROR,
+            }
+        } else {
+            image.read_sector(track, sector - 1, &mut self.buf);
+            DISK_OK
+        }
+    }
+
+    pub fn input(&mut self, port: u8) -> Option<u8> {
+        match port.wrapping_sub(SIMPLE_DISK_PORT) {
+            0 => Some(self.drive as u8),
+            1 => Some(self.track as u8),
+            2 => Some(self.sector as u8),
+            4 => Some(self.status),
+            5 => {
+                let byte = self.buf[self.pos % 128];
+                self.pos += 1;
+                Some(byte)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn output(&mut self, port: u8, value: u8) -> bool {
+        match port.wrapping_sub(SIMPLE_DISK_PORT) {
+            0 => self.drive = value as usize,
+            1 => self.track = value as usize,
+            2 => self.sector = value as usize,
+            3 => self.status = self.command(value == 1),
+            5 => {
+                self.buf[self.pos % 128] = value;
+                self.pos += 1;
+                return true;
+            },
+            _ => return false,
+        }
+        self.pos = 0;
+        true
+    }
+
+    //Controller registers for save states, without the images
+    pub fn save(&self, out: &mut Writer) {
+        out.u8(self.drive as u8);
+        out.u16(self.track as u16);
+        out.u16(self.sector as u16);
+        out.u8(self.status);
+        out.data.extend_from_slice(&self.buf);
+        out.u16(self.pos as u16);
+    }
+
+    pub fn load(&mut self, data: &mut Reader) -> Result<(), String> {
+        self.drive = data.u8()? as usize;
+        self.track = data.u16()? as usize;
+        self.sector = data.u16()? as usize;
+        self.status = data.u8()?;
+        self.buf.copy_from_slice(data.bytes(128)?);
+        self.pos = data.u16()? as usize;
+        Ok(())
+    }
+}
+
+impl Default for SimpleDisk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::breakpoints::{AccessKind, Kind};
+use crate::debugger::Target;
+use crate::runner::{Runner, StopReason};
+
+//Instructions between checks for a break request from the client
+const POLL_INTERVAL: u64 = 0x4000;
+
+//GDB remote serial protocol server for a debugger target.
+//
+//Registers are exchanged as six 16 bit pairs, little endian, in the order
+//of the Z80 GDB port so its register layout can be reused:
+//  0 AF (PSW, F in the low byte)  1 BC  2 DE  3 HL  4 SP  5 PC
+//Software and hardware breakpoints (Z0, Z1) both become execution
+//breakpoints, Z2/Z3/Z4 watch writes, reads and both on address ranges.
+//Ctrl-C from the client interrupts a continue. Reverse step and continue
+//(bs, bc) work when the runner records history.
+pub struct GdbServer<T: Target> {
+    pub target: T,
+    pub runner: Runner,
+    //Breakpoint ids by GDB type, address and length
+    breakpoints: HashMap<(u8, u16, u16), usize>,
+    ack: bool,
+}
+
+enum Reply {
+    Packet(String),
+    //Client detached or killed the program
+    Close,
+}
+
+enum Received {
+    Packet(String),
+    //Checksum mismatch, the client sends it again on a nak
+    Corrupt,
+    Disconnected,
+}
+
+impl<T: Target> GdbServer<T> {
+    pub fn new(target: T, runner: Runner) -> GdbServer<T> {
+        GdbServer {
+            target,
+            runner,
+            breakpoints: HashMap::new(),
+            ack: true,
+        }
+    }
+
+    //Waits for one client on 127.0.0.1:port and serves it until it detaches
+    pub fn listen(&mut self, port: u16) -> io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("Waiting for GDB on {}", listener.local_addr()?);
+        let (stream, addr) = listener.accept()?;
+        println!("GDB connected from {}", addr);
+        stream.set_nodelay(true)?;
+        self.serve(stream)
+    }
+
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        loop {
+            let packet = match read_packet(&mut reader)? {
+                Received::Packet(packet) => packet,
+                Received::Corrupt => {
+                    if self.ack {
+                        writer.write_all(b"-")?;
+                    }
+                    continue;
+                },
+                Received::Disconnected => break,
+            };
+            if self.ack {
+                writer.write_all(b"+")?;
+            }
+            match self.handle(&packet, &mut reader)? {
+                Reply::Packet(reply) => write_packet(&mut writer, &reply)?,
+                Reply::Close => {
+                    write_packet(&mut writer, "OK")?;
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str, reader: &mut BufReader<TcpStream>) -> io::Result<Reply> {
+        let command = packet.get(..1).unwrap_or("");
+        let args = packet.get(1..).unwrap_or("");
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => {
+                let state = self.target.state();
+                [state.psw(), state.bc(), state.de(), state.hl(), state.sp, state.pc].iter()
+                    .map(|r| hex(&r.to_le_bytes()))
+                    .collect()
+            },
+            "G" => match unhex(args) {
+                Some(bytes) if bytes.len() >= 12 => {
+                    for (i, pair) in bytes.chunks_exact(2).take(6).enumerate() {
+                        self.set_register(i, u16::from_le_bytes([pair[0], pair[1]]));
+                    }
+                    "OK".to_string()
+                },
+                _ => "E01".to_string(),
+            },
+            "p" => match usize::from_str_radix(args, 16).ok().and_then(|i| self.register(i)) {
+                Some(value) => hex(&value.to_le_bytes()),
+                None => "E01".to_string(),
+            },
+            "P" => {
+                let parsed = args.split_once('=').and_then(|(i, v)| {
+                    let bytes = unhex(v)?;
+                    Some((usize::from_str_radix(i, 16).ok()?, *bytes.first()?, bytes.get(1).copied().unwrap_or(0)))
+                });
+                match parsed {
+                    Some((i, lo, hi)) if i < 6 => {
+                        self.set_register(i, u16::from_le_bytes([lo, hi]));
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    let state = self.target.state();
+                    let bytes: Vec<u8> = (0..len)
+                        .map(|i| state.memory[addr.wrapping_add(i) as usize])
+                        .collect();
+                    hex(&bytes)
+                },
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let parsed = args.split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range)?, unhex(data)?)));
+                match parsed {
+                    Some(((addr, _), data)) => {
+                        let state = self.target.state();
+                        for (i, byte) in data.iter().enumerate() {
+                            state.memory[addr.wrapping_add(i as u16) as usize] = *byte;
+                        }
+                        "OK".to_string()
+                    },
+                    None => "E01".to_string(),
+                }
+            },
+            "s" | "c" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    self.target.state().pc = addr;
+                }
+                if command == "s" {
+                    let reason = self.target.step(&mut self.runner);
+                    self.stop_reply(reason)
+                } else {
+                    self.resume(reader)?
+                }
+            },
+            "Z" | "z" => {
+                let parsed = args.splitn(3, ',').collect::<Vec<&str>>();
+                let parsed = match parsed.as_slice() {
+                    [kind, addr, len] => kind.parse::<u8>().ok().zip(u16::from_str_radix(addr, 16).ok())
+                        .zip(u16::from_str_radix(len.split(';').next().unwrap_or(""), 16).ok()),
+                    _ => None,
+                };
+                match parsed {
+                    Some(((kind, addr), len)) if kind <= 4 => self.breakpoint(command == "Z", kind, addr, len),
+                    _ => String::new(),
+                }
+            },
+            "b" => match args {
+                "s" => match self.runner.reverse_step(self.target.state()) {
+                    Some(_) => "S05".to_string(),
+                    None => "T05replaylog:begin;".to_string(),
+                },
+                "c" => match self.runner.reverse_continue(self.target.state()) {
+                    Some(reason) => self.stop_reply(Some(reason)),
+                    None => "T05replaylog:begin;".to_string(),
+                },
+                _ => String::new(),
+            },
+            "k" | "D" => return Ok(Reply::Close),
+            "H" => "OK".to_string(),
+            "T" => "OK".to_string(),
+            "q" | "Q" => match packet {
+                p if p.starts_with("qSupported") => "PacketSize=4000;QStartNoAckMode+;ReverseStep+;ReverseContinue+".to_string(),
+                "QStartNoAckMode" => {
+                    self.ack = false;
+                    "OK".to_string()
+                },
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        };
+        Ok(Reply::Packet(reply))
+    }
+
+    fn register(&mut self, i: usize) -> Option<u16> {
+        let state = self.target.state();
+        Some(match i {
+            0 => state.psw(),
+            1 => state.bc(),
+            2 => state.de(),
+            3 => state.hl(),
+            4 => state.sp,
+            5 => state.pc,
+            _ => return None,
+        })
+    }
+
+    fn set_register(&mut self, i: usize, value: u16) {
+        let state = self.target.state();
+        match i {
+            0 => state.set_psw(value),
+            1 => state.set_bc(value),
+            2 => state.set_de(value),
+            3 => state.set_hl(value),
+            4 => state.sp = value,
+            5 => state.pc = value,
+            _ => {},
+        }
+    }
+
+    fn breakpoint(&mut self, insert: bool, kind: u8, addr: u16, len: u16) -> String {
+        let key = (kind, addr, len);
+        if !insert {
+            if let Some(id) = self.breakpoints.remove(&key) {
+                self.runner.breakpoints_mut().remove(id);
+            }
+            return "OK".to_string();
+        }
+        if self.breakpoints.contains_key(&key) {
+            return "OK".to_string();
+        }
+        let range = addr..=addr.saturating_add(len.max(1) - 1);
+        let kind = match kind {
+            0 | 1 => Kind::Exec(addr),
+            2 => Kind::Write(range),
+            3 => Kind::Read(range),
+            _ => Kind::Access(range),
+        };
+        let id = self.runner.breakpoints_mut().add(kind, None);
+        self.breakpoints.insert(key, id);
+        "OK".to_string()
+    }
+
+    //Runs until the runner stops or the client sends Ctrl-C
+    fn resume(&mut self, reader: &mut BufReader<TcpStream>) -> io::Result<String> {
+        let mut executed = 0u64;
+        loop {
+            if let Some(reason) = self.target.step(&mut self.runner) {
+                return Ok(self.stop_reply(Some(reason)));
+            }
+            executed += 1;
+            if executed.is_multiple_of(POLL_INTERVAL) && interrupted(reader)? {
+                return Ok("S02".to_string());
+            }
+        }
+    }
+
+    fn stop_reply(&self, reason: Option<StopReason>) -> String {
+        match reason {
+            Some(StopReason::Watchpoint { access, .. }) => {
+                let kind = match access.kind {
+                    AccessKind::Write => "watch",
+                    AccessKind::Read => "rwatch",
+                    _ => return "S05".to_string(),
+                };
+                format!("T05{}:{:04x};", kind, access.addr)
+            },
+            _ => "S05".to_string(),
+        }
+    }
+}
+
+//Checks without blocking whether the client sent Ctrl-C
+fn interrupted(reader: &mut BufReader<TcpStream>) -> io::Result<bool> {
+    if !reader.buffer().is_empty() {
+        let byte = reader.buffer()[0];
+        reader.consume(1);
+        return Ok(byte == 0x03);
+    }
+    let stream = reader.get_mut();
+    stream.set_nonblocking(true)?;
+    let mut byte = [0u8];
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+    match result {
+        Ok(0) => Err(io::Error::new(io::ErrorKind::ConnectionAborted, "GDB disconnected")),
+        Ok(_) => Ok(byte[0] == 0x03),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+//Reads the next packet, skipping acks
+fn read_packet<R: Read>(reader: &mut R) -> io::Result<Received> {
+    let mut byte = [0u8];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(Received::Disconnected);
+        }
+        if byte[0] == b'$' {
+            break;
+        }
+        //Acks, naks and a Ctrl-C while stopped are ignored
+    }
+    let mut data = Vec::new();
+    //Sum of the bytes as sent, before unescaping
+    let mut sum = 0u8;
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(Received::Disconnected);
+        }
+        match byte[0] {
+            b'#' => break,
+            b'}' => {
+                sum = sum.wrapping_add(byte[0]);
+                reader.read_exact(&mut byte)?;
+                sum = sum.wrapping_add(byte[0]);
+                data.push(byte[0] ^ 0x20);
+            },
+            b => {
+                sum = sum.wrapping_add(b);
+                data.push(b);
+            },
+        }
+    }
+    let mut checksum = [0u8; 2];
+    reader.read_exact(&mut checksum)?;
+    let checksum = std::str::from_utf8(&checksum).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+    if checksum != Some(sum) {
+        return Ok(Received::Corrupt);
+    }
+    Ok(Received::Packet(String::from_utf8_lossy(&data).into_owned()))
+}
+
+fn write_packet<W: Write>(writer: &mut W, data: &str) -> io::Result<()> {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    write!(writer, "${}#{:02x}", data, checksum)?;
+    writer.flush()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+//addr,length
+fn parse_range(s: &str) -> Option<(u16, u16)> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = u32::from_str_radix(addr, 16).ok()?;
+    let len = u32::from_str_radix(len, 16).ok()?;
+    if addr > 0xffff || len > 0x10000 {
+        return None;
+    }
+    Some((addr as u16, len.min(0xffff) as u16))
+}
@@ -0,0 +1,178 @@
+use std::collections::VecDeque;
+
+use crate::i8080cpu::{ConditionCodes, State8080};
+
+//Registers before an instruction, everything but memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub cc: ConditionCodes,
+    pub int_enable: bool,
+    pub halted: bool,
+    pub cycles: u64,
+}
+
+impl Registers {
+    pub fn save(state: &State8080) -> Registers {
+        Registers {
+            a: state.a,
+            b: state.b,
+            c: state.c,
+            d: state.d,
+            e: state.e,
+            h: state.h,
+            l: state.l,
+            sp: state.sp,
+            pc: state.pc,
+            cc: state.cc,
+            int_enable: state.int_enable,
+            halted: state.halted,
+            cycles: state.cycles,
+        }
+    }
+
+    pub fn restore(&self, state: &mut State8080) {
+        state.a = self.a;
+        state.b = self.b;
+        state.c = self.c;
+        state.d = self.d;
+        state.e = self.e;
+        state.h = self.h;
+        state.l = self.l;
+        state.sp = self.sp;
+        state.pc = self.pc;
+        state.cc = self.cc;
+        state.int_enable = self.int_enable;
+        state.halted = self.halted;
+        state.cycles = self.cycles;
+    }
+}
+
+//A memory write of an instruction with the value it replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Write {
+    //Address as the cpu wrote it
+    pub addr: u16,
+    //Where it landed in memory after the bus remapped it
+    pub target: u16,
+    pub old: u8,
+    pub value: u8,
+}
+
+//One executed instruction: the registers before it and how many memory
+//writes it made
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    //Number of the instruction, counted by the Runner from 1
+    pub instruction: u64,
+    pub before: Registers,
+    pub writes: usize,
+}
+
+//The last executed instructions, enough to undo them one by one. Memory
+//changed by host traps like BDOS calls and device state are not recorded.
+//The writes of all records share one queue, oldest first, so recording an
+//instruction allocates nothing once the queues have grown.
+pub struct History {
+    records: VecDeque<Record>,
+    writes: VecDeque<Write>,
+    capacity: usize,
+    //Writes of the instruction being executed, not yet in a record
+    pending: usize,
+    //Writes of the last undone instruction
+    undone: Vec<Write>,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            records: VecDeque::new(),
+            writes: VecDeque::new(),
+            capacity,
+            pending: 0,
+            undone: Vec::new(),
+        }
+    }
+
+    //Adds a write of the instruction being executed
+    pub fn write(&mut self, write: Write) {
+        self.writes.push_back(write);
+        self.pending += 1;
+    }
+
+    //Records the instruction that just made the pending writes
+    pub fn push(&mut self, instruction: u64, before: Registers) {
+        let writes = std::mem::take(&mut self.pending);
+        if self.capacity == 0 {
+            self.writes.clear();
+            return;
+        }
+        self.records.push_back(Record { instruction, before, writes });
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.records.len() > self.capacity {
+            let oldest = self.records.pop_front().unwrap();
+            self.writes.drain(..oldest.writes);
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    //Drops the oldest records beyond the new capacity
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    //Undoes the last instruction and returns its record, its writes are
+    //in undone() until the next undo
+    pub fn undo(&mut self, state: &mut State8080) -> Option<Record> {
+        let record = self.records.pop_back()?;
+        self.undone.clear();
+        for _ in 0..record.writes {
+            let write = self.writes.pop_back().unwrap();
+            state.memory[write.target as usize] = write.old;
+            self.undone.push(write);
+        }
+        self.undone.reverse();
+        record.before.restore(state);
+        Some(record)
+    }
+
+    pub fn undone(&self) -> &[Write] {
+        &self.undone
+    }
+
+    //Most recent instruction that changed memory at addr
+    pub fn last_write(&self, addr: u16) -> Option<(Record, Write)> {
+        let mut end = self.writes.len();
+        for record in self.records.iter().rev() {
+            let start = end - record.writes;
+            if let Some(write) = self.writes.range(start..end).rev().find(|w| w.target == addr) {
+                return Some((*record, *write));
+            }
+            end = start;
+        }
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
@@ -1,3 +1,5 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub struct ConditionCodes {
     pub z: bool,
     pub s: bool,
@@ -8,7 +10,7 @@ pub struct ConditionCodes {
 }
 
 impl ConditionCodes {
-    pub fn new() -> ConditionCodes {
+    pub const fn new() -> ConditionCodes {
         ConditionCodes {
             z: true,
             s: true,
@@ -18,8 +20,34 @@ impl ConditionCodes {
             pad: 3,
         }
     }
+
+    //Flags as they appear in the low byte of PSW: S Z 0 AC 0 P 1 CY
+    pub fn to_byte(self) -> u8 {
+        (self.s as u8) << 7
+            | (self.z as u8) << 6
+            | (self.ac & 1) << 4
+            | (self.p & 1) << 2
+            | (self.pad & 1) << 1
+            | (self.cy & 1)
+    }
+
+    pub fn set_byte(&mut self, flags: u8) {
+        self.s = flags & 0x80 != 0;
+        self.z = flags & 0x40 != 0;
+        self.ac = (flags >> 4) & 1;
+        self.p = (flags >> 2) & 1;
+        self.cy = flags & 1;
+    }
+}
+
+impl Default for ConditionCodes {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
+#[derive(Clone)]
+#[non_exhaustive]
 pub struct State8080 {
     pub a: u8,
     pub b: u8,
@@ -28,15 +56,17 @@ pub struct State8080 {
     pub e: u8,
     pub h: u8,
     pub l: u8,
-    pub sp: usize,
+    pub sp: u16,
     pub pc: u16,
-    pub memory: [u8; 0x4000],
+    pub memory: [u8; 0x10000],
     pub cc: ConditionCodes,
     pub int_enable: bool,
+    pub halted: bool,
+    pub cycles: u64,
 }
 
 impl State8080 {
-    pub fn new() -> State8080 {
+    pub const fn new() -> State8080 {
         State8080 {
             a: 0,
             b: 0,
@@ -47,388 +77,1268 @@ impl State8080 {
             l: 0,
             sp: 0,
             pc: 0,
-            memory: [0; 0x4000],
+            memory: [0; 0x10000],
             cc: ConditionCodes::new(),
             int_enable: true,
+            halted: false,
+            cycles: 0,
         }
     }
+
+    pub fn bc(&self) -> u16 {
+        shift_nn(self.b, self.c)
+    }
+
+    pub fn de(&self) -> u16 {
+        shift_nn(self.d, self.e)
+    }
+
+    pub fn hl(&self) -> u16 {
+        shift_nn(self.h, self.l)
+    }
+
+    pub fn psw(&self) -> u16 {
+        shift_nn(self.a, self.cc.to_byte())
+    }
+
+    pub fn set_bc(&mut self, value: u16) {
+        self.b = (value >> 8) as u8;
+        self.c = value as u8;
+    }
+
+    pub fn set_de(&mut self, value: u16) {
+        self.d = (value >> 8) as u8;
+        self.e = value as u8;
+    }
+
+    pub fn set_hl(&mut self, value: u16) {
+        self.h = (value >> 8) as u8;
+        self.l = value as u8;
+    }
+
+    pub fn set_psw(&mut self, value: u16) {
+        self.a = (value >> 8) as u8;
+        self.cc.set_byte(value as u8);
+    }
+}
+
+impl Default for State8080 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-fn unimplemented_instruction(state: &State8080) {
-    println!("\nInstruction: 0x{:02x}/{}", state.memory[state.pc as usize - 1],
-             state.memory[state.pc as usize - 1]);
-    println!("PC: {:04x}", state.pc - 1);
-    println!("Not implemented yet! Exiting...");
-    std::process::exit(1);
+//Everything the cpu touches outside of its registers goes through a Bus.
+//The default methods give plain RAM over State8080::memory and unconnected ports.
+pub trait Bus {
+    fn read(&mut self, memory: &[u8], addr: u16) -> u8 {
+        memory[addr as usize]
+    }
+
+    //Opcode and operand bytes. Kept apart from read so watchpoints don't see
+    //instruction fetches.
+    fn fetch(&mut self, memory: &[u8], addr: u16) -> u8 {
+        memory[addr as usize]
+    }
+
+    fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+        memory[addr as usize] = value;
+    }
+
+    //Index into memory a write to addr changes, None when it changes none.
+    //Buses that remap or drop writes say so here too, so a debugger can
+    //tell what a write replaced.
+    fn write_target(&self, addr: u16) -> Option<u16> {
+        Some(addr)
+    }
+
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+
+    fn output(&mut self, _port: u8, _value: u8) {}
 }
 
+//Bus without any devices attached
+pub struct NullBus;
+
+impl Bus for NullBus {}
+
+//Clock cycles per opcode. Conditional calls and returns list the taken case,
+//untaken ones are 6 cycles shorter.
+pub const CYCLES: [u8; 256] = [
+    4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4, //0x00
+    4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4, //0x10
+    4, 10, 16, 5, 5, 5, 7, 4, 4, 10, 16, 5, 5, 5, 7, 4, //0x20
+    4, 10, 13, 5, 10, 10, 10, 4, 4, 10, 13, 5, 5, 5, 7, 4, //0x30
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5, //0x40
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5, //0x50
+    5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5, //0x60
+    7, 7, 7, 7, 7, 7, 7, 7, 5, 5, 5, 5, 5, 5, 7, 5, //0x70
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, //0x80
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, //0x90
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, //0xa0
+    4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4, //0xb0
+    11, 10, 10, 10, 17, 11, 7, 11, 11, 10, 10, 10, 17, 17, 7, 11, //0xc0
+    11, 10, 10, 10, 17, 11, 7, 11, 11, 10, 10, 10, 17, 17, 7, 11, //0xd0
+    11, 10, 10, 18, 17, 11, 7, 11, 11, 5, 10, 4, 17, 17, 7, 11, //0xe0
+    11, 10, 10, 4, 17, 11, 7, 11, 11, 5, 10, 4, 17, 17, 7, 11, //0xf0
+];
+
+//Bytes of each instruction, the opcode included
+const LENGTHS: [u8; 256] = [
+    1, 3, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, //0x00
+    1, 3, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, //0x10
+    1, 3, 3, 1, 1, 1, 2, 1, 1, 1, 3, 1, 1, 1, 2, 1, //0x20
+    1, 3, 3, 1, 1, 1, 2, 1, 1, 1, 3, 1, 1, 1, 2, 1, //0x30
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0x40
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0x50
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0x60
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0x70
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0x80
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0x90
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0xa0
+    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //0xb0
+    1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 3, 3, 3, 2, 1, //0xc0
+    1, 1, 3, 2, 3, 1, 2, 1, 1, 1, 3, 2, 3, 3, 2, 1, //0xd0
+    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, //0xe0
+    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, //0xf0
+];
+
 fn shift_nn(shift1: u8, shift2: u8) -> u16 {
-    let mut tmp: u16;
-    tmp = (shift1 as u16) << 8;
-    tmp |= shift2 as u16;
-    return tmp;
+    ((shift1 as u16) << 8) | shift2 as u16
+}
+
+fn parity(value: u8) -> u8 {
+    (value.count_ones() & 1 == 0) as u8
+}
+
+fn set_zsp(state: &mut State8080, value: u8) {
+    state.cc.z = value == 0;
+    state.cc.s = 0x80 == (value & 0x80);
+    state.cc.p = parity(value);
+}
+
+fn add(state: &mut State8080, value: u8, carry: u8) {
+    let res = state.a as u16 + value as u16 + carry as u16;
+    state.cc.ac = ((state.a & 0xf) + (value & 0xf) + carry > 0xf) as u8;
+    state.cc.cy = (res > 0xff) as u8;
+    state.a = res as u8;
+    set_zsp(state, state.a);
+}
+
+//Subtraction is an addition of the complement on the 8080, which is also
+//where the auxiliary carry comes from.
+fn sub(state: &mut State8080, value: u8, borrow: u8) {
+    add(state, !value, borrow ^ 1);
+    state.cc.cy ^= 1;
+}
+
+fn cmp(state: &mut State8080, value: u8) {
+    let a = state.a;
+    sub(state, value, 0);
+    state.a = a;
 }
 
-pub fn emulate_8080_op(state: &mut State8080) {
-    if state.pc >= 0x2000 {
-        std::process::exit(0);
+fn ana(state: &mut State8080, value: u8) {
+    state.cc.ac = ((state.a | value) & 0x08 != 0) as u8;
+    state.cc.cy = 0;
+    state.a &= value;
+    set_zsp(state, state.a);
+}
+
+fn xra(state: &mut State8080, value: u8) {
+    state.cc.ac = 0;
+    state.cc.cy = 0;
+    state.a ^= value;
+    set_zsp(state, state.a);
+}
+
+fn ora(state: &mut State8080, value: u8) {
+    state.cc.ac = 0;
+    state.cc.cy = 0;
+    state.a |= value;
+    set_zsp(state, state.a);
+}
+
+fn inr(state: &mut State8080, value: u8) -> u8 {
+    let res = value.wrapping_add(1);
+    state.cc.ac = (res & 0xf == 0) as u8;
+    set_zsp(state, res);
+    res
+}
+
+fn dcr(state: &mut State8080, value: u8) -> u8 {
+    let res = value.wrapping_sub(1);
+    state.cc.ac = (res & 0xf != 0xf) as u8;
+    set_zsp(state, res);
+    res
+}
+
+fn dad(state: &mut State8080, value: u16) {
+    let res = state.hl() as u32 + value as u32;
+    state.cc.cy = (res > 0xffff) as u8;
+    state.set_hl(res as u16);
+}
+
+fn daa(state: &mut State8080) {
+    let mut correction = 0;
+    let mut cy = state.cc.cy;
+    let lsb = state.a & 0xf;
+    let msb = state.a >> 4;
+    if state.cc.ac == 1 || lsb > 9 {
+        correction |= 0x06;
+    }
+    if state.cc.cy == 1 || msb > 9 || (msb >= 9 && lsb > 9) {
+        correction |= 0x60;
+        cy = 1;
+    }
+    add(state, correction, 0);
+    state.cc.cy = cy;
+}
+
+fn push<B: Bus + ?Sized>(state: &mut State8080, bus: &mut B, value: u16) {
+    state.sp = state.sp.wrapping_sub(2);
+    bus.write(&mut state.memory, state.sp.wrapping_add(1), (value >> 8) as u8);
+    bus.write(&mut state.memory, state.sp, value as u8);
+}
+
+fn pop<B: Bus + ?Sized>(state: &mut State8080, bus: &mut B) -> u16 {
+    let lo = bus.read(&state.memory, state.sp);
+    let hi = bus.read(&state.memory, state.sp.wrapping_add(1));
+    state.sp = state.sp.wrapping_add(2);
+    shift_nn(hi, lo)
+}
+
+//Acknowledges an interrupt by executing RST num, if interrupts are enabled.
+//Returns whether the interrupt was taken.
+pub fn generate_interrupt<B: Bus + ?Sized>(state: &mut State8080, bus: &mut B, num: u8) -> bool {
+    if !state.int_enable {
+        return false;
+    }
+    let ret = state.pc;
+    push(state, bus, ret);
+    state.pc = 8 * (num as u16 & 7);
+    state.int_enable = false;
+    state.halted = false;
+    state.cycles += CYCLES[0xc7] as u64;
+    true
+}
+
+//Executes the instruction at pc and returns the number of cycles it took.
+//A halted cpu idles until an interrupt arrives.
+pub fn emulate_8080_op<B: Bus + ?Sized>(state: &mut State8080, bus: &mut B) -> u32 {
+    if state.halted {
+        state.cycles += 4;
+        return 4;
     }
     let mut opcode: [u8; 3] = [0; 3];
-    for i in 0..3 {
-        opcode[i] = state.memory[state.pc as usize + i];
-        //println!("{}: {}", i, opcode[i]); //debug
+    opcode[0] = bus.fetch(&state.memory, state.pc);
+    for i in 1..LENGTHS[opcode[0] as usize] as usize {
+        opcode[i] = bus.fetch(&state.memory, state.pc.wrapping_add(i as u16));
     }
+    let mut cycles = CYCLES[opcode[0] as usize] as u32;
 
-    state.pc += 1;
+    state.pc = state.pc.wrapping_add(1);
 
     match opcode[0] {
         0x00 => {}, //NOP
-        0x01 => { //LXI B,word
-            state.c = opcode[1];
+        0x01 => { //LXI B,D16
             state.b = opcode[2];
-            state.pc += 2;
+            state.c = opcode[1];
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x02 => { //STAX B
+            let addr = state.bc();
+            bus.write(&mut state.memory, addr, state.a);
+        },
+        0x03 => { //INX B
+            let bc = state.bc().wrapping_add(1);
+            state.set_bc(bc);
+        },
+        0x04 => { //INR B
+            state.b = inr(state, state.b);
         },
-        0x02 => {unimplemented_instruction(&state)},
-        0x03 => {unimplemented_instruction(&state)},
-        0x04 => {unimplemented_instruction(&state)},
         0x05 => { //DCR B
-            if state.b as i16 - 1 < 0 {
-                state.b = 0xff;
-            } else {
-                state.b -= 1;
-            }
-            state.cc.z = state.b == 0;
-            state.cc.s = 0x80 == (state.b & 0x80);
-            //TODO parity flag
-            println!("DCR B b: {:02x}, cc.z: {}, cc.s: {}", state.b, state.cc.z, state.cc.s);
+            state.b = dcr(state, state.b);
         },
-        0x06 => { //MVI B,N
+        0x06 => { //MVI B,D8
             state.b = opcode[1];
-            state.pc += 1;
-            println!("MVI B b: {:02x}", state.b);
-        },
-        0x07 => {unimplemented_instruction(&state)},
-        0x08 => {unimplemented_instruction(&state)},
-        0x09 => {unimplemented_instruction(&state)},
-        0x0a => {unimplemented_instruction(&state)},
-        0x0b => {unimplemented_instruction(&state)},
-        0x0c => {unimplemented_instruction(&state)},
-        0x0d => {unimplemented_instruction(&state)},
-        0x0e => {unimplemented_instruction(&state)},
-        0x0f => {unimplemented_instruction(&state)},
-
-        0x10 => {unimplemented_instruction(&state)},
-        0x11 => { //LXI D,NN
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x07 => { //RLC
+            state.cc.cy = state.a >> 7;
+            state.a = state.a.rotate_left(1);
+        },
+        0x08 => {}, //NOP (undocumented)
+        0x09 => { //DAD B
+            dad(state, state.bc());
+        },
+        0x0a => { //LDAX B
+            state.a = bus.read(&state.memory, state.bc());
+        },
+        0x0b => { //DCX B
+            let bc = state.bc().wrapping_sub(1);
+            state.set_bc(bc);
+        },
+        0x0c => { //INR C
+            state.c = inr(state, state.c);
+        },
+        0x0d => { //DCR C
+            state.c = dcr(state, state.c);
+        },
+        0x0e => { //MVI C,D8
+            state.c = opcode[1];
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x0f => { //RRC
+            state.cc.cy = state.a & 1;
+            state.a = state.a.rotate_right(1);
+        },
+
+        0x10 => {}, //NOP (undocumented)
+        0x11 => { //LXI D,D16
             state.d = opcode[2];
             state.e = opcode[1];
-            println!("LXI D d: {:02x}, e: {:02x}", state.d, state.e); //debug
-            state.pc += 2;
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x12 => { //STAX D
+            let addr = state.de();
+            bus.write(&mut state.memory, addr, state.a);
         },
-        0x12 => {unimplemented_instruction(&state)},
         0x13 => { //INX D
-            let mut de = shift_nn(state.d, state.e);
-            de = de.wrapping_add(1);
-            state.d = (de >> 8) as u8;
-            state.e = (de & 0xff) as u8;
-            println!("INX H de: {:04x} d: {:02x}, e: {:02x}", de, state.d, state.e); //debug
-        },
-        0x14 => {unimplemented_instruction(&state)},
-        0x15 => {unimplemented_instruction(&state)},
-        0x16 => {unimplemented_instruction(&state)},
-        0x17 => {unimplemented_instruction(&state)},
-        0x18 => {unimplemented_instruction(&state)},
-        0x19 => {unimplemented_instruction(&state)},
+            let de = state.de().wrapping_add(1);
+            state.set_de(de);
+        },
+        0x14 => { //INR D
+            state.d = inr(state, state.d);
+        },
+        0x15 => { //DCR D
+            state.d = dcr(state, state.d);
+        },
+        0x16 => { //MVI D,D8
+            state.d = opcode[1];
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x17 => { //RAL
+            let cy = state.cc.cy;
+            state.cc.cy = state.a >> 7;
+            state.a = (state.a << 1) | cy;
+        },
+        0x18 => {}, //NOP (undocumented)
+        0x19 => { //DAD D
+            dad(state, state.de());
+        },
         0x1a => { //LDAX D
-            state.a = state.memory[shift_nn(state.d, state.e) as usize];
-            println!("LDAX D a: {:02x}", state.a); //debug
+            state.a = bus.read(&state.memory, state.de());
+        },
+        0x1b => { //DCX D
+            let de = state.de().wrapping_sub(1);
+            state.set_de(de);
+        },
+        0x1c => { //INR E
+            state.e = inr(state, state.e);
+        },
+        0x1d => { //DCR E
+            state.e = dcr(state, state.e);
+        },
+        0x1e => { //MVI E,D8
+            state.e = opcode[1];
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x1f => { //RAR
+            let cy = state.cc.cy;
+            state.cc.cy = state.a & 1;
+            state.a = (state.a >> 1) | (cy << 7);
         },
-        0x1b => {unimplemented_instruction(&state)},
-        0x1c => {unimplemented_instruction(&state)},
-        0x1d => {unimplemented_instruction(&state)},
-        0x1e => {unimplemented_instruction(&state)},
-        0x1f => {unimplemented_instruction(&state)},
 
-        0x20 => {unimplemented_instruction(&state)},
-        0x21 => { //LXI H,NN
+        0x20 => {}, //NOP (undocumented)
+        0x21 => { //LXI H,D16
             state.h = opcode[2];
             state.l = opcode[1];
-            println!("LXI H h: {:02x}, l: {:02x}", state.h, state.l); //debug
-            state.pc += 2;
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x22 => { //SHLD adr
+            let addr = shift_nn(opcode[2], opcode[1]);
+            bus.write(&mut state.memory, addr, state.l);
+            bus.write(&mut state.memory, addr.wrapping_add(1), state.h);
+            state.pc = state.pc.wrapping_add(2);
         },
-        0x22 => {unimplemented_instruction(&state)},
         0x23 => { //INX H
-            let mut hl = shift_nn(state.h, state.l);
-            hl = hl.wrapping_add(1);
-            state.h = (hl >> 8) as u8;
-            state.l = (hl & 0xff) as u8;
-            println!("INX H hl: {:04x} h: {:02x}, l: {:02x}", hl, state.h, state.l); //debug
+            let hl = state.hl().wrapping_add(1);
+            state.set_hl(hl);
         },
         0x24 => { //INR H
-            //TODO flags
-            state.h += 1;
-        },
-        0x25 => {unimplemented_instruction(&state)},
-        0x26 => {unimplemented_instruction(&state)},
-        0x27 => {unimplemented_instruction(&state)},
-        0x28 => {unimplemented_instruction(&state)},
-        0x29 => {unimplemented_instruction(&state)},
-        0x2a => {unimplemented_instruction(&state)},
-        0x2b => {unimplemented_instruction(&state)},
-        0x2c => {unimplemented_instruction(&state)},
-        0x2d => {unimplemented_instruction(&state)},
-        0x2e => {unimplemented_instruction(&state)},
-        0x2f => {unimplemented_instruction(&state)},
-
-        0x30 => {unimplemented_instruction(&state)},
-        0x31 => { //LXI SP,NN
-            state.sp = shift_nn(opcode[2], opcode[1]) as usize;
-            println!("sp: {:04x}", state.sp); //debug
-            state.pc += 2;
-        }, 
-        0x32 => {unimplemented_instruction(&state)},
-        0x33 => {unimplemented_instruction(&state)},
-        0x34 => {unimplemented_instruction(&state)},
-        0x35 => {unimplemented_instruction(&state)},
-        0x36 => {unimplemented_instruction(&state)},
-        0x37 => {unimplemented_instruction(&state)},
-        0x38 => {unimplemented_instruction(&state)},
-        0x39 => {unimplemented_instruction(&state)},
-        0x3a => {unimplemented_instruction(&state)},
-        0x3b => {unimplemented_instruction(&state)},
-        0x3c => {unimplemented_instruction(&state)},
-        0x3d => {unimplemented_instruction(&state)},
-        0x3e => {unimplemented_instruction(&state)},
-        0x3f => {unimplemented_instruction(&state)},
-
-        0x40 => {unimplemented_instruction(&state)},
+            state.h = inr(state, state.h);
+        },
+        0x25 => { //DCR H
+            state.h = dcr(state, state.h);
+        },
+        0x26 => { //MVI H,D8
+            state.h = opcode[1];
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x27 => { //DAA
+            daa(state);
+        },
+        0x28 => {}, //NOP (undocumented)
+        0x29 => { //DAD H
+            dad(state, state.hl());
+        },
+        0x2a => { //LHLD adr
+            let addr = shift_nn(opcode[2], opcode[1]);
+            state.l = bus.read(&state.memory, addr);
+            state.h = bus.read(&state.memory, addr.wrapping_add(1));
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x2b => { //DCX H
+            let hl = state.hl().wrapping_sub(1);
+            state.set_hl(hl);
+        },
+        0x2c => { //INR L
+            state.l = inr(state, state.l);
+        },
+        0x2d => { //DCR L
+            state.l = dcr(state, state.l);
+        },
+        0x2e => { //MVI L,D8
+            state.l = opcode[1];
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x2f => { //CMA
+            state.a = !state.a;
+        },
+
+        0x30 => {}, //NOP (undocumented)
+        0x31 => { //LXI SP,D16
+            state.sp = shift_nn(opcode[2], opcode[1]);
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x32 => { //STA adr
+            bus.write(&mut state.memory, shift_nn(opcode[2], opcode[1]), state.a);
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x33 => { //INX SP
+            state.sp = state.sp.wrapping_add(1);
+        },
+        0x34 => { //INR M
+            let hl = state.hl();
+            let value = inr(state, bus.read(&state.memory, hl));
+            bus.write(&mut state.memory, hl, value);
+        },
+        0x35 => { //DCR M
+            let hl = state.hl();
+            let value = dcr(state, bus.read(&state.memory, hl));
+            bus.write(&mut state.memory, hl, value);
+        },
+        0x36 => { //MVI M,D8
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, opcode[1]);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x37 => { //STC
+            state.cc.cy = 1;
+        },
+        0x38 => {}, //NOP (undocumented)
+        0x39 => { //DAD SP
+            dad(state, state.sp);
+        },
+        0x3a => { //LDA adr
+            state.a = bus.read(&state.memory, shift_nn(opcode[2], opcode[1]));
+            state.pc = state.pc.wrapping_add(2);
+        },
+        0x3b => { //DCX SP
+            state.sp = state.sp.wrapping_sub(1);
+        },
+        0x3c => { //INR A
+            state.a = inr(state, state.a);
+        },
+        0x3d => { //DCR A
+            state.a = dcr(state, state.a);
+        },
+        0x3e => { //MVI A,D8
+            state.a = opcode[1];
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0x3f => { //CMC
+            state.cc.cy ^= 1;
+        },
+
+        0x40 => {}, //MOV B,B
         0x41 => { //MOV B,C
-            state.b = state.c
+            state.b = state.c;
         },
         0x42 => { //MOV B,D
-            state.b = state.d
+            state.b = state.d;
         },
         0x43 => { //MOV B,E
-            state.b = state.e
-        },
-        0x44 => {unimplemented_instruction(&state)},
-        0x45 => {unimplemented_instruction(&state)},
-        0x46 => {unimplemented_instruction(&state)},
-        0x47 => {unimplemented_instruction(&state)},
-        0x48 => {unimplemented_instruction(&state)},
-        0x49 => {unimplemented_instruction(&state)},
-        0x4a => {unimplemented_instruction(&state)},
-        0x4b => {unimplemented_instruction(&state)},
-        0x4c => {unimplemented_instruction(&state)},
-        0x4d => {unimplemented_instruction(&state)},
-        0x4e => {unimplemented_instruction(&state)},
-        0x4f => {unimplemented_instruction(&state)},
-
-        0x50 => {unimplemented_instruction(&state)},
-        0x51 => {unimplemented_instruction(&state)},
-        0x52 => {unimplemented_instruction(&state)},
-        0x53 => {unimplemented_instruction(&state)},
-        0x54 => {unimplemented_instruction(&state)},
-        0x55 => {unimplemented_instruction(&state)},
-        0x56 => {unimplemented_instruction(&state)},
-        0x57 => {unimplemented_instruction(&state)},
-        0x58 => {unimplemented_instruction(&state)},
-        0x59 => {unimplemented_instruction(&state)},
-        0x5a => {unimplemented_instruction(&state)},
-        0x5b => {unimplemented_instruction(&state)},
-        0x5c => {unimplemented_instruction(&state)},
-        0x5d => {unimplemented_instruction(&state)},
-        0x5e => {unimplemented_instruction(&state)},
-        0x5f => {unimplemented_instruction(&state)},
-
-        0x60 => {unimplemented_instruction(&state)},
-        0x61 => {unimplemented_instruction(&state)},
-        0x62 => {unimplemented_instruction(&state)},
-        0x63 => {unimplemented_instruction(&state)},
-        0x64 => {unimplemented_instruction(&state)},
-        0x65 => {unimplemented_instruction(&state)},
-        0x66 => {unimplemented_instruction(&state)},
-        0x67 => {unimplemented_instruction(&state)},
-        0x68 => {unimplemented_instruction(&state)},
-        0x69 => {unimplemented_instruction(&state)},
-        0x6a => {unimplemented_instruction(&state)},
-        0x6b => {unimplemented_instruction(&state)},
-        0x6c => {unimplemented_instruction(&state)},
-        0x6d => {unimplemented_instruction(&state)},
-        0x6e => {unimplemented_instruction(&state)},
-        0x6f => {unimplemented_instruction(&state)},
-
-        0x70 => {unimplemented_instruction(&state)},
-        0x71 => {unimplemented_instruction(&state)},
-        0x72 => {unimplemented_instruction(&state)},
-        0x73 => {unimplemented_instruction(&state)},
-        0x74 => {unimplemented_instruction(&state)},
-        0x75 => {unimplemented_instruction(&state)},
-        0x76 => {unimplemented_instruction(&state)},
+            state.b = state.e;
+        },
+        0x44 => { //MOV B,H
+            state.b = state.h;
+        },
+        0x45 => { //MOV B,L
+            state.b = state.l;
+        },
+        0x46 => { //MOV B,M
+            state.b = bus.read(&state.memory, state.hl());
+        },
+        0x47 => { //MOV B,A
+            state.b = state.a;
+        },
+        0x48 => { //MOV C,B
+            state.c = state.b;
+        },
+        0x49 => {}, //MOV C,C
+        0x4a => { //MOV C,D
+            state.c = state.d;
+        },
+        0x4b => { //MOV C,E
+            state.c = state.e;
+        },
+        0x4c => { //MOV C,H
+            state.c = state.h;
+        },
+        0x4d => { //MOV C,L
+            state.c = state.l;
+        },
+        0x4e => { //MOV C,M
+            state.c = bus.read(&state.memory, state.hl());
+        },
+        0x4f => { //MOV C,A
+            state.c = state.a;
+        },
+
+        0x50 => { //MOV D,B
+            state.d = state.b;
+        },
+        0x51 => { //MOV D,C
+            state.d = state.c;
+        },
+        0x52 => {}, //MOV D,D
+        0x53 => { //MOV D,E
+            state.d = state.e;
+        },
+        0x54 => { //MOV D,H
+            state.d = state.h;
+        },
+        0x55 => { //MOV D,L
+            state.d = state.l;
+        },
+        0x56 => { //MOV D,M
+            state.d = bus.read(&state.memory, state.hl());
+        },
+        0x57 => { //MOV D,A
+            state.d = state.a;
+        },
+        0x58 => { //MOV E,B
+            state.e = state.b;
+        },
+        0x59 => { //MOV E,C
+            state.e = state.c;
+        },
+        0x5a => { //MOV E,D
+            state.e = state.d;
+        },
+        0x5b => {}, //MOV E,E
+        0x5c => { //MOV E,H
+            state.e = state.h;
+        },
+        0x5d => { //MOV E,L
+            state.e = state.l;
+        },
+        0x5e => { //MOV E,M
+            state.e = bus.read(&state.memory, state.hl());
+        },
+        0x5f => { //MOV E,A
+            state.e = state.a;
+        },
+
+        0x60 => { //MOV H,B
+            state.h = state.b;
+        },
+        0x61 => { //MOV H,C
+            state.h = state.c;
+        },
+        0x62 => { //MOV H,D
+            state.h = state.d;
+        },
+        0x63 => { //MOV H,E
+            state.h = state.e;
+        },
+        0x64 => {}, //MOV H,H
+        0x65 => { //MOV H,L
+            state.h = state.l;
+        },
+        0x66 => { //MOV H,M
+            state.h = bus.read(&state.memory, state.hl());
+        },
+        0x67 => { //MOV H,A
+            state.h = state.a;
+        },
+        0x68 => { //MOV L,B
+            state.l = state.b;
+        },
+        0x69 => { //MOV L,C
+            state.l = state.c;
+        },
+        0x6a => { //MOV L,D
+            state.l = state.d;
+        },
+        0x6b => { //MOV L,E
+            state.l = state.e;
+        },
+        0x6c => { //MOV L,H
+            state.l = state.h;
+        },
+        0x6d => {}, //MOV L,L
+        0x6e => { //MOV L,M
+            state.l = bus.read(&state.memory, state.hl());
+        },
+        0x6f => { //MOV L,A
+            state.l = state.a;
+        },
+
+        0x70 => { //MOV M,B
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.b);
+        },
+        0x71 => { //MOV M,C
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.c);
+        },
+        0x72 => { //MOV M,D
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.d);
+        },
+        0x73 => { //MOV M,E
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.e);
+        },
+        0x74 => { //MOV M,H
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.h);
+        },
+        0x75 => { //MOV M,L
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.l);
+        },
+        0x76 => { //HLT
+            state.halted = true;
+        },
         0x77 => { //MOV M,A
-            state.memory[shift_nn(state.h, state.l) as usize] = state.a;
-            println!("MOV M,A hl: {:04x}, memory: {:02x}", shift_nn(state.h, state.l),
-                state.memory[shift_nn(state.h, state.l) as usize]); //debug
-        },
-        0x78 => {unimplemented_instruction(&state)},
-        0x79 => {unimplemented_instruction(&state)},
-        0x7a => {unimplemented_instruction(&state)},
-        0x7b => {unimplemented_instruction(&state)},
-        0x7c => {unimplemented_instruction(&state)},
-        0x7d => {unimplemented_instruction(&state)},
-        0x7e => {unimplemented_instruction(&state)},
-        0x7f => {unimplemented_instruction(&state)},
-
-        0x80 => {unimplemented_instruction(&state)},
-        0x81 => {unimplemented_instruction(&state)},
-        0x82 => {unimplemented_instruction(&state)},
-        0x83 => {unimplemented_instruction(&state)},
-        0x84 => {unimplemented_instruction(&state)},
-        0x85 => {unimplemented_instruction(&state)},
-        0x86 => {unimplemented_instruction(&state)},
-        0x87 => {unimplemented_instruction(&state)},
-        0x88 => {unimplemented_instruction(&state)},
-        0x89 => {unimplemented_instruction(&state)},
-        0x8a => {unimplemented_instruction(&state)},
-        0x8b => {unimplemented_instruction(&state)},
-        0x8c => {unimplemented_instruction(&state)},
-        0x8d => {unimplemented_instruction(&state)},
-        0x8e => {unimplemented_instruction(&state)},
-        0x8f => {unimplemented_instruction(&state)},
-
-        0x90 => {unimplemented_instruction(&state)},
-        0x91 => {unimplemented_instruction(&state)},
-        0x92 => {unimplemented_instruction(&state)},
-        0x93 => {unimplemented_instruction(&state)},
-        0x94 => {unimplemented_instruction(&state)},
-        0x95 => {unimplemented_instruction(&state)},
-        0x96 => {unimplemented_instruction(&state)},
-        0x97 => {unimplemented_instruction(&state)},
-        0x98 => {unimplemented_instruction(&state)},
-        0x99 => {unimplemented_instruction(&state)},
-        0x9a => {unimplemented_instruction(&state)},
-        0x9b => {unimplemented_instruction(&state)},
-        0x9c => {unimplemented_instruction(&state)},
-        0x9d => {unimplemented_instruction(&state)},
-        0x9e => {unimplemented_instruction(&state)},
-        0x9f => {unimplemented_instruction(&state)},
-
-        0xa0 => {unimplemented_instruction(&state)},
-        0xa1 => {unimplemented_instruction(&state)},
-        0xa2 => {unimplemented_instruction(&state)},
-        0xa3 => {unimplemented_instruction(&state)},
-        0xa4 => {unimplemented_instruction(&state)},
-        0xa5 => {unimplemented_instruction(&state)},
-        0xa6 => {unimplemented_instruction(&state)},
-        0xa7 => {unimplemented_instruction(&state)},
-        0xa8 => {unimplemented_instruction(&state)},
-        0xa9 => {unimplemented_instruction(&state)},
-        0xaa => {unimplemented_instruction(&state)},
-        0xab => {unimplemented_instruction(&state)},
-        0xac => {unimplemented_instruction(&state)},
-        0xad => {unimplemented_instruction(&state)},
-        0xae => {unimplemented_instruction(&state)},
-        0xaf => {unimplemented_instruction(&state)},
-
-        0xb0 => {unimplemented_instruction(&state)},
-        0xb1 => {unimplemented_instruction(&state)},
-        0xb2 => {unimplemented_instruction(&state)},
-        0xb3 => {unimplemented_instruction(&state)},
-        0xb4 => {unimplemented_instruction(&state)},
-        0xb5 => {unimplemented_instruction(&state)},
-        0xb6 => {unimplemented_instruction(&state)},
-        0xb7 => {unimplemented_instruction(&state)},
-        0xb8 => {unimplemented_instruction(&state)},
-        0xb9 => {unimplemented_instruction(&state)},
-        0xba => {unimplemented_instruction(&state)},
-        0xbb => {unimplemented_instruction(&state)},
-        0xbc => {unimplemented_instruction(&state)},
-        0xbd => {unimplemented_instruction(&state)},
-        0xbe => {unimplemented_instruction(&state)},
-        0xbf => {unimplemented_instruction(&state)},
-
-        0xc0 => {unimplemented_instruction(&state)},
-        0xc1 => {unimplemented_instruction(&state)},
-        0xc2 => { //JNZ NN
-            if state.cc.z {
+            let addr = state.hl();
+            bus.write(&mut state.memory, addr, state.a);
+        },
+        0x78 => { //MOV A,B
+            state.a = state.b;
+        },
+        0x79 => { //MOV A,C
+            state.a = state.c;
+        },
+        0x7a => { //MOV A,D
+            state.a = state.d;
+        },
+        0x7b => { //MOV A,E
+            state.a = state.e;
+        },
+        0x7c => { //MOV A,H
+            state.a = state.h;
+        },
+        0x7d => { //MOV A,L
+            state.a = state.l;
+        },
+        0x7e => { //MOV A,M
+            state.a = bus.read(&state.memory, state.hl());
+        },
+        0x7f => {}, //MOV A,A
+
+        0x80 => { //ADD B
+            add(state, state.b, 0);
+        },
+        0x81 => { //ADD C
+            add(state, state.c, 0);
+        },
+        0x82 => { //ADD D
+            add(state, state.d, 0);
+        },
+        0x83 => { //ADD E
+            add(state, state.e, 0);
+        },
+        0x84 => { //ADD H
+            add(state, state.h, 0);
+        },
+        0x85 => { //ADD L
+            add(state, state.l, 0);
+        },
+        0x86 => { //ADD M
+            let value = bus.read(&state.memory, state.hl());
+            add(state, value, 0);
+        },
+        0x87 => { //ADD A
+            add(state, state.a, 0);
+        },
+        0x88 => { //ADC B
+            let cy = state.cc.cy;
+            add(state, state.b, cy);
+        },
+        0x89 => { //ADC C
+            let cy = state.cc.cy;
+            add(state, state.c, cy);
+        },
+        0x8a => { //ADC D
+            let cy = state.cc.cy;
+            add(state, state.d, cy);
+        },
+        0x8b => { //ADC E
+            let cy = state.cc.cy;
+            add(state, state.e, cy);
+        },
+        0x8c => { //ADC H
+            let cy = state.cc.cy;
+            add(state, state.h, cy);
+        },
+        0x8d => { //ADC L
+            let cy = state.cc.cy;
+            add(state, state.l, cy);
+        },
+        0x8e => { //ADC M
+            let value = bus.read(&state.memory, state.hl());
+            let cy = state.cc.cy;
+            add(state, value, cy);
+        },
+        0x8f => { //ADC A
+            let cy = state.cc.cy;
+            add(state, state.a, cy);
+        },
+
+        0x90 => { //SUB B
+            sub(state, state.b, 0);
+        },
+        0x91 => { //SUB C
+            sub(state, state.c, 0);
+        },
+        0x92 => { //SUB D
+            sub(state, state.d, 0);
+        },
+        0x93 => { //SUB E
+            sub(state, state.e, 0);
+        },
+        0x94 => { //SUB H
+            sub(state, state.h, 0);
+        },
+        0x95 => { //SUB L
+            sub(state, state.l, 0);
+        },
+        0x96 => { //SUB M
+            let value = bus.read(&state.memory, state.hl());
+            sub(state, value, 0);
+        },
+        0x97 => { //SUB A
+            sub(state, state.a, 0);
+        },
+        0x98 => { //SBB B
+            let cy = state.cc.cy;
+            sub(state, state.b, cy);
+        },
+        0x99 => { //SBB C
+            let cy = state.cc.cy;
+            sub(state, state.c, cy);
+        },
+        0x9a => { //SBB D
+            let cy = state.cc.cy;
+            sub(state, state.d, cy);
+        },
+        0x9b => { //SBB E
+            let cy = state.cc.cy;
+            sub(state, state.e, cy);
+        },
+        0x9c => { //SBB H
+            let cy = state.cc.cy;
+            sub(state, state.h, cy);
+        },
+        0x9d => { //SBB L
+            let cy = state.cc.cy;
+            sub(state, state.l, cy);
+        },
+        0x9e => { //SBB M
+            let value = bus.read(&state.memory, state.hl());
+            let cy = state.cc.cy;
+            sub(state, value, cy);
+        },
+        0x9f => { //SBB A
+            let cy = state.cc.cy;
+            sub(state, state.a, cy);
+        },
+
+        0xa0 => { //ANA B
+            ana(state, state.b);
+        },
+        0xa1 => { //ANA C
+            ana(state, state.c);
+        },
+        0xa2 => { //ANA D
+            ana(state, state.d);
+        },
+        0xa3 => { //ANA E
+            ana(state, state.e);
+        },
+        0xa4 => { //ANA H
+            ana(state, state.h);
+        },
+        0xa5 => { //ANA L
+            ana(state, state.l);
+        },
+        0xa6 => { //ANA M
+            let value = bus.read(&state.memory, state.hl());
+            ana(state, value);
+        },
+        0xa7 => { //ANA A
+            ana(state, state.a);
+        },
+        0xa8 => { //XRA B
+            xra(state, state.b);
+        },
+        0xa9 => { //XRA C
+            xra(state, state.c);
+        },
+        0xaa => { //XRA D
+            xra(state, state.d);
+        },
+        0xab => { //XRA E
+            xra(state, state.e);
+        },
+        0xac => { //XRA H
+            xra(state, state.h);
+        },
+        0xad => { //XRA L
+            xra(state, state.l);
+        },
+        0xae => { //XRA M
+            let value = bus.read(&state.memory, state.hl());
+            xra(state, value);
+        },
+        0xaf => { //XRA A
+            xra(state, state.a);
+        },
+
+        0xb0 => { //ORA B
+            ora(state, state.b);
+        },
+        0xb1 => { //ORA C
+            ora(state, state.c);
+        },
+        0xb2 => { //ORA D
+            ora(state, state.d);
+        },
+        0xb3 => { //ORA E
+            ora(state, state.e);
+        },
+        0xb4 => { //ORA H
+            ora(state, state.h);
+        },
+        0xb5 => { //ORA L
+            ora(state, state.l);
+        },
+        0xb6 => { //ORA M
+            let value = bus.read(&state.memory, state.hl());
+            ora(state, value);
+        },
+        0xb7 => { //ORA A
+            ora(state, state.a);
+        },
+        0xb8 => { //CMP B
+            cmp(state, state.b);
+        },
+        0xb9 => { //CMP C
+            cmp(state, state.c);
+        },
+        0xba => { //CMP D
+            cmp(state, state.d);
+        },
+        0xbb => { //CMP E
+            cmp(state, state.e);
+        },
+        0xbc => { //CMP H
+            cmp(state, state.h);
+        },
+        0xbd => { //CMP L
+            cmp(state, state.l);
+        },
+        0xbe => { //CMP M
+            let value = bus.read(&state.memory, state.hl());
+            cmp(state, value);
+        },
+        0xbf => { //CMP A
+            cmp(state, state.a);
+        },
+
+        0xc0 => { //RNZ
+            if !state.cc.z {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xc1 => { //POP B
+            let bc = pop(state, bus);
+            state.set_bc(bc);
+        },
+        0xc2 => { //JNZ adr
+            if !state.cc.z {
                 state.pc = shift_nn(opcode[2], opcode[1]);
-                println!("jnz pc: {:04x}", state.pc); //debug
             } else {
-                println!("jnz skipped!");
+                state.pc = state.pc.wrapping_add(2);
             }
         },
         0xc3 => { //JMP adr
             state.pc = shift_nn(opcode[2], opcode[1]);
-            println!("jmp pc: {:04x}", state.pc); //debug
-        },
-        0xc4 => {unimplemented_instruction(&state)},
-        0xc5 => {unimplemented_instruction(&state)},
-        0xc6 => {unimplemented_instruction(&state)},
-        0xc7 => {unimplemented_instruction(&state)},
-        0xc8 => {unimplemented_instruction(&state)},
-        0xc9 => {unimplemented_instruction(&state)},
-        0xca => {unimplemented_instruction(&state)},
-        0xcb => {unimplemented_instruction(&state)},
-        0xcc => {unimplemented_instruction(&state)},
-        0xcd => { //CALL NN
-            let tmp: u16 = state.pc + 2;
-            state.memory[state.sp - 1] = ((tmp >> 8) & 0xff) as u8;
-            state.memory[state.sp - 2] = (tmp & 0xff) as u8;
-            state.sp -= 2;
+        },
+        0xc4 => { //CNZ adr
+            if !state.cc.z {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xc5 => { //PUSH B
+            let bc = state.bc();
+            push(state, bus, bc);
+        },
+        0xc6 => { //ADI D8
+            add(state, opcode[1], 0);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xc7 => { //RST 0
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x0;
+        },
+        0xc8 => { //RZ
+            if state.cc.z {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xc9 => { //RET
+            state.pc = pop(state, bus);
+        },
+        0xca => { //JZ adr
+            if state.cc.z {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xcb => { //JMP adr (undocumented)
+            state.pc = shift_nn(opcode[2], opcode[1]);
+        },
+        0xcc => { //CZ adr
+            if state.cc.z {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xcd => { //CALL adr
+            let ret = state.pc.wrapping_add(2);
+            push(state, bus, ret);
             state.pc = shift_nn(opcode[2], opcode[1]);
-            println!("call pc: {:04x}", state.pc); //debug
-        },
-        0xce => {unimplemented_instruction(&state)},
-        0xcf => {unimplemented_instruction(&state)},
-
-        0xd0 => {unimplemented_instruction(&state)},
-        0xd1 => {unimplemented_instruction(&state)},
-        0xd2 => {unimplemented_instruction(&state)},
-        0xd3 => {unimplemented_instruction(&state)},
-        0xd4 => {unimplemented_instruction(&state)},
-        0xd5 => {unimplemented_instruction(&state)},
-        0xd6 => {unimplemented_instruction(&state)},
-        0xd7 => {unimplemented_instruction(&state)},
-        0xd8 => {unimplemented_instruction(&state)},
-        0xd9 => {unimplemented_instruction(&state)},
-        0xda => {unimplemented_instruction(&state)},
-        0xdb => {unimplemented_instruction(&state)},
-        0xdc => {unimplemented_instruction(&state)},
-        0xdd => {unimplemented_instruction(&state)},
-        0xde => {unimplemented_instruction(&state)},
-        0xdf => {unimplemented_instruction(&state)},
-
-        0xe0 => {unimplemented_instruction(&state)},
-        0xe1 => {unimplemented_instruction(&state)},
-        0xe2 => {unimplemented_instruction(&state)},
-        0xe3 => {unimplemented_instruction(&state)},
-        0xe4 => {unimplemented_instruction(&state)},
-        0xe5 => {unimplemented_instruction(&state)},
-        0xe6 => {unimplemented_instruction(&state)},
-        0xe7 => {unimplemented_instruction(&state)},
-        0xe8 => {unimplemented_instruction(&state)},
-        0xe9 => {unimplemented_instruction(&state)},
-        0xea => {unimplemented_instruction(&state)},
-        0xeb => {unimplemented_instruction(&state)},
-        0xec => {unimplemented_instruction(&state)},
-        0xed => {unimplemented_instruction(&state)},
-        0xee => {unimplemented_instruction(&state)},
-        0xef => {unimplemented_instruction(&state)},
-
-        0xf0 => {unimplemented_instruction(&state)},
-        0xf1 => {unimplemented_instruction(&state)},
-        0xf2 => {unimplemented_instruction(&state)},
-        0xf3 => {unimplemented_instruction(&state)},
-        0xf4 => {unimplemented_instruction(&state)},
-        0xf5 => {unimplemented_instruction(&state)},
-        0xf6 => {unimplemented_instruction(&state)},
-        0xf7 => {unimplemented_instruction(&state)},
-        0xf8 => {unimplemented_instruction(&state)},
-        0xf9 => {unimplemented_instruction(&state)},
-        0xfa => {unimplemented_instruction(&state)},
-        0xfb => {unimplemented_instruction(&state)},
-        0xfc => {unimplemented_instruction(&state)},
-        0xfd => {unimplemented_instruction(&state)},
-        0xfe => {unimplemented_instruction(&state)},
-        0xff => {unimplemented_instruction(&state)},
+        },
+        0xce => { //ACI D8
+            let cy = state.cc.cy;
+            add(state, opcode[1], cy);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xcf => { //RST 1
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x8;
+        },
+
+        0xd0 => { //RNC
+            if state.cc.cy == 0 {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xd1 => { //POP D
+            let de = pop(state, bus);
+            state.set_de(de);
+        },
+        0xd2 => { //JNC adr
+            if state.cc.cy == 0 {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xd3 => { //OUT D8
+            bus.output(opcode[1], state.a);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xd4 => { //CNC adr
+            if state.cc.cy == 0 {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xd5 => { //PUSH D
+            let de = state.de();
+            push(state, bus, de);
+        },
+        0xd6 => { //SUI D8
+            sub(state, opcode[1], 0);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xd7 => { //RST 2
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x10;
+        },
+        0xd8 => { //RC
+            if state.cc.cy == 1 {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xd9 => { //RET (undocumented)
+            state.pc = pop(state, bus);
+        },
+        0xda => { //JC adr
+            if state.cc.cy == 1 {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xdb => { //IN D8
+            state.a = bus.input(opcode[1]);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xdc => { //CC adr
+            if state.cc.cy == 1 {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xdd => { //CALL adr (undocumented)
+            let ret = state.pc.wrapping_add(2);
+            push(state, bus, ret);
+            state.pc = shift_nn(opcode[2], opcode[1]);
+        },
+        0xde => { //SBI D8
+            let cy = state.cc.cy;
+            sub(state, opcode[1], cy);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xdf => { //RST 3
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x18;
+        },
+
+        0xe0 => { //RPO
+            if state.cc.p == 0 {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xe1 => { //POP H
+            let hl = pop(state, bus);
+            state.set_hl(hl);
+        },
+        0xe2 => { //JPO adr
+            if state.cc.p == 0 {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xe3 => { //XTHL
+            let lo = bus.read(&state.memory, state.sp);
+            let hi = bus.read(&state.memory, state.sp.wrapping_add(1));
+            bus.write(&mut state.memory, state.sp, state.l);
+            bus.write(&mut state.memory, state.sp.wrapping_add(1), state.h);
+            state.l = lo;
+            state.h = hi;
+        },
+        0xe4 => { //CPO adr
+            if state.cc.p == 0 {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xe5 => { //PUSH H
+            let hl = state.hl();
+            push(state, bus, hl);
+        },
+        0xe6 => { //ANI D8
+            ana(state, opcode[1]);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xe7 => { //RST 4
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x20;
+        },
+        0xe8 => { //RPE
+            if state.cc.p == 1 {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xe9 => { //PCHL
+            state.pc = state.hl();
+        },
+        0xea => { //JPE adr
+            if state.cc.p == 1 {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xeb => { //XCHG
+            core::mem::swap(&mut state.h, &mut state.d);
+            core::mem::swap(&mut state.l, &mut state.e);
+        },
+        0xec => { //CPE adr
+            if state.cc.p == 1 {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xed => { //CALL adr (undocumented)
+            let ret = state.pc.wrapping_add(2);
+            push(state, bus, ret);
+            state.pc = shift_nn(opcode[2], opcode[1]);
+        },
+        0xee => { //XRI D8
+            xra(state, opcode[1]);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xef => { //RST 5
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x28;
+        },
+
+        0xf0 => { //RP
+            if !state.cc.s {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xf1 => { //POP PSW
+            let psw = pop(state, bus);
+            state.set_psw(psw);
+        },
+        0xf2 => { //JP adr
+            if !state.cc.s {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xf3 => { //DI
+            state.int_enable = false;
+        },
+        0xf4 => { //CP adr
+            if !state.cc.s {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xf5 => { //PUSH PSW
+            let psw = state.psw();
+            push(state, bus, psw);
+        },
+        0xf6 => { //ORI D8
+            ora(state, opcode[1]);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xf7 => { //RST 6
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x30;
+        },
+        0xf8 => { //RM
+            if state.cc.s {
+                state.pc = pop(state, bus);
+            } else {
+                cycles -= 6;
+            }
+        },
+        0xf9 => { //SPHL
+            state.sp = state.hl();
+        },
+        0xfa => { //JM adr
+            if state.cc.s {
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+            }
+        },
+        0xfb => { //EI
+            state.int_enable = true;
+        },
+        0xfc => { //CM adr
+            if state.cc.s {
+                let ret = state.pc.wrapping_add(2);
+                push(state, bus, ret);
+                state.pc = shift_nn(opcode[2], opcode[1]);
+            } else {
+                state.pc = state.pc.wrapping_add(2);
+                cycles -= 6;
+            }
+        },
+        0xfd => { //CALL adr (undocumented)
+            let ret = state.pc.wrapping_add(2);
+            push(state, bus, ret);
+            state.pc = shift_nn(opcode[2], opcode[1]);
+        },
+        0xfe => { //CPI D8
+            cmp(state, opcode[1]);
+            state.pc = state.pc.wrapping_add(1);
+        },
+        0xff => { //RST 7
+            let ret = state.pc;
+            push(state, bus, ret);
+            state.pc = 0x38;
+        },
     }
+    state.cycles += cycles as u64;
+    cycles
 }
@@ -0,0 +1,264 @@
+use crate::audio::SoundRecorder;
+use crate::i8080cpu::{Bus, State8080};
+use crate::runner::{Runner, StopReason};
+use crate::savestate::{Device, Reader, Writer};
+
+pub const ROM_SIZE: usize = 0x2000;
+
+//2 MHz cpu clock and a 60 Hz display
+pub const CPU_HZ: u64 = 2_000_000;
+pub const CYCLES_PER_FRAME: u64 = CPU_HZ / 60;
+const HALF_FRAME: u64 = CYCLES_PER_FRAME / 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Coin,
+    P1Start,
+    P2Start,
+    P1Fire,
+    P1Left,
+    P1Right,
+    P2Fire,
+    P2Left,
+    P2Right,
+    Tilt,
+}
+
+pub struct DipSwitches {
+    //Ships per game, 3 to 6
+    pub ships: u8,
+    //Extra ship at 1000 instead of 1500 points
+    pub extra_ship_early: bool,
+    //Show the coin info on the demo screen
+    pub coin_info: bool,
+}
+
+impl DipSwitches {
+    pub fn new() -> DipSwitches {
+        DipSwitches {
+            ships: 3,
+            extra_ship_early: false,
+            coin_info: true,
+        }
+    }
+}
+
+impl Default for DipSwitches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//Everything on the board besides the cpu: memory map, MB14241 shift
+//register, input ports and sound latches. Watchdog writes on port 6 are
+//accepted but the emulated board never needs resetting.
+pub struct InvadersBus {
+    pub dip: DipSwitches,
+    port1: u8,
+    port2: u8,
+    shift: u16,
+    shift_offset: u8,
+    pub sound1: u8,
+    pub sound2: u8,
+}
+
+impl InvadersBus {
+    pub fn new() -> InvadersBus {
+        InvadersBus {
+            dip: DipSwitches::new(),
+            port1: 0x08,
+            port2: 0x00,
+            shift: 0,
+            shift_offset: 0,
+            sound1: 0,
+            sound2: 0,
+        }
+    }
+
+    pub fn set_input(&mut self, input: Input, pressed: bool) {
+        let (port, bit) = match input {
+            Input::Coin => (&mut self.port1, 0),
+            Input::P2Start => (&mut self.port1, 1),
+            Input::P1Start => (&mut self.port1, 2),
+            Input::P1Fire => (&mut self.port1, 4),
+            Input::P1Left => (&mut self.port1, 5),
+            Input::P1Right => (&mut self.port1, 6),
+            Input::Tilt => (&mut self.port2, 2),
+            Input::P2Fire => (&mut self.port2, 4),
+            Input::P2Left => (&mut self.port2, 5),
+            Input::P2Right => (&mut self.port2, 6),
+        };
+        if pressed {
+            *port |= 1 << bit;
+        } else {
+            *port &= !(1 << bit);
+        }
+    }
+
+    //Input ports 1 and 2 without the DIP switches
+    pub fn ports(&self) -> (u8, u8) {
+        (self.port1, self.port2)
+    }
+
+    pub fn set_ports(&mut self, (port1, port2): (u8, u8)) {
+        self.port1 = port1;
+        self.port2 = port2;
+    }
+
+    fn dip_bits(&self) -> u8 {
+        let mut bits = (self.dip.ships.clamp(3, 6) - 3) & 0x03;
+        if self.dip.extra_ship_early {
+            bits |= 0x08;
+        }
+        if !self.dip.coin_info {
+            bits |= 0x80;
+        }
+        bits
+    }
+}
+
+impl Default for InvadersBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus for InvadersBus {
+    fn read(&mut self, memory: &[u8], addr: u16) -> u8 {
+        //Only 16 KiB are decoded, everything above mirrors it
+        memory[(addr & 0x3fff) as usize]
+    }
+
+    fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+        if let Some(addr) = self.write_target(addr) {
+            memory[addr as usize] = value;
+        }
+    }
+
+    //RAM is mirrored above 0x4000, the ROM ignores writes
+    fn write_target(&self, addr: u16) -> Option<u16> {
+        let addr = addr & 0x3fff;
+        if (addr as usize) < ROM_SIZE { None } else { Some(addr) }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        match port {
+            0 => 0x0e,
+            1 => self.port1,
+            2 => self.port2 | self.dip_bits(),
+            3 => (self.shift >> (8 - self.shift_offset)) as u8,
+            _ => 0,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            2 => self.shift_offset = value & 0x07,
+            3 => self.sound1 = value,
+            4 => self.shift = ((value as u16) << 8) | (self.shift >> 8),
+            5 => self.sound2 = value,
+            _ => {},
+        }
+    }
+}
+
+pub struct SpaceInvaders {
+    pub state: State8080,
+    pub bus: InvadersBus,
+    pub frame: u64,
+    //Records writes to the sound ports 3 and 5 when set
+    pub sound: Option<SoundRecorder>,
+    next_interrupt: u64,
+    next_rst: u8,
+}
+
+impl SpaceInvaders {
+    //rom is invaders.h, .g, .f and .e concatenated, 8 KiB in total
+    pub fn new(rom: &[u8]) -> Result<SpaceInvaders, String> {
+        if rom.len() > ROM_SIZE {
+            return Err(format!("ROM too large: {} bytes, expected {}", rom.len(), ROM_SIZE));
+        }
+        let mut state = State8080::new();
+        state.memory[..rom.len()].copy_from_slice(rom);
+        Ok(SpaceInvaders {
+            state,
+            bus: InvadersBus::new(),
+            frame: 0,
+            sound: None,
+            next_interrupt: HALF_FRAME,
+            next_rst: 1,
+        })
+    }
+
+    //RST 1 fires when the beam reaches the middle of the screen, RST 2 at vblank
+    fn interrupts(&mut self, runner: &mut Runner) {
+        while self.state.cycles >= self.next_interrupt {
+            runner.interrupt(&mut self.state, &mut self.bus, self.next_rst);
+            self.next_interrupt += HALF_FRAME;
+            if self.next_rst == 2 {
+                self.frame += 1;
+                self.next_rst = 1;
+            } else {
+                self.next_rst = 2;
+            }
+        }
+    }
+
+    //Runs until the current frame is complete or the runner stops
+    pub fn run_frame(&mut self, runner: &mut Runner) -> Option<StopReason> {
+        let frame = self.frame;
+        while self.frame == frame {
+            if let Some(reason) = self.step(runner) {
+                return Some(reason);
+            }
+        }
+        None
+    }
+
+    //Executes one instruction and raises the interrupts that became due.
+    //When the runner stops, that is left to the next step.
+    pub fn step(&mut self, runner: &mut Runner) -> Option<StopReason> {
+        if let Some(reason) = runner.step(&mut self.state, &mut self.bus) {
+            return Some(reason);
+        }
+        if let Some(sound) = self.sound.as_mut() {
+            sound.record(self.state.cycles, 3, self.bus.sound1);
+            sound.record(self.state.cycles, 5, self.bus.sound2);
+        }
+        self.interrupts(runner);
+        None
+    }
+}
+
+//Input ports, shift register, sound latches and the interrupt timing. The
+//DIP switches come from the command line.
+impl Device for SpaceInvaders {
+    fn tag(&self) -> [u8; 4] {
+        *b"INVD"
+    }
+
+    fn save(&self, out: &mut Writer) {
+        out.u8(self.bus.port1);
+        out.u8(self.bus.port2);
+        out.u16(self.bus.shift);
+        out.u8(self.bus.shift_offset);
+        out.u8(self.bus.sound1);
+        out.u8(self.bus.sound2);
+        out.u64(self.frame);
+        out.u64(self.next_interrupt);
+        out.u8(self.next_rst);
+    }
+
+    fn load(&mut self, data: &mut Reader) -> Result<(), String> {
+        self.bus.port1 = data.u8()?;
+        self.bus.port2 = data.u8()?;
+        self.bus.shift = data.u16()?;
+        self.bus.shift_offset = data.u8()? & 0x07;
+        self.bus.sound1 = data.u8()?;
+        self.bus.sound2 = data.u8()?;
+        self.frame = data.u64()?;
+        self.next_interrupt = data.u64()?;
+        self.next_rst = data.u8()?;
+        Ok(())
+    }
+}
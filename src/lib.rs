@@ -0,0 +1,75 @@
+//! Intel 8080 emulator library.
+//!
+//! The stable API is the cpu core ([`State8080`], [`Bus`], [`emulate_8080_op`]),
+//! the `Runner` that steps it until a `StopCondition` is met, the
+//! `disassembler` and the program image `loader`. The remaining modules hold
+//! the machines, debugger and tools the `i8080-emu` binary is built from and
+//! may change in any release.
+//!
+//! Everything but the cpu core needs the default `std` feature. Without it the
+//! crate is `#![no_std]` and never allocates, memory and I/O come from the
+//! embedder through [`Bus`].
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod i8080cpu;
+
+#[cfg(feature = "std")]
+pub mod breakpoints;
+#[cfg(feature = "std")]
+pub mod disassembler;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod runner;
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod altair;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod audio;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod console;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod cpm;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod cpm22;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod debugger;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod difftest;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod disk;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod gdbstub;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod invaders;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod movie;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod savestate;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod sst;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod video;
+
+pub use i8080cpu::{emulate_8080_op, generate_interrupt, Bus, ConditionCodes, NullBus, State8080};
+#[cfg(feature = "std")]
+pub use disassembler::format_8080_op;
+#[cfg(feature = "std")]
+pub use runner::{Runner, StopCondition, StopReason};
@@ -0,0 +1,22 @@
+use std::fs;
+use std::path::Path;
+
+use crate::i8080cpu::State8080;
+
+//Copies image into memory at addr and points pc at it
+pub fn load_image(state: &mut State8080, image: &[u8], addr: u16) -> Result<(), String> {
+    let start = addr as usize;
+    if start + image.len() > state.memory.len() {
+        return Err(format!("Image does not fit into memory at {:04x}", addr));
+    }
+    state.memory[start..start + image.len()].copy_from_slice(image);
+    state.pc = addr;
+    Ok(())
+}
+
+//Reads a program image from path and loads it like load_image, returns its length
+pub fn load_file(state: &mut State8080, path: &Path, addr: u16) -> Result<usize, String> {
+    let image = fs::read(path).map_err(|e| format!("Error loading file '{}': {}", path.display(), e))?;
+    load_image(state, &image, addr)?;
+    Ok(image.len())
+}
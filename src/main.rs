@@ -2,53 +2,865 @@ use std::io;
 use std::env;
 use std::io::prelude::*;
 use std::fs::File;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-mod disassembler;
-mod i8080cpu;
+use i8080_emu::{altair, audio, console, cpm, cpm22, debugger, difftest, disassembler, disk,
+                gdbstub, history, i8080cpu, invaders, loader, movie, runner, savestate, sst, video};
+
+use i8080_emu::runner::StopCondition;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() == 1 {
         usage();
         return
-    } else if args.len() != 3 {
+    } else if args.len() < 3 {
         usage();
         std::process::exit(1);
     }
 
+    if args[1] == "sst" && args.len() == 3 {
+        return single_step_tests(PathBuf::from(&args[2]).as_path());
+    }
+    if args[1] == "difftest" {
+        return differential_test(&args[2..]);
+    }
+
+    let file = &args[args.len() - 1];
     let mut buffer = Vec::new();
-    match read_file_to_buf(&args[2], &mut buffer) {
-        Ok(()) => println!("Successfully loaded file: {}", args[2]),
+    match read_file_to_buf(file, &mut buffer) {
+        Ok(()) => println!("Successfully loaded file: {}", file),
         Err(i) => {
-            panic!("Error loading file '{}': {}", args[2], i);
+            panic!("Error loading file '{}': {}", file, i);
         }
     }
 
-    if args[1] == "hexdump" {
+    if args[1] == "hexdump" && args.len() == 3 {
         disassembler::hexdump(buffer);
-    } else if args[1] == "disassemble" {
+    } else if args[1] == "disassemble" && args.len() == 3 {
         let length = buffer.len();
         let mut i:usize = 0;
         while i < length {
             i += disassembler::disassemble_8080_op(&buffer, i);
         }
-    } else if args[1] == "emulate" {
-        let mut state = i8080cpu::State8080::new();
+    } else if args[1] == "emulate" || args[1] == "debug" {
+        let options = match EmulateOptions::parse(&args[2..args.len() - 1]) {
+            Ok(options) => options,
+            Err(e) => {
+                println!("{}\n", e);
+                usage();
+                std::process::exit(1);
+            }
+        };
+        if args[1] == "debug" {
+            debug(options, &buffer);
+        } else {
+            emulate(options, file, &buffer);
+        }
+    } else {
+        println!("Unknown command!\n");
+        usage();
+        std::process::exit(1);
+    }
+}
+
+struct EmulateOptions {
+    cpm: bool,
+    dir: PathBuf,
+    command_tail: String,
+    gdb: Option<u16>,
+    history: Option<usize>,
+    load_state: Option<PathBuf>,
+    save_state: Option<PathBuf>,
+    record: Option<PathBuf>,
+    replay: Option<PathBuf>,
+    machine: Option<String>,
+    inputs: Vec<ScriptedInput>,
+    frames: FrameOptions,
+    sound_log: Option<PathBuf>,
+    wav: Option<PathBuf>,
+    samples: HashMap<(u8, u8), audio::Sample>,
+    serial: altair::SerialCard,
+    sense_switches: u8,
+    rom: bool,
+    disks: Vec<(PathBuf, bool)>,
+    controller: String,
+    cpm_base: Option<u16>,
+    load: u16,
+    pc: Option<u16>,
+    sp: Option<u16>,
+    registers: Vec<(String, u16)>,
+    conditions: Vec<StopCondition>,
+}
+
+impl EmulateOptions {
+    fn parse(args: &[String]) -> Result<EmulateOptions, String> {
+        let mut options = EmulateOptions {
+            cpm: false,
+            dir: PathBuf::from("."),
+            command_tail: String::new(),
+            gdb: None,
+            history: None,
+            load_state: None,
+            save_state: None,
+            record: None,
+            replay: None,
+            machine: None,
+            inputs: Vec::new(),
+            frames: FrameOptions {
+                dir: None,
+                every: 0,
+                at: Vec::new(),
+                format: "png".to_string(),
+                config: video::RenderConfig::invaders(),
+            },
+            sound_log: None,
+            wav: None,
+            samples: HashMap::new(),
+            serial: altair::SerialCard::TwoSio,
+            sense_switches: 0,
+            rom: false,
+            disks: Vec::new(),
+            controller: "dcdd".to_string(),
+            cpm_base: None,
+            load: 0,
+            pc: None,
+            sp: None,
+            registers: Vec::new(),
+            conditions: vec![StopCondition::Halt],
+        };
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "--cpm" {
+                options.cpm = true;
+                continue;
+            } else if arg == "--rom" {
+                options.rom = true;
+                continue;
+            }
+            let value = match iter.next() {
+                Some(value) => value,
+                None => return Err(format!("Missing value for '{}'", arg)),
+            };
+            match arg.as_str() {
+                "--machine" => match value.as_str() {
+                    "invaders" | "altair" | "cpm22" => options.machine = Some(value.clone()),
+                    _ => return Err(format!("Unknown machine '{}'", value)),
+                },
+                "--dir" => options.dir = PathBuf::from(value),
+                "--args" => options.command_tail = value.clone(),
+                "--gdb" => options.gdb = Some(parse_u16(value)?),
+                "--history" => options.history = Some(parse_u64(value)? as usize),
+                "--load-state" => options.load_state = Some(PathBuf::from(value)),
+                "--save-state" => options.save_state = Some(PathBuf::from(value)),
+                "--record" => options.record = Some(PathBuf::from(value)),
+                "--replay" => options.replay = Some(PathBuf::from(value)),
+                "--input" => options.inputs.push(ScriptedInput::parse(value)?),
+                "--frames" => {
+                    let cycles = parse_u64(value)?.checked_mul(invaders::CYCLES_PER_FRAME)
+                        .ok_or(format!("Too many frames '{}'", value))?;
+                    options.conditions.push(StopCondition::Cycles(cycles));
+                },
+                "--dump-frames" => options.frames.dir = Some(PathBuf::from(value)),
+                "--frame-every" => options.frames.every = parse_u64(value)?,
+                "--frame-at" => options.frames.at.push(parse_u64(value)?),
+                "--frame-format" => match value.as_str() {
+                    "png" | "ppm" => options.frames.format = value.clone(),
+                    _ => return Err(format!("Unknown frame format '{}'", value)),
+                },
+                "--scale" => options.frames.config.scale = parse_u64(value)?.max(1) as usize,
+                "--rotate" => options.frames.config.rotation = match value.as_str() {
+                    "0" => video::Rotation::None,
+                    "90" => video::Rotation::Cw,
+                    "180" => video::Rotation::Half,
+                    "270" => video::Rotation::Ccw,
+                    _ => return Err(format!("Rotation must be 0, 90, 180 or 270, got '{}'", value)),
+                },
+                "--overlay" => match value.as_str() {
+                    "on" => options.frames.config.overlays = video::RenderConfig::invaders().overlays,
+                    "off" => options.frames.config.overlays.clear(),
+                    _ => return Err(format!("Expected on or off, got '{}'", value)),
+                },
+                "--sound-log" => options.sound_log = Some(PathBuf::from(value)),
+                "--wav" => options.wav = Some(PathBuf::from(value)),
+                "--sample" | "--loop-sample" => {
+                    let (key, file) = parse_sample(value)?;
+                    let sample = audio::Sample::load(&PathBuf::from(file), arg == "--loop-sample")
+                        .map_err(|e| format!("Error loading sample '{}': {}", file, e))?;
+                    options.samples.insert(key, sample);
+                },
+                "--serial" => options.serial = match value.as_str() {
+                    "sio" => altair::SerialCard::Sio,
+                    "2sio" => altair::SerialCard::TwoSio,
+                    _ => return Err(format!("Unknown serial card '{}'", value)),
+                },
+                "--sense" => {
+                    let switches = parse_u16(value)?;
+                    if switches > 0xff {
+                        return Err(format!("Sense switches out of range: '{}'", value));
+                    }
+                    options.sense_switches = switches as u8;
+                },
+                "--disk" => options.disks.push((PathBuf::from(value), false)),
+                "--disk-ro" => options.disks.push((PathBuf::from(value), true)),
+                "--controller" => match value.as_str() {
+                    "dcdd" | "simple" => options.controller = value.clone(),
+                    _ => return Err(format!("Unknown disk controller '{}'", value)),
+                },
+                "--cpm-base" => options.cpm_base = Some(parse_u16(value)?),
+                "--load" => options.load = parse_u16(value)?,
+                "--pc" => options.pc = Some(parse_u16(value)?),
+                "--sp" => options.sp = Some(parse_u16(value)?),
+                "--reg" => {
+                    let (name, v) = value.split_once('=')
+                        .ok_or(format!("Expected <reg>=<value>, got '{}'", value))?;
+                    options.registers.push((name.to_lowercase(), parse_u16(v)?));
+                },
+                "--stop-at" => options.conditions.push(StopCondition::Address(parse_u16(value)?)),
+                "--max-instructions" => options.conditions.push(StopCondition::Instructions(parse_u64(value)?)),
+                "--max-cycles" => options.conditions.push(StopCondition::Cycles(parse_u64(value)?)),
+                "--stop-on-write" => options.conditions.push(StopCondition::MemoryWrite(parse_u16(value)?)),
+                "--stop-on-out" => {
+                    let port = parse_u16(value)?;
+                    if port > 0xff {
+                        return Err(format!("Port out of range: '{}'", value));
+                    }
+                    options.conditions.push(StopCondition::PortWrite(port as u8));
+                },
+                _ => return Err(format!("Unknown option '{}'", arg)),
+            }
+        }
+
+        if options.frames.dir.is_none() && (options.frames.every != 0 || !options.frames.at.is_empty()) {
+            return Err("--frame-every and --frame-at need --dump-frames".to_string());
+        }
+        if options.frames.dir.is_some() && options.frames.every == 0 && options.frames.at.is_empty() {
+            options.frames.every = 1;
+        }
+        if options.cpm && options.machine.is_some() {
+            return Err("--cpm and --machine are exclusive".to_string());
+        }
+        if options.record.is_some() && options.replay.is_some() {
+            return Err("--record and --replay are exclusive".to_string());
+        }
+
+        Ok(options)
+    }
+}
+
+//Which frames of a machine with video to save and where
+struct FrameOptions {
+    dir: Option<PathBuf>,
+    every: u64,
+    at: Vec<u64>,
+    format: String,
+    config: video::RenderConfig,
+}
 
-        //Load memory
-        for i in 0..buffer.len() {
-            state.memory[i] = buffer[i];
+impl FrameOptions {
+    fn dump(&self, memory: &[u8], frame: u64) {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let periodic = self.every != 0 && frame.is_multiple_of(self.every);
+        if !periodic && !self.at.contains(&frame) {
+            return;
+        }
+        let path = dir.join(format!("frame_{:06}.{}", frame, self.format));
+        if let Err(e) = video::render(memory, &self.config).save(&path) {
+            println!("Error writing frame '{}': {}", path.display(), e);
+            std::process::exit(1);
         }
+    }
+}
+
+//Machine input held down for a number of frames, given as <input>@<frame>[:<frames>]
+struct ScriptedInput {
+    input: invaders::Input,
+    frame: u64,
+    frames: u64,
+}
 
-        //Main Loop
-        loop {
-            i8080cpu::emulate_8080_op(&mut state);
+impl ScriptedInput {
+    fn parse(s: &str) -> Result<ScriptedInput, String> {
+        let (name, when) = s.split_once('@')
+            .ok_or(format!("Expected <input>@<frame>[:<frames>], got '{}'", s))?;
+        let (frame, frames) = match when.split_once(':') {
+            Some((frame, frames)) => (parse_u64(frame)?, parse_u64(frames)?),
+            None => (parse_u64(when)?, 5),
+        };
+        let input = match name {
+            "coin" => invaders::Input::Coin,
+            "p1start" => invaders::Input::P1Start,
+            "p2start" => invaders::Input::P2Start,
+            "p1fire" => invaders::Input::P1Fire,
+            "p1left" => invaders::Input::P1Left,
+            "p1right" => invaders::Input::P1Right,
+            "p2fire" => invaders::Input::P2Fire,
+            "p2left" => invaders::Input::P2Left,
+            "p2right" => invaders::Input::P2Right,
+            "tilt" => invaders::Input::Tilt,
+            _ => return Err(format!("Unknown input '{}'", name)),
+        };
+        Ok(ScriptedInput {
+            input,
+            frame,
+            frames,
+        })
+    }
+}
+
+//Runs single step test vectors and prints the results per opcode, exits with
+//an error when any case failed
+fn single_step_tests(path: &std::path::Path) {
+    let reports = match sst::run(path) {
+        Ok(reports) => reports,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut failed = 0;
+    for report in &reports {
+        match &report.failure {
+            None => println!("{}: {}/{} passed", report.name, report.passed, report.total),
+            Some(failure) => {
+                failed += 1;
+                println!("{}: {}/{} passed, first failure {}", report.name, report.passed, report.total, failure);
+            },
         }
+    }
+    let passed: usize = reports.iter().map(|r| r.passed).sum();
+    let total: usize = reports.iter().map(|r| r.total).sum();
+    println!("{}/{} cases passed, {} of {} opcodes failing", passed, total, failed, reports.len());
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+//Runs two cores in lock-step on a program, a save state or random
+//instruction streams and exits with an error at the first divergence
+fn differential_test(args: &[String]) {
+    let result = (|| -> Result<bool, String> {
+        let mut cores = ("reference".to_string(), "reference".to_string());
+        let mut steps = None;
+        let mut random = 0;
+        let mut seed = 1;
+        let mut load = 0;
+        let mut state_file = None;
+        let mut repro = None;
+        let mut file = None;
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if !arg.starts_with("--") {
+                file = Some(arg.clone());
+                continue;
+            }
+            let value = iter.next().ok_or(format!("{} needs a value", arg))?;
+            match arg.as_str() {
+                "--cores" => cores = match value.split_once(',') {
+                    Some((a, b)) => (a.to_string(), b.to_string()),
+                    None => return Err("--cores takes two names, like reference,reference".to_string()),
+                },
+                "--steps" => steps = Some(parse_u64(value)?),
+                "--random" => random = parse_u64(value)?,
+                "--seed" => seed = parse_u64(value)?,
+                "--load" => load = parse_u16(value)?,
+                "--state" => state_file = Some(PathBuf::from(value)),
+                "--repro" => repro = Some(PathBuf::from(value)),
+                _ => return Err(format!("Unknown difftest option {}", arg)),
+            }
+        }
+
+        //Start states with a description
+        let mut starts = Vec::new();
+        if let Some(path) = &state_file {
+            let mut state = i8080cpu::State8080::new();
+            savestate::load(path, &mut state, None)?;
+            starts.push((Box::new(state), path.display().to_string()));
+        }
+        if let Some(file) = &file {
+            let mut buffer = Vec::new();
+            read_file_to_buf(file, &mut buffer).map_err(|e| format!("Error loading file '{}': {}", file, e))?;
+            let start = load as usize;
+            if start + buffer.len() > 0x10000 {
+                return Err(format!("File does not fit into memory at {:04x}", start));
+            }
+            let mut state = Box::new(i8080cpu::State8080::new());
+            state.memory[start..start + buffer.len()].copy_from_slice(&buffer);
+            state.pc = load;
+            starts.push((state, file.clone()));
+        }
+        let mut rng = difftest::Rng::new(seed);
+        for case in 0..random {
+            starts.push((Box::new(difftest::random_state(&mut rng)), format!("random case {}", case + 1)));
+        }
+        if starts.is_empty() {
+            return Err("difftest needs a file, --state or --random".to_string());
+        }
+        let steps = steps.unwrap_or(if random > 0 { 10_000 } else { 100_000_000 });
+
+        let mut harness = difftest::Harness::new(&cores.0, &cores.1, seed)?;
+        for (start, name) in &starts {
+            let divergence = match harness.run(start, steps) {
+                Some(divergence) => divergence,
+                None => continue,
+            };
+            println!("{}", name);
+            difftest::report(&divergence, &harness.names);
+            let (minimal, steps) = harness.minimize(start, &divergence);
+            println!("Minimized reproducer, {} instruction(s) from:", steps);
+            difftest::print_state(&minimal);
+            if let Some(divergence) = harness.run(&minimal, steps) {
+                difftest::report(&divergence, &harness.names);
+            }
+            if let Some(path) = &repro {
+                savestate::save(path, &minimal, None)
+                    .map_err(|e| format!("Error writing '{}': {}", path.display(), e))?;
+                println!("Wrote the reproducer to {}, rerun it with difftest --state {} --steps {}",
+                         path.display(), path.display(), steps);
+            }
+            return Ok(false);
+        }
+        println!("{} and {} agree on {} start state(s), up to {} instructions each",
+                 harness.names.0, harness.names.1, starts.len(), steps);
+        Ok(true)
+    })();
+    match result {
+        Ok(true) => {},
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn emulate(mut options: EmulateOptions, file: &str, buffer: &[u8]) {
+    let machine = match options.machine.as_deref() {
+        Some(machine) => machine,
+        None if options.cpm => "cpm",
+        None => "bare",
+    };
+    let session = start_session(&options, machine, buffer);
+    match machine {
+        "invaders" => return emulate_invaders(options, buffer, session),
+        "altair" => return emulate_altair(options, buffer, session),
+        "cpm22" => return emulate_cpm22(options, file, session),
+        _ => {},
+    }
+
+    let mut state = i8080cpu::State8080::new();
+    let mut cpm = None;
+
+    //Load memory
+    if options.cpm {
+        let console = movie::MovieConsole::new(console::HostConsole::new(), session.as_ref());
+        cpm = Some(load_cpm(&mut state, &options, buffer, console));
     } else {
-        println!("Unknown command!\n");
-        usage();
+        load_bare(&mut state, &options, buffer);
+    }
+    match cpm.as_mut() {
+        Some(env) => load_state(&options, &mut state, Some(env)),
+        None => load_state(&options, &mut state, None),
+    }
+
+    apply_registers(&mut state, &options);
+
+    //Main Loop
+    if cpm.is_some() {
+        //A jump to 0x0000 is the program exiting
+        options.conditions.push(StopCondition::Address(0x0000));
+    }
+    let mut runner = session_runner(&mut options, session.as_ref(), &state);
+    let reason = match cpm.as_mut() {
+        Some(env) => {
+            let reason = env.run(&mut state, &mut i8080cpu::NullBus, &mut runner);
+            println!();
+            reason
+        },
+        None => runner.run(&mut state, &mut i8080cpu::NullBus),
+    };
+    let device = cpm.as_ref().map(|env| env as &dyn savestate::Device);
+    save_state(&options, &state, device);
+    finish_session(&options, session.as_ref(), &state, device);
+    println!("Stopped: {:?} after {} instructions, {} cycles", reason,
+             runner.instructions, runner.cycles);
+    print_registers(&state);
+}
+
+fn load_cpm<C: console::Console>(state: &mut i8080cpu::State8080, options: &EmulateOptions,
+                                 buffer: &[u8], console: C) -> cpm::Cpm<C> {
+    let env = cpm::Cpm::new(console, options.dir.clone());
+    let loaded = env.load(state, buffer)
+        .and_then(|()| env.set_command_tail(state, &options.command_tail));
+    if let Err(e) = loaded {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+    env
+}
+
+fn load_bare(state: &mut i8080cpu::State8080, options: &EmulateOptions, buffer: &[u8]) {
+    if let Err(e) = loader::load_image(state, buffer, options.load) {
+        println!("{}", e);
         std::process::exit(1);
     }
+    state.sp = 0;
+}
+
+//Runs the program under the interactive debugger. Plain images, CP/M
+//programs and the Space Invaders board are supported.
+fn debug(mut options: EmulateOptions, buffer: &[u8]) {
+    if options.record.is_some() || options.replay.is_some() {
+        println!("--record and --replay are not supported by the debugger");
+        std::process::exit(1);
+    }
+    let console = Rc::new(RefCell::new(console::HostConsole::new()));
+    let mut conditions = std::mem::take(&mut options.conditions);
+    match options.machine.as_deref() {
+        Some("invaders") => {
+            let machine = match invaders::SpaceInvaders::new(buffer) {
+                Ok(machine) => machine,
+                Err(e) => {
+                    println!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            run_debugger(machine, runner::Runner::new(conditions), console, &options);
+        },
+        Some(machine) => {
+            println!("The debugger does not support --machine {}", machine);
+            std::process::exit(1);
+        },
+        None if options.cpm => {
+            let mut state = i8080cpu::State8080::new();
+            let env = load_cpm(&mut state, &options, buffer, console.clone());
+            conditions.push(StopCondition::Address(0x0000));
+            let target = debugger::CpmProgram { state, env };
+            run_debugger(target, runner::Runner::new(conditions), console, &options);
+        },
+        None => {
+            let mut state = i8080cpu::State8080::new();
+            load_bare(&mut state, &options, buffer);
+            let target = debugger::Bare { state };
+            run_debugger(target, runner::Runner::new(conditions), console, &options);
+        },
+    }
+}
+
+//Applies --load-state, the registers and --history, then serves GDB on the
+//port if given or runs the interactive monitor
+fn run_debugger<T: debugger::Target>(mut target: T, mut runner: runner::Runner,
+                                     console: Rc<RefCell<console::HostConsole>>, options: &EmulateOptions) {
+    if let Some(path) = &options.load_state {
+        if let Err(e) = target.load_state(path) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    apply_registers(target.state(), options);
+    runner.set_history(options.history.map(history::History::new));
+    match options.gdb {
+        Some(port) => {
+            let mut server = gdbstub::GdbServer::new(target, runner);
+            if let Err(e) = server.listen(port) {
+                println!("GDB server: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => debugger::Debugger::new(target, runner, console).repl(),
+    }
+}
+
+fn emulate_invaders(mut options: EmulateOptions, buffer: &[u8], session: Option<movie::Session>) {
+    let mut machine = match invaders::SpaceInvaders::new(buffer) {
+        Ok(machine) => machine,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(path) = &options.load_state {
+        if let Err(e) = debugger::Target::load_state(&mut machine, path) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    apply_registers(&mut machine.state, &options);
+    if options.sound_log.is_some() || options.wav.is_some() {
+        machine.sound = Some(audio::SoundRecorder::new());
+    }
+
+    let mut runner = session_runner(&mut options, session.as_ref(), &machine.state);
+    let reason = loop {
+        for scripted in &options.inputs {
+            let held = machine.frame >= scripted.frame
+                && machine.frame < scripted.frame + scripted.frames;
+            machine.bus.set_input(scripted.input, held);
+        }
+        if let Some(session) = &session {
+            let ports = session.ports(machine.state.cycles, machine.bus.ports());
+            machine.bus.set_ports(ports);
+        }
+        if let Some(reason) = machine.run_frame(&mut runner) {
+            break reason;
+        }
+        options.frames.dump(&machine.state.memory, machine.frame);
+    };
+    if let Some(sound) = &machine.sound {
+        save_sound(&options, sound, machine.state.cycles);
+    }
+    save_state(&options, &machine.state, Some(&machine));
+    finish_session(&options, session.as_ref(), &machine.state, Some(&machine));
+    println!("Stopped: {:?} after {} frames, {} instructions, {} cycles", reason,
+             machine.frame, runner.instructions, runner.cycles);
+    print_registers(&machine.state);
+}
+
+fn emulate_altair(mut options: EmulateOptions, buffer: &[u8], session: Option<movie::Session>) {
+    let console = movie::MovieConsole::new(host_console(&session), session.as_ref());
+    let mut machine = altair::Altair::new(console, options.serial);
+    machine.bus.sense_switches = options.sense_switches;
+    if let Err(e) = attach_disks(&mut machine.bus, &options) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = machine.load(buffer, options.load, options.rom) {
+        println!("{}", e);
+        std::process::exit(1);
+    }
+    load_state(&options, &mut machine.state, Some(&mut machine.bus));
+    apply_registers(&mut machine.state, &options);
+
+    let mut runner = session_runner(&mut options, session.as_ref(), &machine.state);
+    let reason = machine.run(&mut runner);
+    //Leave raw mode before printing the summary
+    machine.bus.console.inner.set_raw(false);
+    println!("\r");
+    save_state(&options, &machine.state, Some(&machine.bus));
+    finish_session(&options, session.as_ref(), &machine.state, Some(&machine.bus));
+    match reason {
+        Some(reason) => println!("Stopped: {:?} after {} instructions, {} cycles", reason,
+                                 runner.instructions, runner.cycles),
+        None => println!("Quit after {} instructions, {} cycles", runner.instructions, runner.cycles),
+    }
+    print_registers(&machine.state);
+}
+
+fn emulate_cpm22(mut options: EmulateOptions, file: &str, session: Option<movie::Session>) {
+    let open = |path: &std::path::Path, read_only| {
+        disk::DiskImage::open(path, disk::Geometry::IBM_3740, read_only)
+            .map_err(|e| format!("Error opening disk image '{}': {}", path.display(), e))
+    };
+    let mut system = open(file.as_ref(), options.rom).and_then(|drive_a| {
+        let console = movie::MovieConsole::new(host_console(&session), session.as_ref());
+        cpm22::Cpm22::new(console, drive_a, options.cpm_base)
+    });
+    for (drive, (path, read_only)) in options.disks.iter().enumerate() {
+        system = system.and_then(|mut system| {
+            system.insert(drive + 1, open(path, *read_only)?)?;
+            Ok(system)
+        });
+    }
+    let mut system = match system {
+        Ok(system) => system,
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut state = i8080cpu::State8080::new();
+    system.boot(&mut state);
+    load_state(&options, &mut state, Some(&mut system));
+    apply_registers(&mut state, &options);
+
+    let mut runner = session_runner(&mut options, session.as_ref(), &state);
+    let reason = system.run(&mut state, &mut runner);
+    //Leave raw mode before printing the summary
+    system.console.inner.set_raw(false);
+    println!("\r");
+    save_state(&options, &state, Some(&system));
+    finish_session(&options, session.as_ref(), &state, Some(&system));
+    match reason {
+        Some(reason) => println!("Stopped: {:?} after {} instructions, {} cycles", reason,
+                                 runner.instructions, runner.cycles),
+        None => println!("Quit after {} instructions, {} cycles", runner.instructions, runner.cycles),
+    }
+    print_registers(&state);
+}
+
+//Loads the --replay movie or starts recording for --record
+fn start_session(options: &EmulateOptions, machine: &str, program: &[u8]) -> Option<movie::Session> {
+    if let Some(path) = &options.replay {
+        match movie::Session::replay(path, machine, program) {
+            Ok(session) => Some(session),
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        options.record.as_ref().map(|_| movie::Session::record(machine, program))
+    }
+}
+
+//Runner with the stop conditions of the options, driving the clock of the
+//session. A replay also stops once it ran past the end of the movie.
+fn session_runner(options: &mut EmulateOptions, session: Option<&movie::Session>,
+                  state: &i8080cpu::State8080) -> runner::Runner {
+    let mut conditions = std::mem::take(&mut options.conditions);
+    if let Some(session) = session {
+        if let (true, Some((end, _))) = (session.replay, session.movie.borrow().end) {
+            conditions.push(StopCondition::Cycles(end.saturating_sub(state.cycles) + 1));
+        }
+    }
+    let mut runner = runner::Runner::new(conditions);
+    runner.set_clock(session.map(|s| s.clock.clone()));
+    runner
+}
+
+//Writes the recorded movie, or exits with an error when the replay diverged
+fn finish_session(options: &EmulateOptions, session: Option<&movie::Session>, state: &i8080cpu::State8080,
+                  device: Option<&dyn savestate::Device>) {
+    let (session, path) = match (session, options.replay.as_ref().or(options.record.as_ref())) {
+        (Some(session), Some(path)) => (session, path),
+        _ => return,
+    };
+    match session.finish(path, state, device) {
+        Ok(summary) => println!("{}", summary),
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+//Raw terminal for the machine's console, a line buffered one when a movie
+//supplies the input
+fn host_console(session: &Option<movie::Session>) -> console::HostConsole {
+    match session {
+        Some(session) if session.replay => console::HostConsole::new(),
+        _ => console::HostConsole::raw(),
+    }
+}
+
+//Restores --load-state into the machine set up from the command line
+fn load_state(options: &EmulateOptions, state: &mut i8080cpu::State8080,
+              device: Option<&mut dyn savestate::Device>) {
+    if let Some(path) = &options.load_state {
+        if let Err(e) = savestate::load(path, state, device) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+//Writes --save-state when the emulation stops
+fn save_state(options: &EmulateOptions, state: &i8080cpu::State8080,
+              device: Option<&dyn savestate::Device>) {
+    if let Some(path) = &options.save_state {
+        match savestate::save(path, state, device) {
+            Ok(()) => println!("Saved state to {}", path.display()),
+            Err(e) => println!("Error writing save state '{}': {}", path.display(), e),
+        }
+    }
+}
+
+fn attach_disks<C: console::Console>(bus: &mut altair::AltairBus<C>,
+                                    options: &EmulateOptions) -> Result<(), String> {
+    if options.disks.is_empty() {
+        return Ok(());
+    }
+    let simple = options.controller == "simple";
+    let geometry = if simple { disk::Geometry::IBM_3740 } else { disk::Geometry::ALTAIR_8IN };
+    let mut dcdd = disk::Dcdd::new();
+    let mut simple_disk = disk::SimpleDisk::new();
+    for (drive, (path, read_only)) in options.disks.iter().enumerate() {
+        let image = disk::DiskImage::open(path, geometry, *read_only)
+            .map_err(|e| format!("Error opening disk image '{}': {}", path.display(), e))?;
+        if simple {
+            simple_disk.insert(drive, image)?;
+        } else {
+            dcdd.insert(drive, image)?;
+        }
+    }
+    if simple {
+        bus.simple_disk = Some(simple_disk);
+    } else {
+        bus.dcdd = Some(dcdd);
+    }
+    Ok(())
+}
+
+fn save_sound(options: &EmulateOptions, sound: &audio::SoundRecorder, cycles: u64) {
+    let result = options.sound_log.as_ref().map_or(Ok(()), |path| {
+        File::create(path).and_then(|f| sound.write_log(io::BufWriter::new(f)))
+    }).and_then(|()| options.wav.as_ref().map_or(Ok(()), |path| {
+        let track = audio::mix(&sound.events, &options.samples, invaders::CPU_HZ, cycles);
+        audio::save_wav(path, &track)
+    }));
+    if let Err(e) = result {
+        println!("Error writing sound: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn print_registers(state: &i8080cpu::State8080) {
+    println!("PC: {:04x} SP: {:04x} A: {:02x} F: {:02x} BC: {:02x}{:02x} DE: {:02x}{:02x} HL: {:02x}{:02x} INTE: {}",
+             state.pc, state.sp, state.a, state.cc.to_byte(), state.b, state.c, state.d, state.e,
+             state.h, state.l, state.int_enable as u8);
+}
+
+fn apply_registers(state: &mut i8080cpu::State8080, options: &EmulateOptions) {
+    if let Some(pc) = options.pc {
+        state.pc = pc;
+    }
+    if let Some(sp) = options.sp {
+        state.sp = sp;
+    }
+    for (name, value) in &options.registers {
+        if let Err(e) = debugger::set_register(state, name, *value) {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+//<port>:<bit>=<file>
+fn parse_sample(s: &str) -> Result<((u8, u8), &str), String> {
+    let err = || format!("Expected <port>:<bit>=<file>, got '{}'", s);
+    let (key, file) = s.split_once('=').ok_or_else(err)?;
+    let (port, bit) = key.split_once(':').ok_or_else(err)?;
+    let port = parse_u16(port)?;
+    let bit = parse_u16(bit)?;
+    if port > 0xff || bit > 7 {
+        return Err(err());
+    }
+    Ok(((port as u8, bit as u8), file))
+}
+
+fn parse_u64(s: &str) -> Result<u64, String> {
+    let parsed = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number '{}'", s))
+}
+
+fn parse_u16(s: &str) -> Result<u16, String> {
+    let value = parse_u64(s)?;
+    if value > 0xffff {
+        return Err(format!("Number out of range: '{}'", s));
+    }
+    Ok(value as u16)
 }
 
 fn read_file_to_buf(file: &str, buffer: &mut Vec<u8>) -> io::Result<()> {
@@ -60,8 +872,70 @@ fn read_file_to_buf(file: &str, buffer: &mut Vec<u8>) -> io::Result<()> {
 }
 
 fn usage() {
-    println!("USAGE: i8080-emu <command> <file>\n");
+    println!("USAGE: i8080-emu <command> [options] <file>\n");
     println!("COMMANDS:");
     println!("disassemble   disassemble file and output to stdout");
     println!("hexdump       hexdump file and output to stdout");
+    println!("emulate       run file on the emulated cpu");
+    println!("debug         run file under the interactive debugger, takes the emulate");
+    println!("              options except --machine altair and cpm22");
+    println!("sst           run single step test vectors, file is a JSON file or a directory");
+    println!("difftest      run two cpu cores in lock-step and report the first divergence\n");
+    println!("EMULATE OPTIONS:");
+    println!("--gdb <port>               debug: serve the GDB remote protocol on 127.0.0.1:port");
+    println!("--history <n>              debug: record the last n instructions for reverse execution");
+    println!("--load-state <file>        restore a save state after setting up the machine");
+    println!("--save-state <file>        write a save state when the emulation stops");
+    println!("--record <movie>           record console and machine input with cycle stamps");
+    println!("--replay <movie>           replay a movie and verify the final state checksum");
+    println!("--cpm                      run a CP/M .COM file with console and file BDOS calls");
+    println!("--dir <dir>                host directory holding the CP/M files (default .)");
+    println!("--args <args>              command line passed to the CP/M program");
+    println!("--machine invaders         run an 8 KiB Space Invaders ROM on the arcade board");
+    println!("--machine altair           run file on an Altair 8800 with 64 KiB RAM, Ctrl-] quits");
+    println!("--machine cpm22            boot CP/M 2.2 from the IBM 3740 image in file as drive A:,");
+    println!("                           --disk attaches B: to D:, Ctrl-] quits");
+    println!("--cpm-base <addr>          CCP address the system was built for (default: detect)");
+    println!("--serial <sio|2sio>        Altair serial card on the console (default 2sio)");
+    println!("--sense <value>            Altair front panel sense switches, read from port 0xff");
+    println!("--rom                      make the loaded file read only");
+    println!("--disk <image>             attach a disk image to the next Altair drive");
+    println!("--disk-ro <image>          same, but write protected");
+    println!("--controller <dcdd|simple> disk controller: 88-DCDD with 137 byte sectors on ports");
+    println!("                           0x08-0x0a (default) or a simple controller for IBM 3740");
+    println!("                           images on ports 0x20-0x25");
+    println!("--frames <n>               stop after n 60 Hz frames (2 MHz clock)");
+    println!("--input <in>@<frame>[:<n>] hold a machine input for n frames (default 5)");
+    println!("                           coin, p1start, p2start, p1fire, p1left, p1right,");
+    println!("                           p2fire, p2left, p2right, tilt");
+    println!("--dump-frames <dir>        save video frames of the machine to dir");
+    println!("--frame-every <n>          save every nth frame (default 1)");
+    println!("--frame-at <frame>         save this frame, can be given multiple times");
+    println!("--frame-format <png|ppm>   image format of saved frames (default png)");
+    println!("--scale <n>                scale saved frames by n");
+    println!("--rotate <0|90|180|270>    monitor rotation (default 270)");
+    println!("--overlay <on|off>         color overlay (default on)");
+    println!("--sound-log <file>         write sound port changes with their cycle to file");
+    println!("--wav <file>               mix the samples for the sound port bits into file");
+    println!("--sample <port>:<bit>=<wav>       sample played when the bit is set");
+    println!("--loop-sample <port>:<bit>=<wav>  sample repeated while the bit is set");
+    println!("--load <addr>              address the file is loaded to (default 0)");
+    println!("--pc <addr>                entry point (default: load address)");
+    println!("--sp <addr>                initial stack pointer (default 0)");
+    println!("--reg <reg>=<value>        initial register value (a-l, bc, de, hl, flags)");
+    println!("--stop-at <addr>           stop when pc reaches addr");
+    println!("--max-instructions <n>     stop after n instructions");
+    println!("--max-cycles <n>           stop after n cycles");
+    println!("--stop-on-write <addr>     stop after a write to addr");
+    println!("--stop-on-out <port>       stop after an OUT to port");
+    println!("The emulator always stops on HLT. Numbers are decimal or 0x prefixed hex.\n");
+    println!("DIFFTEST OPTIONS (file is optional):");
+    println!("--cores <a>,<b>            cores to compare (default reference,reference)");
+    println!("--random <n>               also run n random memory and register states");
+    println!("--steps <n>                instructions per start state (default 10000 with");
+    println!("                           --random, 100000000 otherwise), HLT ends a run early");
+    println!("--seed <n>                 seed of the random states and port inputs (default 1)");
+    println!("--load <addr>              address file is loaded to and run from (default 0)");
+    println!("--state <file>             start from a save state, like a written reproducer");
+    println!("--repro <file>             write the minimized reproducer as a save state");
 }
@@ -0,0 +1,310 @@
+//Movies record everything that reaches a machine from outside, stamped with
+//the cycle count, so a run can be replayed exactly: console characters and
+//Space Invaders input ports. Interrupts are raised from the cycle count on
+//every machine and need no recording. Host files of --cpm programs and disk
+//images are not part of a movie and have to be the same when replaying.
+//
+//Movies are text files, one item per line, numbers hex:
+//  i8080-movie 1
+//  machine <invaders|altair|cpm22|cpm|bare>
+//  program <FNV-1a 64 hash of the program or disk image file>
+//  <cycle> key <byte>             console character, available from cycle on
+//  <cycle> eof                    console input closed
+//  <cycle> ports <port1> <port2>  Space Invaders input ports from cycle on
+//  end <cycle> <checksum>         final cycle count and FNV-1a 64 hash of the
+//                                 save state of the machine
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::console::Console;
+use crate::i8080cpu::State8080;
+use crate::savestate::{self, Device};
+
+pub const VERSION: u32 = 1;
+
+//Cycle count of the running machine, shared by the Runner with the devices
+pub type Clock = Rc<Cell<u64>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(u8),
+    Eof,
+    Ports(u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub cycle: u64,
+    pub input: Input,
+}
+
+pub struct Movie {
+    pub machine: String,
+    pub program: u64,
+    pub events: VecDeque<Event>,
+    //Final cycle count and checksum
+    pub end: Option<(u64, u64)>,
+}
+
+impl Movie {
+    pub fn new(machine: &str, program: u64) -> Movie {
+        Movie {
+            machine: machine.to_string(),
+            program,
+            events: VecDeque::new(),
+            end: None,
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Error reading movie '{}': {}", path.display(), e))?;
+        let mut lines = text.lines().enumerate();
+        match lines.next() {
+            Some((_, header)) if header == format!("i8080-movie {}", VERSION) => {},
+            _ => return Err(format!("'{}' is not a version {} movie", path.display(), VERSION)),
+        }
+        let mut movie = Movie::new("", 0);
+        for (n, line) in lines {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match words.as_slice() {
+                [] => Some(()),
+                ["machine", machine] => {
+                    movie.machine = machine.to_string();
+                    Some(())
+                },
+                ["program", hash] => u64::from_str_radix(hash, 16).ok().map(|hash| movie.program = hash),
+                ["end", cycle, checksum] => hex(cycle).zip(hex(checksum)).map(|end| movie.end = Some(end)),
+                [cycle, event @ ..] => hex(cycle).and_then(|cycle| {
+                    let input = match event {
+                        ["key", byte] => Input::Key(u8::from_str_radix(byte, 16).ok()?),
+                        ["eof"] => Input::Eof,
+                        ["ports", port1, port2] => Input::Ports(u8::from_str_radix(port1, 16).ok()?,
+                                                                u8::from_str_radix(port2, 16).ok()?),
+                        _ => return None,
+                    };
+                    movie.events.push_back(Event { cycle, input });
+                    Some(())
+                }),
+            };
+            if parsed.is_none() {
+                return Err(format!("{}:{}: invalid movie line '{}'", path.display(), n + 1, line));
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut text = format!("i8080-movie {}\nmachine {}\nprogram {:016x}\n", VERSION, self.machine, self.program);
+        for event in &self.events {
+            let line = match event.input {
+                Input::Key(byte) => format!("{:x} key {:02x}\n", event.cycle, byte),
+                Input::Eof => format!("{:x} eof\n", event.cycle),
+                Input::Ports(port1, port2) => format!("{:x} ports {:02x} {:02x}\n", event.cycle, port1, port2),
+            };
+            text.push_str(&line);
+        }
+        if let Some((cycle, checksum)) = self.end {
+            text.push_str(&format!("end {:x} {:016x}\n", cycle, checksum));
+        }
+        fs::write(path, text)
+    }
+}
+
+fn hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+//FNV-1a, 64 bit
+pub fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+pub fn checksum(state: &State8080, device: Option<&dyn Device>) -> u64 {
+    hash(&savestate::encode(state, device))
+}
+
+//A movie being recorded or replayed, shared by the consoles and the
+//machine's input code
+pub struct Session {
+    pub movie: Rc<RefCell<Movie>>,
+    pub clock: Clock,
+    pub replay: bool,
+    ports: Cell<Option<(u8, u8)>>,
+}
+
+impl Session {
+    pub fn record(machine: &str, program: &[u8]) -> Session {
+        Session {
+            movie: Rc::new(RefCell::new(Movie::new(machine, hash(program)))),
+            clock: Clock::default(),
+            replay: false,
+            ports: Cell::new(None),
+        }
+    }
+
+    //Loads the movie and checks it was made with the machine and program
+    pub fn replay(path: &Path, machine: &str, program: &[u8]) -> Result<Session, String> {
+        let movie = Movie::load(path)?;
+        if movie.machine != machine {
+            return Err(format!("Movie was recorded on machine {}", movie.machine));
+        }
+        if movie.program != hash(program) {
+            return Err("Movie was recorded with a different program".to_string());
+        }
+        Ok(Session {
+            movie: Rc::new(RefCell::new(movie)),
+            clock: Clock::default(),
+            replay: true,
+            ports: Cell::new(None),
+        })
+    }
+
+    //Records the input ports when they changed, or returns the ports the
+    //movie holds for cycle when replaying
+    pub fn ports(&self, cycle: u64, ports: (u8, u8)) -> (u8, u8) {
+        let mut movie = self.movie.borrow_mut();
+        if !self.replay {
+            if self.ports.get() != Some(ports) {
+                movie.events.push_back(Event { cycle, input: Input::Ports(ports.0, ports.1) });
+                self.ports.set(Some(ports));
+            }
+            return ports;
+        }
+        while let Some(Event { cycle: at, input: Input::Ports(port1, port2) }) = movie.events.front().copied() {
+            if at > cycle {
+                break;
+            }
+            self.ports.set(Some((port1, port2)));
+            movie.events.pop_front();
+        }
+        self.ports.get().unwrap_or(ports)
+    }
+
+    //Saves the recorded movie with the final state, or compares the final
+    //state with the movie. Returns a summary, an error when replay diverged.
+    pub fn finish(&self, path: &Path, state: &State8080, device: Option<&dyn Device>) -> Result<String, String> {
+        let end = (state.cycles, checksum(state, device));
+        let mut movie = self.movie.borrow_mut();
+        if !self.replay {
+            movie.end = Some(end);
+            movie.save(path).map_err(|e| format!("Error writing movie '{}': {}", path.display(), e))?;
+            return Ok(format!("Recorded movie to {}, checksum {:016x}", path.display(), end.1));
+        }
+        match movie.end {
+            Some(expected) if expected == end && movie.events.is_empty() => {
+                Ok(format!("Replay matches at cycle {}, checksum {:016x}", end.0, end.1))
+            },
+            Some((cycle, checksum)) => Err(format!(
+                "Replay diverged: expected cycle {} checksum {:016x}, got cycle {} checksum {:016x}, {} inputs left",
+                cycle, checksum, end.0, end.1, movie.events.len())),
+            None => Err("Movie has no end record".to_string()),
+        }
+    }
+}
+
+//Console that records the characters typed on inner, or types the ones of
+//a movie. Output always goes to inner. Without a session it passes
+//everything through.
+pub struct MovieConsole<C: Console> {
+    pub inner: C,
+    movie: Option<(Rc<RefCell<Movie>>, Clock, bool)>,
+    //Cycle at which status first saw the pending character
+    available: Option<u64>,
+    //Input was closed, recorded as a single eof
+    closed: bool,
+}
+
+impl<C: Console> MovieConsole<C> {
+    pub fn new(inner: C, session: Option<&Session>) -> MovieConsole<C> {
+        MovieConsole {
+            inner,
+            movie: session.map(|s| (s.movie.clone(), s.clock.clone(), s.replay)),
+            available: None,
+            closed: false,
+        }
+    }
+}
+
+impl<C: Console> Console for MovieConsole<C> {
+    fn write(&mut self, byte: u8) {
+        self.inner.write(byte)
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        let (movie, clock, replay) = match &self.movie {
+            Some(movie) => movie,
+            None => return self.inner.read(),
+        };
+        let mut movie = movie.borrow_mut();
+        if self.closed {
+            return None;
+        }
+        if *replay {
+            let i = movie.events.iter().position(|e| !matches!(e.input, Input::Ports(..)))?;
+            return match movie.events.remove(i)?.input {
+                Input::Key(byte) => Some(byte),
+                _ => {
+                    self.closed = true;
+                    None
+                },
+            };
+        }
+        let byte = self.inner.read();
+        let cycle = self.available.take().unwrap_or_else(|| clock.get());
+        movie.events.push_back(Event { cycle, input: byte.map_or(Input::Eof, Input::Key) });
+        self.closed = byte.is_none();
+        byte
+    }
+
+    fn status(&mut self) -> bool {
+        let (movie, clock, replay) = match &self.movie {
+            Some(movie) => movie,
+            None => return self.inner.status(),
+        };
+        if *replay {
+            let movie = movie.borrow();
+            return movie.events.iter()
+                .find(|e| !matches!(e.input, Input::Ports(..)))
+                .is_some_and(|e| matches!(e.input, Input::Key(_)) && e.cycle <= clock.get());
+        }
+        let status = self.inner.status();
+        if status && self.available.is_none() {
+            self.available = Some(clock.get());
+        }
+        status
+    }
+
+    fn closed(&mut self) -> bool {
+        let (movie, clock, replay) = match &self.movie {
+            Some(movie) => movie,
+            None => return self.inner.closed(),
+        };
+        if self.closed {
+            return true;
+        }
+        let mut movie = movie.borrow_mut();
+        if *replay {
+            let i = match movie.events.iter().position(|e| !matches!(e.input, Input::Ports(..))) {
+                Some(i) => i,
+                None => return false,
+            };
+            if movie.events[i].input == Input::Eof && movie.events[i].cycle <= clock.get() {
+                movie.events.remove(i);
+                self.closed = true;
+            }
+            return self.closed;
+        }
+        if self.inner.closed() {
+            movie.events.push_back(Event { cycle: clock.get(), input: Input::Eof });
+            self.closed = true;
+        }
+        self.closed
+    }
+}
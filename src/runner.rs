@@ -0,0 +1,309 @@
+use crate::breakpoints::{Access, AccessKind, Breakpoints};
+use crate::history::{History, Record, Registers, Write};
+use crate::i8080cpu::{self, Bus, State8080};
+use crate::movie::Clock;
+
+#[non_exhaustive]
+pub enum StopCondition {
+    Halt,
+    Address(u16),
+    Instructions(u64),
+    Cycles(u64),
+    MemoryWrite(u16),
+    PortWrite(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StopReason {
+    Halted,
+    Address(u16),
+    Instructions(u64),
+    Cycles(u64),
+    MemoryWrite { addr: u16, value: u8 },
+    PortWrite { port: u8, value: u8 },
+    Breakpoint(usize),
+    Watchpoint { id: usize, access: Access },
+}
+
+//Runs emulate_8080_op until one of the stop conditions is met or a
+//breakpoint hits. Addresses are checked after each instruction, so the entry
+//point itself never stops the run.
+pub struct Runner {
+    conditions: Vec<StopCondition>,
+    //Whether a condition stops on memory or port writes
+    watch_writes: bool,
+    //Summary of the conditions checked after each instruction, so the list
+    //is only walked when one of them may hold
+    stop_halt: bool,
+    stop_addresses: Vec<u16>,
+    stop_instructions: u64,
+    stop_cycles: u64,
+    breakpoints: Breakpoints,
+    //Executed instructions are recorded here when set, so they can be undone
+    history: Option<History>,
+    //Updated with the cycle count around each instruction when set, for
+    //devices that stamp host input
+    clock: Option<Clock>,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl Runner {
+    pub fn new(conditions: Vec<StopCondition>) -> Runner {
+        let watch_writes = conditions.iter()
+            .any(|c| matches!(c, StopCondition::MemoryWrite(_) | StopCondition::PortWrite(_)));
+        let stop_halt = conditions.iter().any(|c| matches!(c, StopCondition::Halt));
+        let stop_addresses = conditions.iter()
+            .filter_map(|c| match c { StopCondition::Address(addr) => Some(*addr), _ => None })
+            .collect();
+        let stop_instructions = conditions.iter()
+            .filter_map(|c| match c { StopCondition::Instructions(n) => Some(*n), _ => None })
+            .min().unwrap_or(u64::MAX);
+        let stop_cycles = conditions.iter()
+            .filter_map(|c| match c { StopCondition::Cycles(n) => Some(*n), _ => None })
+            .min().unwrap_or(u64::MAX);
+        Runner {
+            conditions,
+            watch_writes,
+            stop_halt,
+            stop_addresses,
+            stop_instructions,
+            stop_cycles,
+            breakpoints: Breakpoints::new(),
+            history: None,
+            clock: None,
+            instructions: 0,
+            cycles: 0,
+        }
+    }
+
+    pub fn step<B: Bus + ?Sized>(&mut self, state: &mut State8080, bus: &mut B) -> Option<StopReason> {
+        if let Some(clock) = &self.clock {
+            clock.set(state.cycles);
+        }
+        //Only put the sentinel between cpu and bus when there is something
+        //to watch, it halves the instruction rate
+        let hit = if self.watch_writes || self.breakpoints.watching() || self.history.is_some() {
+            self.step_watched(state, bus)
+        } else {
+            self.cycles += i8080cpu::emulate_8080_op(state, bus) as u64;
+            self.instructions += 1;
+            None
+        };
+        if let Some(clock) = &self.clock {
+            clock.set(state.cycles);
+        }
+        if hit.is_some() {
+            return hit;
+        }
+
+        if !self.breakpoints.is_empty() {
+            if let Some(id) = self.breakpoints.check_exec(state) {
+                return Some(StopReason::Breakpoint(id));
+            }
+        }
+
+        if !(self.stop_halt && state.halted)
+            && self.instructions < self.stop_instructions
+            && self.cycles < self.stop_cycles
+            && !self.stop_addresses.contains(&state.pc) {
+            return None;
+        }
+        for condition in &self.conditions {
+            match *condition {
+                StopCondition::Halt if state.halted => return Some(StopReason::Halted),
+                StopCondition::Address(addr) if state.pc == addr => {
+                    return Some(StopReason::Address(addr))
+                }
+                StopCondition::Instructions(n) if self.instructions >= n => {
+                    return Some(StopReason::Instructions(self.instructions))
+                }
+                StopCondition::Cycles(n) if self.cycles >= n => {
+                    return Some(StopReason::Cycles(self.cycles))
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    //Executes one instruction through a Sentinel, records it in the history
+    //and returns the sentinel's stop reason or watchpoint hit
+    #[inline(never)]
+    fn step_watched<B: Bus + ?Sized>(&mut self, state: &mut State8080, bus: &mut B) -> Option<StopReason> {
+        let mut sentinel = Sentinel {
+            inner: bus,
+            conditions: &self.conditions,
+            breakpoints: if self.breakpoints.watching() { Some(&self.breakpoints) } else { None },
+            hit: None,
+            accesses: Vec::new(),
+            history: self.history.as_mut(),
+        };
+        let before = sentinel.history.as_ref().map(|_| Registers::save(state));
+        self.cycles += i8080cpu::emulate_8080_op(state, &mut sentinel) as u64;
+        self.instructions += 1;
+        if let (Some(history), Some(before)) = (sentinel.history.as_mut(), before) {
+            history.push(self.instructions, before);
+        }
+        if sentinel.hit.is_some() {
+            return sentinel.hit;
+        }
+
+        if !self.breakpoints.is_empty() {
+            for access in &sentinel.accesses {
+                if let Some(id) = self.breakpoints.check_access(state, access) {
+                    return Some(StopReason::Watchpoint { id, access: *access });
+                }
+            }
+        }
+        None
+    }
+
+    //Raises RST num if interrupts are enabled. Its cycles count towards
+    //the Cycles condition like those of instructions. Returns whether the
+    //interrupt was taken.
+    pub fn interrupt<B: Bus + ?Sized>(&mut self, state: &mut State8080, bus: &mut B, num: u8) -> bool {
+        let before = state.cycles;
+        let taken = i8080cpu::generate_interrupt(state, bus, num);
+        self.cycles += state.cycles - before;
+        taken
+    }
+
+    //Breakpoints, history and clock are for the debugger and the machines of
+    //the binary, their types are not part of the stable API
+    #[doc(hidden)]
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    #[doc(hidden)]
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    #[doc(hidden)]
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    #[doc(hidden)]
+    pub fn history_mut(&mut self) -> Option<&mut History> {
+        self.history.as_mut()
+    }
+
+    //Starts recording into history, or stops with None
+    #[doc(hidden)]
+    pub fn set_history(&mut self, history: Option<History>) {
+        self.history = history;
+    }
+
+    #[doc(hidden)]
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.clock = clock;
+    }
+
+    //Undoes the last recorded instruction
+    pub fn reverse_step(&mut self, state: &mut State8080) -> Option<Record> {
+        let after = state.cycles;
+        let record = self.history.as_mut()?.undo(state)?;
+        self.instructions -= 1;
+        self.cycles = self.cycles.saturating_sub(after.saturating_sub(state.cycles));
+        Some(record)
+    }
+
+    //Undoes instructions until one wrote to a watched address or pc reaches
+    //a breakpoint. Read and port watchpoints don't apply, hit counts are left
+    //alone. None when the history ran out first.
+    pub fn reverse_continue(&mut self, state: &mut State8080) -> Option<StopReason> {
+        while self.reverse_step(state).is_some() {
+            let history = self.history.as_ref().unwrap();
+            for write in history.undone() {
+                let access = Access { kind: AccessKind::Write, addr: write.addr, value: write.value };
+                if let Some(id) = self.breakpoints.find_access(state, &access) {
+                    return Some(StopReason::Watchpoint { id, access });
+                }
+            }
+            if let Some(id) = self.breakpoints.find_exec(state) {
+                return Some(StopReason::Breakpoint(id));
+            }
+        }
+        None
+    }
+
+    pub fn run<B: Bus + ?Sized>(&mut self, state: &mut State8080, bus: &mut B) -> StopReason {
+        loop {
+            if let Some(reason) = self.step(state, bus) {
+                return reason;
+            }
+        }
+    }
+}
+
+//Passes everything through to the real bus, remembers the first write that
+//hits a sentinel address or port and collects watched accesses
+struct Sentinel<'a, B: Bus + ?Sized> {
+    inner: &'a mut B,
+    conditions: &'a [StopCondition],
+    //Only set while there are watchpoints
+    breakpoints: Option<&'a Breakpoints>,
+    hit: Option<StopReason>,
+    accesses: Vec<Access>,
+    //Gets the memory writes with the replaced values while recording.
+    //Writes the bus drops are left out.
+    history: Option<&'a mut History>,
+}
+
+impl<B: Bus + ?Sized> Sentinel<'_, B> {
+    fn watch(&mut self, kind: AccessKind, addr: u16, value: u8) {
+        if let Some(breakpoints) = self.breakpoints {
+            let access = Access { kind, addr, value };
+            if breakpoints.watches(&access) {
+                self.accesses.push(access);
+            }
+        }
+    }
+}
+
+impl<B: Bus + ?Sized> Bus for Sentinel<'_, B> {
+    fn read(&mut self, memory: &[u8], addr: u16) -> u8 {
+        let value = self.inner.read(memory, addr);
+        self.watch(AccessKind::Read, addr, value);
+        value
+    }
+
+    fn fetch(&mut self, memory: &[u8], addr: u16) -> u8 {
+        self.inner.fetch(memory, addr)
+    }
+
+    fn write(&mut self, memory: &mut [u8], addr: u16, value: u8) {
+        if let (Some(history), Some(target)) = (self.history.as_mut(), self.inner.write_target(addr)) {
+            history.write(Write { addr, target, old: memory[target as usize], value });
+        }
+        self.inner.write(memory, addr, value);
+        self.watch(AccessKind::Write, addr, value);
+        if self.hit.is_none() && self.conditions.iter()
+            .any(|c| matches!(*c, StopCondition::MemoryWrite(a) if a == addr)) {
+            self.hit = Some(StopReason::MemoryWrite { addr, value });
+        }
+    }
+
+    fn write_target(&self, addr: u16) -> Option<u16> {
+        self.inner.write_target(addr)
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        let value = self.inner.input(port);
+        self.watch(AccessKind::In, port as u16, value);
+        value
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.inner.output(port, value);
+        self.watch(AccessKind::Out, port as u16, value);
+        if self.hit.is_none() && self.conditions.iter()
+            .any(|c| matches!(*c, StopCondition::PortWrite(p) if p == port)) {
+            self.hit = Some(StopReason::PortWrite { port, value });
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//Save state files hold the CPU, all 64 KiB of memory and the state of the
+//machine's devices. All numbers are little endian.
+//
+//  offset  size  contents
+//  0       8     magic "I8080SAV"
+//  8       2     format version, currently 1
+//  10            chunks until the end of the file, each a 4 byte tag, a 4
+//                byte data length and the data
+//
+//Chunks:
+//  "CPU "  A B C D E H L F (flags as in PSW), SP, PC, interrupt enable (0/1),
+//          halted (0/1), cycle count (8 bytes); 22 bytes
+//  "MEM "  65536 bytes of memory
+//  device  the machine's device state, tagged "INVD" (Space Invaders), "CPM "
+//          (CP/M program), "ALTR" (Altair 8800) or "CP22" (CP/M 2.2 BIOS),
+//          see the Device implementations. At most one per file.
+//
+//Readers skip chunks they don't know. Disk images and host files are not
+//part of a save state, they have to be attached again when loading.
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use crate::history::Registers;
+use crate::i8080cpu::{ConditionCodes, State8080};
+
+pub const MAGIC: &[u8; 8] = b"I8080SAV";
+pub const VERSION: u16 = 1;
+
+//Device state of a machine that goes into save states
+pub trait Device {
+    fn tag(&self) -> [u8; 4];
+    fn save(&self, out: &mut Writer);
+    fn load(&mut self, data: &mut Reader) -> Result<(), String>;
+}
+
+pub fn save(path: &Path, state: &State8080, device: Option<&dyn Device>) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&encode(state, device))?;
+    file.flush()
+}
+
+//The save state file contents
+pub fn encode(state: &State8080, device: Option<&dyn Device>) -> Vec<u8> {
+    let mut cpu = Writer::new();
+    for r in [state.a, state.b, state.c, state.d, state.e, state.h, state.l, state.cc.to_byte()].iter() {
+        cpu.u8(*r);
+    }
+    cpu.u16(state.sp);
+    cpu.u16(state.pc);
+    cpu.bool(state.int_enable);
+    cpu.bool(state.halted);
+    cpu.u64(state.cycles);
+
+    let mut out = MAGIC.to_vec();
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    write_chunk(&mut out, b"CPU ", &cpu.data);
+    write_chunk(&mut out, b"MEM ", &state.memory);
+    if let Some(device) = device {
+        let mut data = Writer::new();
+        device.save(&mut data);
+        write_chunk(&mut out, &device.tag(), &data.data);
+    }
+    out
+}
+
+fn write_chunk(out: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(tag);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+//A save state read from a file, restored in two steps so machines that own
+//both the CPU and the devices can be loaded
+pub struct SaveState {
+    pub registers: Registers,
+    pub memory: Vec<u8>,
+    pub device: Option<([u8; 4], Vec<u8>)>,
+}
+
+impl SaveState {
+    pub fn read(path: &Path) -> Result<SaveState, String> {
+        let mut buf = Vec::new();
+        File::open(path).and_then(|mut f| f.read_to_end(&mut buf))
+            .map_err(|e| format!("Error reading save state '{}': {}", path.display(), e))?;
+        if buf.len() < 10 || &buf[..8] != MAGIC {
+            return Err(format!("'{}' is not a save state", path.display()));
+        }
+        let version = u16::from_le_bytes([buf[8], buf[9]]);
+        if version != VERSION {
+            return Err(format!("Unsupported save state version {}", version));
+        }
+
+        let mut cpu = None;
+        let mut memory = None;
+        let mut device = None;
+        let mut reader = Reader::new(&buf[10..]);
+        while !reader.is_empty() {
+            let mut tag = [0; 4];
+            tag.copy_from_slice(reader.bytes(4)?);
+            let len = reader.u32()? as usize;
+            let data = reader.bytes(len)?;
+            match &tag {
+                b"CPU " => cpu = Some(data),
+                b"MEM " => memory = Some(data),
+                b"INVD" | b"CPM " | b"ALTR" | b"CP22" => device = Some((tag, data.to_vec())),
+                _ => {},
+            }
+        }
+
+        let mut cpu = Reader::new(cpu.ok_or("Save state has no CPU chunk")?);
+        let memory = memory.ok_or("Save state has no memory chunk")?;
+        if memory.len() != 0x10000 {
+            return Err("Save state memory has the wrong size".to_string());
+        }
+        let r = cpu.bytes(8)?;
+        let mut cc = ConditionCodes::new();
+        cc.set_byte(r[7]);
+        cc.pad = (cc.pad & !1) | ((r[7] >> 1) & 1);
+        let registers = Registers {
+            a: r[0],
+            b: r[1],
+            c: r[2],
+            d: r[3],
+            e: r[4],
+            h: r[5],
+            l: r[6],
+            cc,
+            sp: cpu.u16()?,
+            pc: cpu.u16()?,
+            int_enable: cpu.bool()?,
+            halted: cpu.bool()?,
+            cycles: cpu.u64()?,
+        };
+        Ok(SaveState { registers, memory: memory.to_vec(), device })
+    }
+
+    //The device chunk has to match the machine, a state without one only
+    //loads into machines without devices
+    pub fn load_device(&self, device: Option<&mut dyn Device>) -> Result<(), String> {
+        match (device, &self.device) {
+            (Some(device), Some((tag, data))) if *tag == device.tag() => device.load(&mut Reader::new(data)),
+            (None, None) => Ok(()),
+            _ => Err("Save state is for a different machine".to_string()),
+        }
+    }
+
+    pub fn load_cpu(&self, state: &mut State8080) {
+        self.registers.restore(state);
+        state.memory.copy_from_slice(&self.memory);
+    }
+}
+
+pub fn load(path: &Path, state: &mut State8080, device: Option<&mut dyn Device>) -> Result<(), String> {
+    let saved = SaveState::read(path)?;
+    saved.load_device(device)?;
+    saved.load_cpu(state);
+    Ok(())
+}
+
+pub struct Writer {
+    pub data: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer { data: Vec::new() }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < len {
+            return Err("Save state is truncated".to_string());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
@@ -0,0 +1,389 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::i8080cpu::{self, Bus, State8080};
+
+//Runs single instruction test vectors in the JSON format of the per-opcode
+//"single step tests": one file per opcode, named after it (3c.json), each an
+//array of cases
+//  {"name": "...",
+//   "initial": {"pc", "sp", "a", "b", "c", "d", "e", "f", "h", "l",
+//               "ram": [[addr, value], ...]},
+//   "final": {same fields},
+//   "cycles": [one entry per clock cycle],
+//   "ports": [[port, value, "r" or "w"], ...]}
+//"ports" is optional and holds the values IN reads and OUT has to write.
+//An optional "inte" (0/1) in initial and final covers the interrupt enable.
+//"cycles" is optional too. Only its length is checked against the cycle
+//count of the instruction, the bus activity of each cycle is not.
+
+//Cases that passed and the first failure of an opcode file
+pub struct Report {
+    pub name: String,
+    pub passed: usize,
+    pub total: usize,
+    pub failure: Option<String>,
+}
+
+//Runs path, a vector file or a directory of them. Returns the reports of
+//all files, sorted by name.
+pub fn run(path: &Path) -> Result<Vec<Report>, String> {
+    let mut files: Vec<PathBuf> = if path.is_dir() {
+        fs::read_dir(path)
+            .map_err(|e| format!("Error reading '{}': {}", path.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect()
+    } else {
+        vec![path.to_path_buf()]
+    };
+    files.sort();
+    files.iter().map(|file| run_file(file)).collect()
+}
+
+pub fn run_file(path: &Path) -> Result<Report, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Error reading '{}': {}", path.display(), e))?;
+    let cases = Json::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let cases = cases.array().ok_or(format!("{}: expected an array of tests", path.display()))?;
+    let mut report = Report {
+        name: path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned()),
+        passed: 0,
+        total: cases.len(),
+        failure: None,
+    };
+    for case in cases {
+        match run_case(case) {
+            Ok(()) => report.passed += 1,
+            Err(e) => if report.failure.is_none() {
+                let name = case.get("name").and_then(Json::str).unwrap_or("?");
+                report.failure = Some(format!("'{}': {}", name, e));
+            },
+        }
+    }
+    Ok(report)
+}
+
+//Serves IN from the expected port reads and collects OUT
+struct VectorBus {
+    reads: Vec<(u8, u8)>,
+    writes: Vec<(u8, u8)>,
+}
+
+impl Bus for VectorBus {
+    fn input(&mut self, port: u8) -> u8 {
+        match self.reads.iter().position(|(p, _)| *p == port) {
+            Some(i) => self.reads.remove(i).1,
+            None => 0xff,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.writes.push((port, value));
+    }
+}
+
+const REGISTERS: [&str; 10] = ["pc", "sp", "a", "b", "c", "d", "e", "f", "h", "l"];
+
+fn run_case(case: &Json) -> Result<(), String> {
+    let initial = case.get("initial").ok_or("no initial state")?;
+    let expected = case.get("final").ok_or("no final state")?;
+    let mut state = State8080::new();
+    for name in REGISTERS.iter() {
+        let value = initial.get(name).and_then(Json::number).ok_or(format!("no initial {}", name))?;
+        set(&mut state, name, value as u16);
+    }
+    if let Some(inte) = initial.get("inte").and_then(Json::number) {
+        state.int_enable = inte != 0.0;
+    }
+    for (addr, value) in ram(initial)? {
+        state.memory[addr as usize] = value;
+    }
+
+    let mut ports = Vec::new();
+    for entry in case.get("ports").and_then(Json::array).unwrap_or(&[]) {
+        let entry = entry.array().unwrap_or(&[]);
+        match (entry.first().and_then(Json::number), entry.get(1).and_then(Json::number),
+               entry.get(2).and_then(Json::str)) {
+            (Some(port), Some(value), Some(dir)) => ports.push((port as u8, value as u8, dir == "w")),
+            _ => return Err("invalid port entry".to_string()),
+        }
+    }
+    let mut bus = VectorBus {
+        reads: ports.iter().filter(|p| !p.2).map(|p| (p.0, p.1)).collect(),
+        writes: Vec::new(),
+    };
+
+    let cycles = i8080cpu::emulate_8080_op(&mut state, &mut bus);
+
+    let mut errors = Vec::new();
+    for name in REGISTERS.iter() {
+        let want = expected.get(name).and_then(Json::number).ok_or(format!("no final {}", name))? as u16;
+        let got = get(&state, name);
+        if got != want {
+            errors.push(format!("{}: expected {:02x}, got {:02x}", name, want, got));
+        }
+    }
+    if let Some(inte) = expected.get("inte").and_then(Json::number) {
+        if state.int_enable != (inte != 0.0) {
+            errors.push(format!("inte: expected {}, got {}", inte, state.int_enable as u8));
+        }
+    }
+    for (addr, want) in ram(expected)? {
+        let got = state.memory[addr as usize];
+        if got != want {
+            errors.push(format!("[{:04x}]: expected {:02x}, got {:02x}", addr, want, got));
+        }
+    }
+    let writes: Vec<(u8, u8)> = ports.iter().filter(|p| p.2).map(|p| (p.0, p.1)).collect();
+    if bus.writes != writes {
+        errors.push(format!("ports: expected writes {:02x?}, got {:02x?}", writes, bus.writes));
+    }
+    if let Some(want) = case.get("cycles").and_then(Json::array) {
+        if cycles as usize != want.len() {
+            errors.push(format!("cycles: expected {}, got {}", want.len(), cycles));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+fn ram(state: &Json) -> Result<Vec<(u16, u8)>, String> {
+    state.get("ram").and_then(Json::array).unwrap_or(&[]).iter().map(|entry| {
+        let entry = entry.array().unwrap_or(&[]);
+        match (entry.first().and_then(Json::number), entry.get(1).and_then(Json::number)) {
+            (Some(addr), Some(value)) => Ok((addr as u16, value as u8)),
+            _ => Err("invalid ram entry".to_string()),
+        }
+    }).collect()
+}
+
+fn set(state: &mut State8080, name: &str, value: u16) {
+    match name {
+        "pc" => state.pc = value,
+        "sp" => state.sp = value,
+        "a" => state.a = value as u8,
+        "b" => state.b = value as u8,
+        "c" => state.c = value as u8,
+        "d" => state.d = value as u8,
+        "e" => state.e = value as u8,
+        "f" => state.cc.set_byte(value as u8),
+        "h" => state.h = value as u8,
+        "l" => state.l = value as u8,
+        _ => unreachable!(),
+    }
+}
+
+fn get(state: &State8080, name: &str) -> u16 {
+    match name {
+        "pc" => state.pc,
+        "sp" => state.sp,
+        "a" => state.a as u16,
+        "b" => state.b as u16,
+        "c" => state.c as u16,
+        "d" => state.d as u16,
+        "e" => state.e as u16,
+        "f" => state.cc.to_byte() as u16,
+        "h" => state.h as u16,
+        "l" => state.l as u16,
+        _ => unreachable!(),
+    }
+}
+
+//Just enough JSON for test vectors
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = Parser { bytes: text.as_bytes(), pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    fn number(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            Json::Bool(b) => Some(*b as u8 as f64),
+            _ => None,
+        }
+    }
+
+    fn str(&self) -> Option<&str> {
+        match self {
+            Json::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> String {
+        let line = self.bytes[..self.pos.min(self.bytes.len())].iter().filter(|b| **b == b'\n').count() + 1;
+        format!("line {}: {}", line, what)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.bytes.get(self.pos) != Some(&byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        if self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("invalid value"))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.bytes.get(self.pos) {
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        },
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            },
+            Some(b'[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                self.skip_whitespace();
+                if self.bytes.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                loop {
+                    values.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.bytes.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(values));
+                        },
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            },
+            Some(b'"') => Ok(Json::Str(self.string()?)),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b) if *b == b'-' || b.is_ascii_digit() => {
+                let start = self.pos;
+                while self.bytes.get(self.pos).is_some_and(|b| b"+-.eE".contains(b) || b.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                let number = std::str::from_utf8(&self.bytes[start..self.pos]).ok()
+                    .and_then(|s| s.parse::<f64>().ok());
+                number.map(Json::Number).ok_or_else(|| self.error("invalid number"))
+            },
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut s = Vec::new();
+        loop {
+            match self.bytes.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return String::from_utf8(s).map_err(|_| self.error("invalid UTF-8"));
+                },
+                Some(b'\\') => {
+                    let escaped = match self.bytes.get(self.pos + 1) {
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        Some(b'r') => b'\r',
+                        Some(b'b') => 0x08,
+                        Some(b'f') => 0x0c,
+                        Some(b'u') => {
+                            //Vectors only use ASCII, other characters become '?'
+                            let code = self.bytes.get(self.pos + 2..self.pos + 6)
+                                .and_then(|hex| std::str::from_utf8(hex).ok())
+                                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                            self.pos += 4;
+                            code.filter(u8::is_ascii).unwrap_or(b'?')
+                        },
+                        Some(b) => *b,
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    s.push(escaped);
+                    self.pos += 2;
+                },
+                Some(b) => {
+                    s.push(*b);
+                    self.pos += 1;
+                },
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
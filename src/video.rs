@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    //Rotated 90 degrees clockwise
+    Cw,
+    Half,
+    //Rotated 90 degrees counterclockwise
+    Ccw,
+}
+
+//Colored area of the output image before scaling, like the gels glued onto
+//the monitors of early arcade cabinets
+#[derive(Debug, Clone, Copy)]
+pub struct Overlay {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    pub color: [u8; 3],
+}
+
+//Describes a 1bpp bitmap in memory: `height` rows of `width` pixels starting
+//at `base`, least significant bit first.
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub base: u16,
+    pub width: usize,
+    pub height: usize,
+    pub rotation: Rotation,
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+    pub overlays: Vec<Overlay>,
+    pub scale: usize,
+}
+
+impl RenderConfig {
+    //Video RAM at 0x2400 on a monitor that is rotated counterclockwise, with
+    //the red and green overlay strips of the upright cabinet
+    pub fn invaders() -> RenderConfig {
+        const RED: [u8; 3] = [0xff, 0x20, 0x20];
+        const GREEN: [u8; 3] = [0x20, 0xff, 0x20];
+        RenderConfig {
+            base: 0x2400,
+            width: 256,
+            height: 224,
+            rotation: Rotation::Ccw,
+            foreground: [0xff, 0xff, 0xff],
+            background: [0x00, 0x00, 0x00],
+            overlays: vec![
+                Overlay { x: 0, y: 32, width: 224, height: 32, color: RED },
+                Overlay { x: 0, y: 184, width: 224, height: 56, color: GREEN },
+                Overlay { x: 16, y: 240, width: 118, height: 16, color: GREEN },
+            ],
+            scale: 1,
+        }
+    }
+
+    //Size of the output image before scaling
+    fn rotated_size(&self) -> (usize, usize) {
+        match self.rotation {
+            Rotation::None | Rotation::Half => (self.width, self.height),
+            Rotation::Cw | Rotation::Ccw => (self.height, self.width),
+        }
+    }
+}
+
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    //RGB, 3 bytes per pixel, rows top to bottom
+    pub pixels: Vec<u8>,
+}
+
+pub fn render(memory: &[u8], config: &RenderConfig) -> Framebuffer {
+    let (width, height) = config.rotated_size();
+    let scale = config.scale.max(1);
+    let mut fb = Framebuffer {
+        width: width * scale,
+        height: height * scale,
+        pixels: vec![0; width * height * scale * scale * 3],
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            //Map the output pixel back to the bitmap in memory
+            let (mx, my) = match config.rotation {
+                Rotation::None => (x, y),
+                Rotation::Cw => (y, config.height - 1 - x),
+                Rotation::Half => (config.width - 1 - x, config.height - 1 - y),
+                Rotation::Ccw => (config.width - 1 - y, x),
+            };
+            let bit = my * config.width + mx;
+            let addr = (config.base as usize + bit / 8) & 0xffff;
+            let on = memory.get(addr).is_some_and(|byte| byte & (1 << (bit % 8)) != 0);
+
+            let color = if on {
+                config.overlays.iter()
+                    .find(|o| x >= o.x && x < o.x + o.width && y >= o.y && y < o.y + o.height)
+                    .map_or(config.foreground, |o| o.color)
+            } else {
+                config.background
+            };
+
+            for sy in 0..scale {
+                let row = (y * scale + sy) * fb.width;
+                for sx in 0..scale {
+                    let i = (row + x * scale + sx) * 3;
+                    fb.pixels[i..i + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+
+    fb
+}
+
+impl Framebuffer {
+    pub fn write_ppm<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        w.write_all(&self.pixels)?;
+        w.flush()
+    }
+
+    //Uncompressed PNG: the zlib stream only uses stored blocks, which keeps
+    //the encoder small and frames byte-for-byte reproducible
+    pub fn write_png<W: Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&(self.width as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(self.height as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]); //8 bit RGB, no interlacing
+        write_chunk(&mut w, b"IHDR", &ihdr)?;
+
+        let stride = self.width * 3;
+        let mut raw = Vec::with_capacity((stride + 1) * self.height);
+        for row in self.pixels.chunks(stride) {
+            raw.push(0); //filter type none
+            raw.extend_from_slice(row);
+        }
+
+        let mut zlib = vec![0x78, 0x01];
+        let mut blocks = raw.chunks(0xffff).peekable();
+        if blocks.peek().is_none() {
+            zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            zlib.push(blocks.peek().is_none() as u8);
+            zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+            zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+            zlib.extend_from_slice(block);
+        }
+        zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+        write_chunk(&mut w, b"IDAT", &zlib)?;
+
+        write_chunk(&mut w, b"IEND", &[])?;
+        w.flush()
+    }
+
+    //Writes a PNG or PPM file depending on the extension of path
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let w = BufWriter::new(File::create(path)?);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ppm") => self.write_ppm(w),
+            _ => self.write_png(w),
+        }
+    }
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = crc32(crc32(0, kind), data);
+    w.write_all(&crc.to_be_bytes())
+}
+
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,91 @@
+use i8080_emu::{emulate_8080_op, format_8080_op, loader, Bus, Runner, State8080, StopCondition, StopReason};
+
+//Sums the bytes read from port 1 and writes the result to port 2
+struct Adder {
+    inputs: Vec<u8>,
+    outputs: Vec<u8>,
+}
+
+impl Bus for Adder {
+    fn input(&mut self, port: u8) -> u8 {
+        assert_eq!(port, 1);
+        self.inputs.pop().unwrap_or(0)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        assert_eq!(port, 2);
+        self.outputs.push(value);
+    }
+}
+
+#[test]
+fn run_program_on_a_custom_bus() {
+    let program = [
+        0x06, 0x03, //0100 MVI B,3
+        0xaf, //0102 XRA A
+        0x4f, //0103 LOOP: MOV C,A
+        0xdb, 0x01, //0104 IN 1
+        0x81, //0106 ADD C
+        0x05, //0107 DCR B
+        0xc2, 0x03, 0x01, //0108 JNZ LOOP
+        0xd3, 0x02, //010b OUT 2
+        0x76, //010d HLT
+    ];
+    let mut state = State8080::new();
+    loader::load_image(&mut state, &program, 0x0100).unwrap();
+    let mut bus = Adder { inputs: vec![1, 2, 3], outputs: Vec::new() };
+    let mut runner = Runner::new(vec![StopCondition::Halt, StopCondition::Cycles(10_000)]);
+
+    assert_eq!(runner.run(&mut state, &mut bus), StopReason::Halted);
+    assert_eq!(bus.outputs, vec![6]);
+    assert_eq!(state.pc, 0x010e);
+    assert_eq!(runner.cycles, state.cycles);
+}
+
+#[test]
+fn step_and_disassemble() {
+    let mut state = State8080::new();
+    loader::load_image(&mut state, &[0x21, 0x34, 0x12, 0x23], 0).unwrap();
+    assert_eq!(i8080_emu::format_8080_op(&state.memory, 0), ("LXI    H,#$1234".to_string(), 3));
+
+    assert_eq!(i8080_emu::emulate_8080_op(&mut state, &mut i8080_emu::NullBus), 10);
+    assert_eq!(i8080_emu::emulate_8080_op(&mut state, &mut i8080_emu::NullBus), 5);
+    assert_eq!(state.hl(), 0x1235);
+    assert_eq!(state.pc, 4);
+}
+
+#[test]
+fn image_past_the_end_of_memory() {
+    let mut state = State8080::new();
+    assert!(loader::load_image(&mut state, &[0; 2], 0xffff).is_err());
+    assert!(loader::load_image(&mut state, &[0x76], 0xffff).is_ok());
+}
+
+//Memory of its own that counts the bytes fetched from it
+struct Fetches {
+    memory: [u8; 4],
+    fetched: Vec<u16>,
+}
+
+impl Bus for Fetches {
+    fn fetch(&mut self, _memory: &[u8], addr: u16) -> u8 {
+        self.fetched.push(addr);
+        self.memory[addr as usize % 4]
+    }
+}
+
+//Every instruction is fetched through the bus, exactly its own bytes
+#[test]
+fn fetches_go_through_the_bus() {
+    for opcode in 0..=0xff {
+        let mut state = State8080::new();
+        state.pc = 0xfffe;
+        state.sp = 0x2000;
+        let mut bus = Fetches { memory: [0; 4], fetched: Vec::new() };
+        bus.memory[2] = opcode;
+        emulate_8080_op(&mut state, &mut bus);
+        let (_, length) = format_8080_op(&[opcode, 0, 0], 0);
+        let expected: Vec<u16> = (0..length as u16).map(|i| 0xfffe_u16.wrapping_add(i)).collect();
+        assert_eq!(bus.fetched, expected, "{:02x}", opcode);
+    }
+}
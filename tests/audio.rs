@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use i8080_emu::audio::{self, Sample, SoundEvent, SAMPLE_RATE};
+
+fn wav_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("i8080-audio-{}-{}.wav", name, std::process::id()))
+}
+
+fn load(name: &str, contents: &[u8]) -> std::io::Result<Sample> {
+    let path = wav_path(name);
+    fs::write(&path, contents).unwrap();
+    let sample = Sample::load(&path, false);
+    fs::remove_file(&path).unwrap();
+    sample
+}
+
+#[test]
+fn wav_round_trip() {
+    let track = [0, 1, -1, 1000, i16::MAX, i16::MIN, -12345];
+    let mut out = Vec::new();
+    audio::write_wav(&mut out, &track).unwrap();
+    let mut header = b"RIFF".to_vec();
+    header.extend_from_slice(&(36u32 + 14).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt \x10\x00\x00\x00\x01\x00\x01\x00");
+    header.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    header.extend_from_slice(&(SAMPLE_RATE * 2).to_le_bytes());
+    header.extend_from_slice(b"\x02\x00\x10\x00data\x0e\x00\x00\x00");
+    assert_eq!(out[..44], header[..]);
+    assert_eq!(out.len(), 44 + 14);
+
+    let path = wav_path("round-trip");
+    audio::save_wav(&path, &track).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), out);
+    let sample = Sample::load(&path, true).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(sample.data, track);
+    assert!(sample.looping);
+}
+
+//8 bit stereo at half the rate is mixed down and each frame doubled
+#[test]
+fn wav_8bit_stereo() {
+    let mut wav = b"RIFF\x2e\x00\x00\x00WAVEfmt \x10\x00\x00\x00\x01\x00\x02\x00".to_vec();
+    wav.extend_from_slice(&(SAMPLE_RATE / 2).to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(b"\x02\x00\x08\x00data\x06\x00\x00\x00");
+    wav.extend_from_slice(&[0xc0, 0x40, 0xff, 0xff, 0x80, 0x00]);
+    assert_eq!(load("stereo", &wav).unwrap().data, [0, 0, 32512, 32512, -16384, -16384]);
+
+    assert!(load("not-wav", b"RIFX\x00\x00\x00\x00WAVE").is_err());
+    assert!(load("truncated", &wav[..wav.len() - 1]).is_err());
+}
+
+#[test]
+fn mix() {
+    //Ten cpu cycles per output sample
+    let cpu_hz = SAMPLE_RATE as u64 * 10;
+    let mut samples = HashMap::new();
+    samples.insert((3, 0), Sample { data: vec![100, 200, 300], looping: false });
+    samples.insert((3, 1), Sample { data: vec![1000, -1000], looping: true });
+    samples.insert((5, 0), Sample { data: vec![30000], looping: false });
+    samples.insert((5, 1), Sample { data: vec![30000], looping: false });
+    let event = |cycle, port, value| SoundEvent { cycle, port, value };
+    let events = [
+        //The one shot starts at sample 2 and plays out, setting another bit
+        //doesn't restart it
+        event(20, 3, 0x01),
+        //The loop repeats from sample 5 until the bit clears at sample 9
+        event(50, 3, 0x03),
+        event(90, 3, 0x00),
+        //Port 4 has no samples
+        event(100, 4, 0xff),
+        //Both one shots at once clip
+        event(110, 5, 0x03),
+    ];
+    let track = audio::mix(&events, &samples, cpu_hz, 120);
+    assert_eq!(track, [0, 0, 100, 200, 300, 1000, -1000, 1000, -1000, 0, 0, 32767]);
+}
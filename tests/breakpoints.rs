@@ -0,0 +1,161 @@
+use i8080_emu::breakpoints::{Access, AccessKind, Condition, Kind};
+use i8080_emu::{NullBus, Runner, State8080, StopCondition, StopReason};
+
+fn eval(source: &str, state: &State8080) -> u32 {
+    Condition::parse(source).unwrap_or_else(|e| panic!("{}: {}", source, e)).eval(state, 7)
+}
+
+#[test]
+fn precedence() {
+    let state = State8080::new();
+    for (source, value) in [
+        ("1 + 2 == 3", 1),
+        ("1 | 2 == 3", 1),
+        ("6 & 3 | 8", 10),
+        ("5 - 1 - 1", 3),
+        ("0 || 1 && 0", 0),
+        ("1 || 0 && 0", 1),
+        ("(1 || 0) && 0", 0),
+        ("1 < 2 == 1", 1),
+        ("!0 + 1", 2),
+        ("!(0 + 1)", 0),
+        ("0x10 + 16", 32),
+        ("0 - 1", u32::MAX),
+    ].iter() {
+        assert_eq!(eval(source, &state), *value, "{}", source);
+    }
+}
+
+#[test]
+fn registers_flags_and_memory() {
+    let mut state = State8080::new();
+    state.a = 0x20;
+    state.b = 0x01;
+    state.c = 0x02;
+    state.d = 0x03;
+    state.e = 0x04;
+    state.h = 0x24;
+    state.l = 0x10;
+    state.sp = 0x3fff;
+    state.pc = 0x0100;
+    state.memory[0x2410] = 0x55;
+    state.memory[0x0100] = 0x76;
+    state.cc.z = true;
+    state.cc.s = false;
+    state.cc.ac = 0;
+    state.cc.p = 0;
+    state.cc.cy = 1;
+    for (source, value) in [
+        ("a", 0x20),
+        ("B", 0x01),
+        ("c + d + e", 9),
+        ("h == 0x24 && l == 0x10", 1),
+        ("bc", 0x0102),
+        ("de", 0x0304),
+        ("HL", 0x2410),
+        ("sp", 0x3fff),
+        ("pc", 0x0100),
+        ("m", 0x55),
+        ("[pc]", 0x76),
+        ("[hl] == m", 1),
+        ("[0x2400 + 0x10]", 0x55),
+        ("z && cy && !s", 1),
+        ("ac | p", 0),
+        ("inte", 1),
+        ("hits", 7),
+        ("A == 0x20 && HL > 0x2400", 1),
+    ].iter() {
+        assert_eq!(eval(source, &state), *value, "{}", source);
+    }
+    assert_eq!(eval("psw", &state), 0x2000 | state.cc.to_byte() as u32);
+}
+
+#[test]
+fn malformed_conditions() {
+    for source in ["", "a ==", "(a == 1", "a == 1)", "[hl", "a $ 1", "0xzz", "12ab", "ix == 0", "a b",
+                   "!", "a = 1"].iter() {
+        assert!(Condition::parse(source).is_err(), "'{}' parsed", source);
+    }
+    assert_eq!(Condition::parse("  a == 1 ").unwrap().to_string(), "a == 1");
+}
+
+//Reads 2000, writes 2001, does port I/O, then writes and reads 2002
+const ACCESSES: &[u8] = &[
+    0x3a, 0x00, 0x20, //0000 LDA 2000h
+    0x32, 0x01, 0x20, //0003 STA 2001h
+    0xdb, 0x10, //0006 IN 10h
+    0xd3, 0x11, //0008 OUT 11h
+    0x21, 0x02, 0x20, //000a LXI H,2002h
+    0x3e, 0x99, //000d MVI A,99h
+    0x77, //000f MOV M,A
+    0x7e, //0010 MOV A,M
+    0x76, //0011 HLT
+];
+
+#[test]
+fn watchpoints() {
+    let mut state = State8080::new();
+    state.memory[..ACCESSES.len()].copy_from_slice(ACCESSES);
+    state.memory[0x2000] = 0x42;
+    let mut runner = Runner::new(vec![StopCondition::Halt]);
+    //Instruction fetches are not reads
+    runner.breakpoints_mut().add(Kind::Read(0x0000..=0x0011), None);
+    let read = runner.breakpoints_mut().add(Kind::Read(0x2000..=0x2000), None);
+    let write = runner.breakpoints_mut().add(Kind::Write(0x1fff..=0x2001), None);
+    let port_in = runner.breakpoints_mut().add(Kind::In(0x10), None);
+    let port_out = runner.breakpoints_mut().add(Kind::Out(0x11), None);
+    let access = runner.breakpoints_mut().add(Kind::Access(0x2002..=0x2002), None);
+
+    let access_of = |kind, addr, value| Access { kind, addr, value };
+    let mut reasons = Vec::new();
+    loop {
+        let reason = runner.run(&mut state, &mut NullBus);
+        reasons.push(reason);
+        if reason == StopReason::Halted {
+            break;
+        }
+    }
+    assert_eq!(reasons, [
+        StopReason::Watchpoint { id: read, access: access_of(AccessKind::Read, 0x2000, 0x42) },
+        StopReason::Watchpoint { id: write, access: access_of(AccessKind::Write, 0x2001, 0x42) },
+        StopReason::Watchpoint { id: port_in, access: access_of(AccessKind::In, 0x10, 0x00) },
+        StopReason::Watchpoint { id: port_out, access: access_of(AccessKind::Out, 0x11, 0x00) },
+        StopReason::Watchpoint { id: access, access: access_of(AccessKind::Write, 0x2002, 0x99) },
+        StopReason::Watchpoint { id: access, access: access_of(AccessKind::Read, 0x2002, 0x99) },
+        StopReason::Halted,
+    ]);
+    //Stops are reported after the instruction
+    assert_eq!(state.pc, 0x0012);
+}
+
+//Increments B in a loop
+const LOOP: &[u8] = &[
+    0x04, //0000 INR B
+    0xc3, 0x00, 0x00, //0001 JMP 0000
+];
+
+#[test]
+fn hit_counts_and_conditions() {
+    let mut state = State8080::new();
+    state.memory[..LOOP.len()].copy_from_slice(LOOP);
+    let mut runner = Runner::new(vec![StopCondition::Instructions(1000)]);
+    let third = runner.breakpoints_mut().add(Kind::Exec(0x0001), Some(Condition::parse("hits == 3").unwrap()));
+    let disabled = runner.breakpoints_mut().add(Kind::Exec(0x0000), None);
+    runner.breakpoints_mut().get_mut(disabled).unwrap().enabled = false;
+    runner.breakpoints_mut().update();
+
+    assert_eq!(runner.run(&mut state, &mut NullBus), StopReason::Breakpoint(third));
+    assert_eq!(state.b, 3);
+    let hits = |runner: &Runner, id| runner.breakpoints().iter().find(|b| b.id == id).unwrap().hits;
+    assert_eq!(hits(&runner, third), 3);
+    assert_eq!(hits(&runner, disabled), 0);
+
+    //The count goes on while the condition is false
+    runner.breakpoints_mut().get_mut(third).unwrap().condition = Some(Condition::parse("b == 10").unwrap());
+    assert_eq!(runner.run(&mut state, &mut NullBus), StopReason::Breakpoint(third));
+    assert_eq!(hits(&runner, third), 10);
+
+    assert!(runner.breakpoints_mut().remove(third));
+    assert!(!runner.breakpoints_mut().remove(third));
+    assert_eq!(runner.run(&mut state, &mut NullBus), StopReason::Instructions(1000));
+}
@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::process::{Command, Output, Stdio};
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use i8080_emu::console::{Console, HostConsole};
+use i8080_emu::cpm::Cpm;
+use i8080_emu::State8080;
+
+//Runs the emulator on program with stdin closed
+fn run(name: &str, args: &[&str], program: &[u8]) -> Output {
+    let path = std::env::temp_dir().join(format!("i8080-console-{}-{}.bin", name, std::process::id()));
+    fs::write(&path, program).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .arg("emulate")
+        .args(args)
+        .arg(&path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run i8080-emu");
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+//Polls closed until the reader thread has seen the end of input
+fn wait_closed(console: &mut HostConsole) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !console.closed() {
+        assert!(Instant::now() < deadline, "the reader never saw the end of input");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+//Closed input has no character waiting, BDOS console status says so
+#[test]
+fn closed_input_is_not_ready() {
+    let console = Rc::new(RefCell::new(HostConsole::with_input(io::empty())));
+    wait_closed(&mut console.borrow_mut());
+    let mut cpm = Cpm::new(console.clone(), std::env::temp_dir());
+    let mut state = State8080::new();
+    state.a = 0x55;
+    state.c = 11;
+    cpm.bdos_call(&mut state);
+    assert_eq!(state.a, 0);
+    assert_eq!(console.borrow_mut().read(), None);
+}
+
+//Characters still queued when input closes are read before it counts as closed
+#[test]
+fn input_before_close() {
+    let mut console = HostConsole::with_input(&b"hi"[..]);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !console.status() {
+        assert!(Instant::now() < deadline, "no input arrived");
+        thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!console.closed());
+    assert_eq!(console.read(), Some(b'h'));
+    assert_eq!(console.read(), Some(b'i'));
+    wait_closed(&mut console);
+    assert!(!console.status());
+    assert_eq!(console.read(), None);
+}
+
+//An Altair program polling the serial port for a key quits when input is
+//closed instead of waiting forever
+#[test]
+fn altair_quits_on_closed_input() {
+    let program = [
+        0xdb, 0x10, //0000 IN 10h
+        0xe6, 0x01, //0002 ANI 1
+        0xca, 0x00, 0x00, //0004 JZ 0000
+        0x76, //0007 HLT
+    ];
+    let output = run("altair", &["--machine", "altair", "--max-cycles", "10000000"], &program);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Quit after"), "{}", stdout);
+}
@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+
+use i8080_emu::console::Console;
+use i8080_emu::cpm::Cpm;
+use i8080_emu::{NullBus, Runner, State8080, StopCondition, StopReason};
+
+//Console that types input and keeps everything written
+#[derive(Default)]
+struct Script {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl Console for Script {
+    fn write(&mut self, byte: u8) {
+        self.output.push(byte);
+    }
+
+    fn read(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn status(&mut self) -> bool {
+        !self.input.is_empty()
+    }
+}
+
+//Runs program until it exits through 0000, with data copied to memory first
+fn run(cpm: &mut Cpm<Script>, program: &[u8], data: &[(u16, &[u8])]) -> Box<State8080> {
+    let mut state = Box::new(State8080::new());
+    cpm.load(&mut state, program).unwrap();
+    for (addr, bytes) in data {
+        state.memory[*addr as usize..*addr as usize + bytes.len()].copy_from_slice(bytes);
+    }
+    let mut runner = Runner::new(vec![StopCondition::Address(0x0000), StopCondition::Instructions(1_000_000)]);
+    assert_eq!(cpm.run(&mut state, &mut NullBus, &mut runner), StopReason::Address(0x0000));
+    state
+}
+
+//Nothing in memory is a '$', printing stops after all of it instead of
+//wrapping around forever
+#[test]
+fn print_string_without_terminator() {
+    let program = [
+        0x0e, 0x09, //0100 MVI C,9
+        0x11, 0x00, 0x03, //0102 LXI D,0300h
+        0xcd, 0x05, 0x00, //0105 CALL 5
+        0xc3, 0x00, 0x00, //0108 JMP 0
+    ];
+    let mut cpm = Cpm::new(Script::default(), std::env::temp_dir());
+    run(&mut cpm, &program, &[]);
+    assert_eq!(cpm.console.output.len(), 0x10000);
+}
+
+//Empty host directory for a test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("i8080-cpm-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    dir
+}
+
+//Program of BDOS calls, each result in A is stored at RESULTS onwards
+struct Calls {
+    program: Vec<u8>,
+    results: u16,
+}
+
+const RESULTS: u16 = 0x0400;
+
+impl Calls {
+    fn new() -> Calls {
+        Calls { program: Vec::new(), results: RESULTS }
+    }
+
+    fn call(&mut self, function: u8, de: u16) -> &mut Calls {
+        self.program.extend_from_slice(&[
+            0x0e, function, //MVI C,function
+            0x11, de as u8, (de >> 8) as u8, //LXI D,de
+            0xcd, 0x05, 0x00, //CALL 5
+            0x32, self.results as u8, (self.results >> 8) as u8, //STA results
+        ]);
+        self.results += 1;
+        self
+    }
+
+    fn exit(&mut self) -> Vec<u8> {
+        let mut program = self.program.clone();
+        program.extend_from_slice(&[0xc3, 0x00, 0x00]); //JMP 0
+        program
+    }
+}
+
+//File control block for name, 36 bytes with the rename target at 16
+fn fcb(name: &[u8; 11], rename: &[u8; 11]) -> Vec<u8> {
+    let mut fcb = vec![0; 36];
+    fcb[1..12].copy_from_slice(name);
+    fcb[17..28].copy_from_slice(rename);
+    fcb
+}
+
+//Copies a host file record by record, renames the copy, deletes the
+//original and works on the copy with the random access functions
+#[test]
+fn file_functions() {
+    let dir = temp_dir("files");
+    let input: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    fs::write(dir.join("input.txt"), &input).unwrap();
+
+    const INPUT: u16 = 0x0500;
+    const OUTPUT: u16 = 0x0600;
+    const DELETE: u16 = 0x0800;
+    const COPY: u16 = 0x0900;
+    const DMA: u16 = 0x0a00;
+    let program = Calls::new()
+        .call(15, INPUT) //Open INPUT.TXT
+        .call(22, OUTPUT) //Make OUTPUT.DAT
+        .call(20, INPUT).call(21, OUTPUT) //Copy three records
+        .call(20, INPUT).call(21, OUTPUT)
+        .call(20, INPUT).call(21, OUTPUT)
+        .call(20, INPUT) //End of file
+        .call(36, INPUT) //Set random record to the current one
+        .call(16, INPUT) //Close both
+        .call(16, OUTPUT)
+        .call(23, OUTPUT) //Rename OUTPUT.DAT to COPY.DAT
+        .call(19, DELETE) //Delete INPUT.TXT
+        .call(15, DELETE) //Open it again
+        .call(15, COPY) //Open COPY.DAT
+        .call(33, COPY) //Read random record 2
+        .call(35, COPY) //Compute file size, record 3
+        .call(26, DMA) //Write random record 3 from DMA
+        .call(34, COPY)
+        .call(16, COPY)
+        .exit();
+
+    let copy = {
+        let mut fcb = fcb(b"COPY    DAT", b"           ");
+        fcb[33] = 2;
+        fcb
+    };
+    let mut cpm = Cpm::new(Script::default(), dir.clone());
+    let state = run(&mut cpm, &program, &[
+        (INPUT, &fcb(b"INPUT   TXT", b"           ")),
+        (OUTPUT, &fcb(b"OUTPUT  DAT", b"COPY    DAT")),
+        (DELETE, &fcb(b"INPUT   TXT", b"           ")),
+        (COPY, &copy),
+        (DMA, &[b'Z'; 128]),
+    ]);
+
+    let results = &state.memory[RESULTS as usize..RESULTS as usize + 21];
+    assert_eq!(results, &[
+        0, 0, //open, make
+        0, 0, 0, 0, 0, 0, //read and write three records
+        1, //end of file
+        0, 0, 0, //set random record, close, close
+        0, //rename
+        0, 0xff, //delete, open the deleted file
+        0, 0, 0, 0, 0, 0, //open, read random, size, set DMA, write random, close
+    ]);
+    assert_eq!(state.memory[INPUT as usize + 33], 3, "random record of INPUT.TXT");
+    assert_eq!(state.memory[COPY as usize + 33], 3, "size of COPY.DAT");
+    //Record 2 of the copy, read into the default DMA buffer
+    assert_eq!(&state.memory[0x80..0x80 + 44], &input[256..]);
+    assert!(state.memory[0x80 + 44..0x100].iter().all(|b| *b == 0x1a));
+
+    let mut names: Vec<String> = fs::read_dir(&dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["copy.dat"]);
+    let copied = fs::read(dir.join("copy.dat")).unwrap();
+    assert_eq!(copied.len(), 4 * 128);
+    assert_eq!(&copied[..300], &input[..]);
+    assert!(copied[300..384].iter().all(|b| *b == 0x1a));
+    assert!(copied[384..].iter().all(|b| *b == b'Z'));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+//Search first and next list the matching host files in name order, files
+//the program creates show up without reopening the directory
+#[test]
+fn search_sees_new_files() {
+    let dir = temp_dir("search");
+    for name in ["b.com", "a.com", "c.txt", "not-8.3-name.com"].iter() {
+        fs::write(dir.join(name), b"x").unwrap();
+    }
+    const PATTERN: u16 = 0x0500;
+    const NEW: u16 = 0x0600;
+    let program = Calls::new()
+        .call(22, NEW) //Make D.COM
+        .call(26, 0x0700) //Entries go to 0700
+        .call(17, PATTERN)
+        .call(26, 0x0720)
+        .call(18, PATTERN)
+        .call(26, 0x0740)
+        .call(18, PATTERN)
+        .call(18, PATTERN)
+        .exit();
+    let mut cpm = Cpm::new(Script::default(), dir.clone());
+    let state = run(&mut cpm, &program, &[
+        (PATTERN, &fcb(b"????????COM", b"           ")),
+        (NEW, &fcb(b"D       COM", b"           ")),
+    ]);
+    assert_eq!(&state.memory[RESULTS as usize..RESULTS as usize + 8], &[0, 0, 0, 0, 0, 0, 0, 0xff]);
+    assert_eq!(&state.memory[0x0701..0x070c], b"A       COM");
+    assert_eq!(&state.memory[0x0721..0x072c], b"B       COM");
+    //The last search next finds nothing more, the other names don't match
+    assert_eq!(&state.memory[0x0741..0x074c], b"D       COM");
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+//An FCB at the top of memory wraps around to page zero instead of running
+//off the end
+#[test]
+fn make_file_at_top_of_memory() {
+    let dir = temp_dir("wrap");
+    let program = Calls::new().call(22, 0xfff0).exit();
+    let mut fcb = fcb(b"TOP     DAT", b"           ");
+    fcb[12..16].copy_from_slice(&[1, 2, 3, 4]);
+    let mut cpm = Cpm::new(Script::default(), dir.clone());
+    let state = run(&mut cpm, &program, &[(0xfff0, &fcb[..16]), (0x0010, &[0x55])]);
+    assert_eq!(state.memory[RESULTS as usize], 0);
+    assert_eq!(&state.memory[0xfffc..], &[0, 0, 0, 0]);
+    assert_eq!(state.memory[0x0010], 0, "current record");
+    assert!(dir.join("top.dat").exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,144 @@
+use std::fs;
+
+use i8080_emu::console::Console;
+use i8080_emu::cpm22::Cpm22;
+use i8080_emu::disk::{DiskImage, Geometry};
+use i8080_emu::{Runner, State8080, StopCondition, StopReason};
+
+struct Silent;
+
+impl Console for Silent {
+    fn write(&mut self, _byte: u8) {}
+
+    fn read(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn status(&mut self) -> bool {
+        false
+    }
+}
+
+const CCP: u16 = 0xe400;
+const BIOS: u16 = CCP + 0x1600;
+//Disk parameter header of drive A, after the 17 jump table entries
+const DPH: u16 = BIOS + 17 * 3;
+
+//Stands in for the CCP at its entry, ccp+35ch. The first time round it
+//clobbers the system and warm boots, the second it selects drives, reads
+//logical sector 4 of track 2 through the skew table to 0200h and writes
+//0300h to physical sector 3.
+const PROGRAM: &[u8] = &[
+    0x21, 0x00, 0x01, //e75c LXI H,0100h
+    0x34, //e75f INR M
+    0x7e, //e760 MOV A,M
+    0xfe, 0x01, //e761 CPI 1
+    0xc2, 0x6d, 0xe7, //e763 JNZ e76d
+    0xaf, //e766 XRA A
+    0x32, 0x00, 0xe5, //e767 STA e500h
+    0xc3, 0x00, 0x00, //e76a JMP 0000
+    0x0e, 0x01, //e76d MVI C,1
+    0xcd, 0x1b, 0xfa, //e76f CALL SELDSK
+    0x22, 0x02, 0x01, //e772 SHLD 0102h
+    0x0e, 0x00, //e775 MVI C,0
+    0xcd, 0x1b, 0xfa, //e777 CALL SELDSK
+    0x22, 0x04, 0x01, //e77a SHLD 0104h
+    0x5e, //e77d MOV E,M
+    0x23, //e77e INX H
+    0x56, //e77f MOV D,M
+    0x01, 0x02, 0x00, //e780 LXI B,2
+    0xcd, 0x1e, 0xfa, //e783 CALL SETTRK
+    0x01, 0x04, 0x00, //e786 LXI B,4
+    0xcd, 0x30, 0xfa, //e789 CALL SECTRAN
+    0x22, 0x06, 0x01, //e78c SHLD 0106h
+    0x44, //e78f MOV B,H
+    0x4d, //e790 MOV C,L
+    0xcd, 0x21, 0xfa, //e791 CALL SETSEC
+    0x01, 0x00, 0x02, //e794 LXI B,0200h
+    0xcd, 0x24, 0xfa, //e797 CALL SETDMA
+    0xcd, 0x27, 0xfa, //e79a CALL READ
+    0x32, 0x08, 0x01, //e79d STA 0108h
+    0x01, 0x03, 0x00, //e7a0 LXI B,3
+    0xcd, 0x21, 0xfa, //e7a3 CALL SETSEC
+    0x01, 0x00, 0x03, //e7a6 LXI B,0300h
+    0xcd, 0x24, 0xfa, //e7a9 CALL SETDMA
+    0xcd, 0x2a, 0xfa, //e7ac CALL WRITE
+    0x32, 0x09, 0x01, //e7af STA 0109h
+    0x76, //e7b2 HLT
+];
+
+//Offset of a sector in the image, sectors counted from 1
+fn offset(track: usize, sector: usize) -> usize {
+    Geometry::IBM_3740.offset(track, sector - 1).unwrap()
+}
+
+//The IBM 3740 skew, physical sector of each logical one
+const SKEW: [u8; 26] = [
+    1, 7, 13, 19, 25, 5, 11, 17, 23, 3, 9, 15, 21,
+    2, 8, 14, 20, 26, 6, 12, 18, 24, 4, 10, 16, 22,
+];
+
+fn word(state: &State8080, addr: u16) -> u16 {
+    u16::from_le_bytes([state.memory[addr as usize], state.memory[addr as usize + 1]])
+}
+
+#[test]
+fn host_bios() {
+    //Every byte of the image differs from its neighbours, the system starts
+    //with the CCP's JMP to its entry so the base is found from it
+    let mut data: Vec<u8> = (0..Geometry::IBM_3740.size()).map(|i| (i % 251) as u8).collect();
+    data[128..131].copy_from_slice(&[0xc3, 0x5c, 0xe7]);
+    data[128 + 0x35c..128 + 0x35c + PROGRAM.len()].copy_from_slice(PROGRAM);
+    let system = data[128..128 + 0x1600].to_vec();
+    let path = std::env::temp_dir().join(format!("i8080-cpm22-{}", std::process::id()));
+    fs::write(&path, &data).unwrap();
+    let image = DiskImage::open(&path, Geometry::IBM_3740, false).unwrap();
+    let mut cpm = Cpm22::new(Silent, image, None).unwrap();
+    assert_eq!(cpm.ccp, CCP);
+
+    //Cold boot loads the system and points page zero at WBOOT and the BDOS
+    let mut state = Box::new(State8080::new());
+    state.memory[0x0300..0x0380].fill(0x3c);
+    cpm.boot(&mut state);
+    assert_eq!((state.pc, state.sp, state.c), (CCP, 0x0080, 0));
+    assert_eq!(state.memory[0x0000..0x0008], [0xc3, 0x03, 0xfa, 0x00, 0x00, 0xc3, 0x06, 0xec]);
+    assert!(state.memory[CCP as usize..BIOS as usize] == system[..], "system not loaded");
+    assert!(state.memory[BIOS as usize..DPH as usize].iter().step_by(3).all(|b| *b == 0xc9));
+
+    //Disk parameter headers share the directory buffer, DPB and skew table,
+    //each drive gets 16 bytes of CSV and 31 of ALV after them
+    let dirbuf = DPH + 4 * 16;
+    let dpb = dirbuf + 128;
+    let xlt = dpb + 15;
+    for drive in 0..4 {
+        let dph = DPH + drive * 16;
+        let csv = xlt + 26 + drive * (16 + 31);
+        let words: Vec<u16> = (0..8).map(|i| word(&state, dph + i * 2)).collect();
+        assert_eq!(words, [xlt, 0, 0, 0, dirbuf, dpb, csv, csv + 16], "drive {}", drive);
+    }
+    assert_eq!(state.memory[dpb as usize..xlt as usize], [26, 0, 3, 7, 0, 242, 0, 63, 0, 0xc0, 0x00, 16, 0, 2, 0]);
+    assert_eq!(state.memory[xlt as usize..xlt as usize + 26], SKEW);
+    assert!(xlt + 26 + 4 * 47 <= BIOS + 0x200);
+
+    let mut runner = Runner::new(vec![StopCondition::Halt]);
+    assert_eq!(cpm.run(&mut state, &mut runner), Some(StopReason::Halted));
+
+    //The warm boot reloaded the clobbered system byte
+    assert_eq!(state.memory[0x0100], 2);
+    assert_eq!(state.memory[0xe500], system[0x100]);
+    assert_ne!(system[0x100], 0);
+
+    //SELDSK returns 0 for the empty drive B and drive A's header
+    assert_eq!(word(&state, 0x0102), 0);
+    assert_eq!(word(&state, 0x0104), DPH);
+    //Logical sector 4 is physical sector 25
+    assert_eq!(word(&state, 0x0106), 25);
+    assert_eq!(state.memory[0x0108], 0);
+    assert!(state.memory[0x0200..0x0280] == data[offset(2, 25)..offset(2, 25) + 128], "wrong sector read");
+
+    assert_eq!(state.memory[0x0109], 0);
+    let written = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(written[offset(2, 3)..offset(2, 3) + 128].iter().all(|b| *b == 0x3c), "sector not written");
+    assert!(written[offset(2, 4)..] == data[offset(2, 4)..]);
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+//Counts A up from 5, storing each value at 2000h
+const COUNTER: &[u8] = &[
+    0x3e, 0x05, //0000 MVI A,5
+    0x32, 0x00, 0x20, //0002 STA 2000h
+    0x3c, //0005 INR A
+    0x32, 0x00, 0x20, //0006 STA 2000h
+    0xc3, 0x05, 0x00, //0009 JMP 0005
+];
+
+//Runs COUNTER under the debugger with commands on stdin and returns what it
+//printed. Piped input isn't a terminal, so lines are read as they are.
+fn debug(name: &str, args: &[&str], commands: &str) -> String {
+    let path = std::env::temp_dir().join(format!("i8080-debugger-{}-{}.bin", name, std::process::id()));
+    fs::write(&path, COUNTER).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .arg("debug")
+        .args(args)
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to run i8080-emu");
+    child.stdin.take().unwrap().write_all(commands.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+//Asserts that the lines appear in order in out
+fn assert_in_order(out: &str, lines: &[&str]) {
+    let mut rest = out;
+    for line in lines {
+        match rest.find(line) {
+            Some(at) => rest = &rest[at + line.len()..],
+            None => panic!("'{}' missing or out of order in:\n{}", line, out),
+        }
+    }
+}
+
+#[test]
+fn breakpoints_and_conditions() {
+    let out = debug("break", &[], "break 5 if A == 7\ncontinue\ncond 1\ncontinue\n\
+                                   watch 2000 if A == 0x0a\ndelete 1\ncontinue\ninfo\ncond 9\nq\n");
+    assert_in_order(&out, &[
+        "Breakpoint 1 at 0005",
+        "Stopped: Breakpoint 1\nPC: 0005 SP: 0000 A: 07",
+        //Without its condition the breakpoint stops the next time round
+        "Stopped: Breakpoint 1\nPC: 0005 SP: 0000 A: 08",
+        "Watchpoint 2",
+        "Stopped: Watchpoint 2: write 2000 = 0a\nPC: 0009 SP: 0000 A: 0a",
+        "  2 watch w 2000-2000 if A == 0x0a hits:",
+        "No breakpoint 9",
+    ]);
+    assert!(!out.contains("  1 break"), "{}", out);
+}
+
+#[test]
+fn step_examine_deposit() {
+    let out = debug("step", &[], "step 4\nmem 2000 2\nstep\ndeposit 2000 12 34\nmem 2000 2\n\
+                                  deposit 2000 100\nmem 1fff 4\nq\n");
+    assert_in_order(&out, &[
+        "PC: 0009 SP: 0000 A: 06",
+        "=>0009 JMP    $0005",
+        "2000 06 00",
+        "PC: 0005 SP: 0000 A: 06",
+        "=>0005 INR    A",
+        "2000 12 34",
+        "Not a byte: '100'",
+        "1fff 00 12 34 00",
+    ]);
+    //Nothing was recording, so there's no history to clear
+    assert!(!out.contains("History cleared"), "{}", out);
+}
+
+#[test]
+fn deposit_clears_history() {
+    let out = debug("history", &["--history", "10"], "step 3\nlastwrite 2000\ndeposit 0005 00\n\
+                                                      rstep\nlastwrite 2000\nq\n");
+    assert_in_order(&out, &[
+        "PC: 0006 SP: 0000 A: 06",
+        //The STA at 0002, disassembled from memory as it is now
+        "Instruction 2 at 0002 wrote 05 (was 00), 0002 now holds STA    $2000",
+        "History cleared",
+        "Reached the start of the history\nPC: 0006 SP: 0000 A: 06",
+        "No write to 2000 in the last 0 instructions",
+    ]);
+}
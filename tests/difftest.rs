@@ -0,0 +1,65 @@
+use std::fs;
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .arg("difftest")
+        .args(args)
+        .output()
+        .expect("failed to run i8080-emu")
+}
+
+fn difftest(args: &[&str]) {
+    let output = run(args);
+    assert!(output.status.success(), "cores diverged:\n{}", String::from_utf8_lossy(&output.stdout));
+}
+
+#[test]
+fn reference_random_streams() {
+    difftest(&["--cores", "reference,reference", "--random", "100", "--steps", "5000"]);
+}
+
+//Stores to memory, then hits the planted ADC B bug with A=0fh and the carry
+//set, where only the carry in makes the auxiliary carry
+const ADC: &[u8] = &[
+    0x21, 0x00, 0x20, //0000 LXI H,2000h
+    0x36, 0x55, //0003 MVI M,55h
+    0x3e, 0x0f, //0005 MVI A,0Fh
+    0x37, //0007 STC
+    0x88, //0008 ADC B
+    0x76, //0009 HLT
+];
+
+//The first divergence is ADC B, the reproducer starts right before it with
+//only the registers and memory the bug needs
+#[test]
+fn faulty_core() {
+    let dir = std::env::temp_dir();
+    let program = dir.join(format!("i8080-difftest-{}.bin", std::process::id()));
+    let repro = dir.join(format!("i8080-difftest-{}.sav", std::process::id()));
+    fs::write(&program, ADC).unwrap();
+    let output = run(&["--cores", "reference,faulty-adc", "--repro", repro.to_str().unwrap(),
+                       program.to_str().unwrap()]);
+    fs::remove_file(&program).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[1..10], [
+        "Divergence at instruction 5: 0008 ADC    B",
+        "before:     PC: 0008 SP: 0000 A: 0f BC: 0000 DE: 0000 HL: 2000 F: d7 [S Z AC P CY] INTE: 1",
+        "reference:  PC: 0009 SP: 0000 A: 10 BC: 0000 DE: 0000 HL: 2000 F: 12 [s z AC p cy] INTE: 1",
+        "faulty-adc: PC: 0009 SP: 0000 A: 10 BC: 0000 DE: 0000 HL: 2000 F: 02 [s z ac p cy] INTE: 1",
+        "  f: 12 != 2",
+        "Minimized reproducer, 1 instruction(s) from:",
+        "  PC: 0008 SP: 0000 A: 0f BC: 0000 DE: 0000 HL: 0000 F: d7 [S Z AC P CY] INTE: 1",
+        "  0008: 88",
+        "Divergence at instruction 1: 0008 ADC    B",
+    ], "{}", stdout);
+
+    //The saved reproducer diverges on its own
+    let replayed = run(&["--cores", "reference,faulty-adc", "--state", repro.to_str().unwrap(), "--steps", "1"]);
+    fs::remove_file(&repro).unwrap();
+    let stdout = String::from_utf8_lossy(&replayed.stdout);
+    assert!(stdout.contains("Divergence at instruction 1: 0008 ADC    B"), "{}", stdout);
+    assert!(stdout.contains("  0008: 88\n"), "{}", stdout);
+}
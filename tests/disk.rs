@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::PathBuf;
+
+use i8080_emu::disk::{self, Dcdd, DiskImage, Geometry, SimpleDisk, SIMPLE_DISK_PORT};
+
+//Image file of its own for the test, every byte different from its neighbours
+fn image(name: &str, geometry: Geometry) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("i8080-disk-{}-{}", name, std::process::id()));
+    let data: Vec<u8> = (0..geometry.size()).map(|i| (i % 251) as u8).collect();
+    fs::write(&path, data).unwrap();
+    path
+}
+
+fn sector(geometry: Geometry, track: usize, sector: usize) -> Vec<u8> {
+    let offset = geometry.offset(track, sector).unwrap();
+    (offset..offset + geometry.sector_size).map(|i| (i % 251) as u8).collect()
+}
+
+//Three tracks of four 16 byte sectors, so stepping and the sector counter
+//wrap early
+const SMALL: Geometry = Geometry { tracks: 3, sectors: 4, sector_size: 16 };
+
+#[test]
+fn dcdd_ports() {
+    let path = image("dcdd", SMALL);
+    let mut dcdd = Dcdd::new();
+    dcdd.insert(0, DiskImage::open(&path, SMALL, false).unwrap()).unwrap();
+    assert!(dcdd.insert(16, DiskImage::open(&path, SMALL, true).unwrap()).is_err());
+
+    //Nothing selected, then drive 1 which is empty
+    assert_eq!(dcdd.input(0x08), Some(0xff));
+    dcdd.output(0x08, 0x01);
+    assert_eq!(dcdd.input(0x08), Some(0xff));
+
+    //Drive 0 on track 0, the head can move but isn't loaded
+    dcdd.output(0x08, 0x00);
+    assert_eq!(dcdd.input(0x08), Some(0xbd));
+    assert_eq!(dcdd.input(0x09), Some(0xff));
+
+    //Stepping stops at the last track and at track 0
+    for _ in 0..3 {
+        dcdd.output(0x09, 0x01);
+    }
+    assert_eq!(dcdd.input(0x08), Some(0xfd));
+    for _ in 0..2 {
+        dcdd.output(0x09, 0x02);
+    }
+    assert_eq!(dcdd.input(0x08), Some(0xbd));
+    dcdd.output(0x09, 0x02);
+    assert_eq!(dcdd.input(0x08), Some(0xbd));
+    dcdd.output(0x09, 0x01);
+    assert_eq!(dcdd.input(0x08), Some(0xfd));
+
+    //With the head loaded there is read data, each poll of the sector
+    //position is the next sector with sector true (bit 0) low
+    dcdd.output(0x09, 0x04);
+    assert_eq!(dcdd.input(0x08), Some(0x79));
+    let positions: Vec<u8> = (0..5).map(|_| dcdd.input(0x09).unwrap()).collect();
+    assert_eq!(positions, [0xc2, 0xc4, 0xc6, 0xc0, 0xc2]);
+
+    //Sector 1 of track 1
+    let read: Vec<u8> = (0..SMALL.sector_size).map(|_| dcdd.input(0x0a).unwrap()).collect();
+    assert_eq!(read, sector(SMALL, 1, 1));
+
+    //Writing a whole sector goes to the file, the next time round it reads back
+    dcdd.output(0x09, 0x80);
+    assert_eq!(dcdd.input(0x08), Some(0x78));
+    let written: Vec<u8> = (0..SMALL.sector_size as u8).map(|i| 0xa0 + i).collect();
+    for byte in &written {
+        dcdd.output(0x0a, *byte);
+    }
+    assert_eq!(dcdd.input(0x08), Some(0x79));
+    let offset = SMALL.offset(1, 1).unwrap();
+    assert_eq!(fs::read(&path).unwrap()[offset..offset + SMALL.sector_size], written[..]);
+    for _ in 0..SMALL.sectors {
+        dcdd.input(0x09);
+    }
+    let read: Vec<u8> = (0..SMALL.sector_size).map(|_| dcdd.input(0x0a).unwrap()).collect();
+    assert_eq!(read, written);
+    assert_eq!(sector(SMALL, 1, 2)[..], fs::read(&path).unwrap()[offset + 16..offset + 32]);
+
+    //Unloading the head stops the sector counter, deselecting hides the drive
+    dcdd.output(0x09, 0x08);
+    assert_eq!(dcdd.input(0x09), Some(0xff));
+    dcdd.output(0x08, 0x80);
+    assert_eq!(dcdd.input(0x08), Some(0xff));
+    assert_eq!(dcdd.input(0x0b), None);
+    assert!(!dcdd.output(0x0b, 0));
+    fs::remove_file(&path).unwrap();
+}
+
+//Three tracks of four 128 byte sectors
+const SIMPLE: Geometry = Geometry { tracks: 3, sectors: 4, sector_size: 128 };
+
+fn select(controller: &mut SimpleDisk, drive: u8, track: u8, sector: u8) {
+    controller.output(SIMPLE_DISK_PORT, drive);
+    controller.output(SIMPLE_DISK_PORT + 1, track);
+    controller.output(SIMPLE_DISK_PORT + 2, sector);
+}
+
+fn command(controller: &mut SimpleDisk, write: bool) -> u8 {
+    controller.output(SIMPLE_DISK_PORT + 3, write as u8);
+    controller.input(SIMPLE_DISK_PORT + 4).unwrap()
+}
+
+#[test]
+fn simple_disk_ports() {
+    let path = image("simple", SIMPLE);
+    let protected = image("simple-ro", SIMPLE);
+    let mut controller = SimpleDisk::new();
+    controller.insert(0, DiskImage::open(&path, SIMPLE, false).unwrap()).unwrap();
+    controller.insert(1, DiskImage::open(&protected, SIMPLE, true).unwrap()).unwrap();
+    assert!(controller.insert(16, DiskImage::open(&path, SIMPLE, true).unwrap()).is_err());
+
+    //Registers read back, sectors count from 1
+    select(&mut controller, 0, 2, 3);
+    let registers: Vec<u8> = (0..3).map(|i| controller.input(SIMPLE_DISK_PORT + i).unwrap()).collect();
+    assert_eq!(registers, [0, 2, 3]);
+    assert_eq!(command(&mut controller, false), disk::DISK_OK);
+    let read: Vec<u8> = (0..128).map(|_| controller.input(SIMPLE_DISK_PORT + 5).unwrap()).collect();
+    assert_eq!(read, sector(SIMPLE, 2, 2));
+
+    //Write round trip through the file
+    select(&mut controller, 0, 1, 4);
+    let written: Vec<u8> = (0..128).map(|i| 0xff - i).collect();
+    for byte in &written {
+        controller.output(SIMPLE_DISK_PORT + 5, *byte);
+    }
+    assert_eq!(command(&mut controller, true), disk::DISK_OK);
+    let offset = SIMPLE.offset(1, 3).unwrap();
+    assert_eq!(fs::read(&path).unwrap()[offset..offset + 128], written[..]);
+    select(&mut controller, 0, 1, 4);
+    assert_eq!(command(&mut controller, false), disk::DISK_OK);
+    let read: Vec<u8> = (0..128).map(|_| controller.input(SIMPLE_DISK_PORT + 5).unwrap()).collect();
+    assert_eq!(read, written);
+
+    select(&mut controller, 2, 0, 1);
+    assert_eq!(command(&mut controller, false), disk::DISK_BAD_DRIVE);
+    select(&mut controller, 0, 3, 1);
+    assert_eq!(command(&mut controller, false), disk::DISK_BAD_TRACK);
+    select(&mut controller, 0, 0, 0);
+    assert_eq!(command(&mut controller, false), disk::DISK_BAD_SECTOR);
+    select(&mut controller, 0, 0, 5);
+    assert_eq!(command(&mut controller, false), disk::DISK_BAD_SECTOR);
+    select(&mut controller, 1, 0, 1);
+    assert_eq!(command(&mut controller, true), disk::DISK_WRITE_PROTECTED);
+    assert_eq!(fs::read(&protected).unwrap()[..128], sector(SIMPLE, 0, 0)[..]);
+
+    assert_eq!(controller.input(SIMPLE_DISK_PORT + 6), None);
+    assert!(!controller.output(SIMPLE_DISK_PORT + 6, 0));
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&protected).unwrap();
+}
@@ -0,0 +1,100 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+//CPU exercisers run as CP/M programs under --cpm, which prints their BDOS
+//console output. Each gets a cycle budget well above what a correct core
+//needs. A program missing from tests/roms fails its test, tests of programs
+//not committed yet are ignored until they are, see tests/roms/README.md.
+
+fn roms() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms")
+}
+
+//Runs program until it exits to 0x0000 and returns its console output, or
+//fails when it needs more than budget cycles
+fn run_cpm(program: &Path, budget: u64) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .args(["emulate", "--cpm", "--max-cycles", &budget.to_string()])
+        .arg(program)
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run i8080-emu");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let name = program.file_name().unwrap().to_string_lossy();
+    assert!(output.status.success(), "{} crashed the emulator:\n{}{}", name, stdout,
+            String::from_utf8_lossy(&output.stderr));
+    assert!(!stdout.contains("Stopped: Cycles("), "{} did not finish within {} cycles:\n{}",
+            name, budget, stdout);
+    assert!(stdout.contains("Stopped: Address(0)"), "{} did not exit through 0x0000:\n{}", name, stdout);
+    stdout
+}
+
+//Runs the exerciser from tests/roms and checks that all expected lines are
+//in its output and none of the failure markers
+fn exerciser(file: &str, budget: u64, expected: &[&str], failures: &[&str]) {
+    let program = roms().join(file);
+    assert!(program.exists(), "{} is missing from tests/roms, see tests/roms/README.md", file);
+    let output = run_cpm(&program, budget);
+    for line in expected {
+        assert!(output.contains(line), "{}: expected '{}' in the output:\n{}", file, line, output);
+    }
+    for marker in failures {
+        assert!(!output.contains(marker), "{} reported '{}':\n{}", file, marker, output);
+    }
+}
+
+#[test]
+#[ignore = "tests/roms/cpudiag.bin is not committed yet"]
+fn cpudiag() {
+    exerciser("cpudiag.bin", 10_000_000, &["CPU IS OPERATIONAL"], &["CPU HAS FAILED"]);
+}
+
+#[test]
+#[ignore = "tests/roms/TST8080.COM is not committed yet"]
+fn tst8080() {
+    exerciser("TST8080.COM", 50_000_000, &["CPU IS OPERATIONAL"], &["CPU HAS FAILED"]);
+}
+
+#[test]
+#[ignore = "tests/roms/8080PRE.COM is not committed yet"]
+fn prelim_8080() {
+    exerciser("8080PRE.COM", 100_000_000, &["8080 Preliminary tests complete"], &["ERROR", "failed"]);
+}
+
+//About 24 billion cycles, run with cargo test --release -- --ignored
+#[test]
+#[ignore = "runs about 24 billion cycles"]
+fn exerciser_8080exm() {
+    exerciser("8080EXM.COM", 30_000_000_000, &["Tests complete"], &["ERROR"]);
+}
+
+//Checks decimal adjust, carry and a counted loop, then prints the result
+//like the exercisers do, so the harness itself is always tested
+#[test]
+fn smoke_program() {
+    let mut program = vec![
+        0x3e, 0x99, //0100 MVI A,99h
+        0xc6, 0x01, //0102 ADI 1
+        0x27, //0104 DAA, 9a becomes 00 with carry
+        0xd2, 0x17, 0x01, //0105 JNC FAIL
+        0xc2, 0x17, 0x01, //0108 JNZ FAIL
+        0x06, 0x05, //010b MVI B,5
+        0x05, //010d LOOP: DCR B
+        0xc2, 0x0d, 0x01, //010e JNZ LOOP
+        0x11, 0x22, 0x01, //0111 LXI D,OK
+        0xc3, 0x1a, 0x01, //0114 JMP PRINT
+        0x11, 0x35, 0x01, //0117 FAIL: LXI D,BAD
+        0x0e, 0x09, //011a PRINT: MVI C,9
+        0xcd, 0x05, 0x00, //011c CALL 5
+        0xc3, 0x00, 0x00, //011f JMP 0
+    ];
+    program.extend_from_slice(b"CPU IS OPERATIONAL$"); //0122 OK
+    program.extend_from_slice(b"CPU HAS FAILED$"); //0135 BAD
+
+    let path = std::env::temp_dir().join(format!("i8080-smoke-{}.com", std::process::id()));
+    fs::write(&path, &program).unwrap();
+    let output = run_cpm(&path, 1_000);
+    fs::remove_file(&path).unwrap();
+    assert!(output.contains("CPU IS OPERATIONAL"), "smoke program failed:\n{}", output);
+}
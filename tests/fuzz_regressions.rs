@@ -0,0 +1,48 @@
+use std::fs;
+use std::process::{Command, Output};
+
+//Inputs that once panicked, kept as fixed cases next to the fuzz targets in
+//fuzz/ so cargo test catches a regression without cargo fuzz
+
+//Writes bytes to a temporary file and runs the emulator on it
+fn run(name: &str, args: &[&str], bytes: &[u8]) -> Output {
+    let path = std::env::temp_dir().join(format!("i8080-fuzz-{}-{}.bin", name, std::process::id()));
+    fs::write(&path, bytes).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .expect("failed to run i8080-emu");
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+//Every opcode as the last byte of a file, missing its operands
+#[test]
+fn disassemble_truncated_instructions() {
+    let bytes: Vec<u8> = (0..=255).collect();
+    for op in bytes.iter() {
+        let output = run("disassemble", &["disassemble"], &bytes[..=*op as usize]);
+        assert!(output.status.success(), "disassembling a trailing {:02x} failed:\n{}", op,
+                String::from_utf8_lossy(&output.stderr));
+    }
+}
+
+//Pushes with sp at 0000, increments hl and memory at ffff and runs pc past
+//ffff, all of which have to wrap around
+#[test]
+fn step_wraps_sp_pc_and_registers() {
+    let program = [
+        0x31, 0x00, 0x00, //0000 LXI SP,0000
+        0xc5, //0003 PUSH B, to fffe and ffff
+        0xe1, //0004 POP H
+        0x21, 0xff, 0xff, //0005 LXI H,ffff
+        0x23, //0008 INX H
+        0x34, //0009 INR M
+        0xc3, 0xff, 0xff, //000a JMP ffff, a NOP running into 0000
+    ];
+    let output = run("step", &["emulate", "--max-cycles", "1000"], &program);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "emulation crashed:\n{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("Stopped: Cycles("), "{}", stdout);
+}
@@ -0,0 +1,132 @@
+use std::io::{BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use i8080_emu::debugger::Bare;
+use i8080_emu::gdbstub::GdbServer;
+use i8080_emu::{Runner, State8080};
+
+//Stores 5 at 2000 and counts up in A
+const PROGRAM: &[u8] = &[
+    0x3e, 0x05, //0000 MVI A,5
+    0x32, 0x00, 0x20, //0002 STA 2000h
+    0x3c, //0005 INR A
+    0xc3, 0x05, 0x00, //0006 JMP 0005
+];
+
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    ack: bool,
+}
+
+impl Client {
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn send_raw(&mut self, data: &str, checksum: u8) {
+        write!(self.writer, "${}#{:02x}", data, checksum).unwrap();
+    }
+
+    //Sends a packet and returns the reply
+    fn send(&mut self, data: &str) -> String {
+        self.send_raw(data, data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b)));
+        if self.ack {
+            assert_eq!(self.byte(), b'+', "ack for {}", data);
+        }
+        self.reply()
+    }
+
+    fn reply(&mut self) -> String {
+        assert_eq!(self.byte(), b'$');
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                b => data.push(b),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let checksum = u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16).unwrap();
+        assert_eq!(checksum, data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)));
+        if self.ack {
+            self.writer.write_all(b"+").unwrap();
+        }
+        String::from_utf8(data).unwrap()
+    }
+}
+
+//Serves a bare machine running PROGRAM on a free localhost port
+fn connect() -> (Client, thread::JoinHandle<()>) {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let mut state = State8080::new();
+        state.memory[..PROGRAM.len()].copy_from_slice(PROGRAM);
+        let mut server = GdbServer::new(Bare { state }, Runner::new(Vec::new()));
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        server.serve(stream).unwrap();
+    });
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_nodelay(true).unwrap();
+    let client = Client { reader: BufReader::new(stream.try_clone().unwrap()), writer: stream, ack: true };
+    (client, server)
+}
+
+#[test]
+fn session() {
+    let (mut gdb, server) = connect();
+    assert_eq!(gdb.send("?"), "S05");
+
+    //AF BC DE HL SP PC, 16 bit little endian each
+    let registers = gdb.send("g");
+    assert_eq!(registers.len(), 24);
+    assert_eq!(&registers[20..], "0000");
+    assert_eq!(gdb.send("G0200341278560000ff3f0000"), "OK");
+    assert_eq!(gdb.send("g"), "0200341278560000ff3f0000");
+    assert_eq!(gdb.send("p1"), "3412");
+
+    assert_eq!(gdb.send("m0,5"), "3e05320020");
+    assert_eq!(gdb.send("M3000,2:aabb"), "OK");
+    assert_eq!(gdb.send("m3000,2"), "aabb");
+
+    //Stops at the breakpoint once STA is done
+    assert_eq!(gdb.send("Z0,5,1"), "OK");
+    assert_eq!(gdb.send("c"), "S05");
+    assert_eq!(gdb.send("p5"), "0500");
+    assert_eq!(gdb.send("m2000,1"), "05");
+    assert_eq!(gdb.send("z0,5,1"), "OK");
+    assert_eq!(gdb.send("s"), "S05");
+    assert_eq!(gdb.send("p5"), "0600");
+    assert_eq!(gdb.send("p0"), "0606");
+
+    //Continuing from 0000 writes 2000 again
+    assert_eq!(gdb.send("Z2,2000,1"), "OK");
+    assert_eq!(gdb.send("c0"), "T05watch:2000;");
+    assert_eq!(gdb.send("p5"), "0500");
+    assert_eq!(gdb.send("z2,2000,1"), "OK");
+
+    assert_eq!(gdb.send("D"), "OK");
+    server.join().unwrap();
+}
+
+//A packet with a wrong checksum is answered with a nak and not executed.
+//Without acks it is dropped silently.
+#[test]
+fn bad_checksum() {
+    let (mut gdb, server) = connect();
+    gdb.send_raw("M0,1:00", 0);
+    assert_eq!(gdb.byte(), b'-');
+    assert_eq!(gdb.send("m0,1"), "3e");
+
+    assert_eq!(gdb.send("QStartNoAckMode"), "OK");
+    gdb.ack = false;
+    gdb.send_raw("M0,1:00", 0);
+    assert_eq!(gdb.send("m0,1"), "3e");
+    assert_eq!(gdb.send("D"), "OK");
+    server.join().unwrap();
+}
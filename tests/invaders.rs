@@ -0,0 +1,132 @@
+use i8080_emu::invaders::{Input, InvadersBus, SpaceInvaders, CYCLES_PER_FRAME};
+use i8080_emu::{Bus, Runner, StopCondition, StopReason};
+
+//Spins with interrupts on, both handlers return right away
+const ROM: &[u8] = &[
+    0x31, 0x00, 0x24, //0000 LXI SP,2400h
+    0xfb, //0003 EI
+    0xc3, 0x04, 0x00, //0004 JMP 0004
+    0x00, //0007
+    0xfb, //0008 RST 1: EI
+    0xc9, //0009 RET
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //000a
+    0xfb, //0010 RST 2: EI
+    0xc9, //0011 RET
+];
+
+//The cycles of the RSTs count towards a frame budget, so --frames stops in
+//the frame it was asked to and not a little later every frame
+#[test]
+fn frame_budget_counts_interrupts() {
+    let mut machine = SpaceInvaders::new(ROM).unwrap();
+    let mut runner = Runner::new(vec![StopCondition::Cycles(100 * CYCLES_PER_FRAME)]);
+    let reason = loop {
+        if let Some(reason) = machine.step(&mut runner) {
+            break reason;
+        }
+    };
+    assert!(matches!(reason, StopReason::Cycles(_)), "{:?}", reason);
+    assert_eq!(runner.cycles, machine.state.cycles);
+    assert_eq!(machine.frame, 100);
+}
+
+//A frame count whose cycles don't fit in 64 bits is an error, not a panic
+#[test]
+fn too_many_frames() {
+    let rom = std::env::temp_dir().join(format!("i8080-invaders-frames-{}.rom", std::process::id()));
+    std::fs::write(&rom, ROM).unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .args(["emulate", "--machine", "invaders", "--frames", "0xffffffffffffffff"])
+        .arg(&rom)
+        .output()
+        .expect("failed to run i8080-emu");
+    std::fs::remove_file(&rom).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{}", stdout);
+    assert!(stdout.lines().any(|line| line == "Too many frames '0xffffffffffffffff'"), "{}", stdout);
+}
+
+//Shifts two bytes into the MB14241 and reads them back at offset 3
+const SHIFT: &[u8] = &[
+    0x3e, 0xaa, //0000 MVI A,0AAh
+    0xd3, 0x04, //0002 OUT 4
+    0x3e, 0xbb, //0004 MVI A,0BBh
+    0xd3, 0x04, //0006 OUT 4
+    0x3e, 0x03, //0008 MVI A,3
+    0xd3, 0x02, //000a OUT 2
+    0xdb, 0x03, //000c IN 3
+    0x76, //000e HLT
+];
+
+#[test]
+fn shift_register() {
+    let mut machine = SpaceInvaders::new(SHIFT).unwrap();
+    let mut runner = Runner::new(vec![StopCondition::Halt]);
+    assert_eq!(runner.run(&mut machine.state, &mut machine.bus), StopReason::Halted);
+    //bbaah shifted left by 3, high byte
+    assert_eq!(machine.state.a, 0xdd);
+
+    //Each offset from 0 to 7, only the low 3 bits of port 2 count
+    let mut bus = InvadersBus::new();
+    bus.output(4, 0xaa);
+    bus.output(4, 0xbb);
+    let shifted: Vec<u8> = (0..8).map(|offset| {
+        bus.output(2, 0xf8 | offset);
+        bus.input(3)
+    }).collect();
+    assert_eq!(shifted, [0xbb, 0x77, 0xee, 0xdd, 0xba, 0x75, 0xea, 0xd5]);
+
+    //A third write pushes the first byte out
+    bus.output(2, 0);
+    bus.output(4, 0xcc);
+    assert_eq!(bus.input(3), 0xcc);
+    bus.output(2, 4);
+    assert_eq!(bus.input(3), 0xcb);
+}
+
+#[test]
+fn input_bits() {
+    let inputs = [
+        (Input::Coin, 1, 0x01),
+        (Input::P2Start, 1, 0x02),
+        (Input::P1Start, 1, 0x04),
+        (Input::P1Fire, 1, 0x10),
+        (Input::P1Left, 1, 0x20),
+        (Input::P1Right, 1, 0x40),
+        (Input::Tilt, 2, 0x04),
+        (Input::P2Fire, 2, 0x10),
+        (Input::P2Left, 2, 0x20),
+        (Input::P2Right, 2, 0x40),
+    ];
+    let mut bus = InvadersBus::new();
+    //Bit 3 of port 1 is always set
+    assert_eq!((bus.input(1), bus.input(2)), (0x08, 0x00));
+    for (input, port, bit) in inputs {
+        bus.set_input(input, true);
+        let expected = if port == 1 { (0x08 | bit, 0x00) } else { (0x08, bit) };
+        assert_eq!((bus.input(1), bus.input(2)), expected, "{:?}", input);
+        assert_eq!(bus.ports(), expected);
+        bus.set_input(input, false);
+        assert_eq!((bus.input(1), bus.input(2)), (0x08, 0x00), "{:?}", input);
+    }
+}
+
+//Port 2 bits 0-1 are ships minus 3, bit 3 the early extra ship and bit 7
+//hides the coin info
+#[test]
+fn dip_switches() {
+    let mut bus = InvadersBus::new();
+    for (ships, bits) in [(2, 0x00), (3, 0x00), (4, 0x01), (5, 0x02), (6, 0x03), (9, 0x03)] {
+        bus.dip.ships = ships;
+        assert_eq!(bus.input(2), bits, "{} ships", ships);
+    }
+    bus.dip.ships = 3;
+    bus.dip.extra_ship_early = true;
+    assert_eq!(bus.input(2), 0x08);
+    bus.dip.coin_info = false;
+    assert_eq!(bus.input(2), 0x88);
+    //The DIP switches aren't part of the inputs
+    bus.set_input(Input::P2Fire, true);
+    assert_eq!(bus.input(2), 0x98);
+    assert_eq!(bus.ports(), (0x08, 0x10));
+}
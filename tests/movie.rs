@@ -0,0 +1,148 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use i8080_emu::altair::{Altair, SerialCard};
+use i8080_emu::console::Console;
+use i8080_emu::invaders::{self, SpaceInvaders, CYCLES_PER_FRAME};
+use i8080_emu::movie::{Clock, MovieConsole, Session};
+use i8080_emu::{Runner, StopCondition};
+
+//Console that types each key once the machine reached its cycle and closes
+//after the last one, like a user at the keyboard
+struct Typist {
+    keys: VecDeque<(u64, u8)>,
+    clock: Clock,
+}
+
+impl Console for Typist {
+    fn write(&mut self, _byte: u8) {}
+
+    fn read(&mut self) -> Option<u8> {
+        match self.status() {
+            true => self.keys.pop_front().map(|(_, key)| key),
+            false => None,
+        }
+    }
+
+    fn status(&mut self) -> bool {
+        self.keys.front().is_some_and(|(cycle, _)| *cycle <= self.clock.get())
+    }
+
+    fn closed(&mut self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+//Counts status polls in BC and stores each key plus the count at 1000h on,
+//so the final state depends on when every key arrived
+const ECHO: &[u8] = &[
+    0x21, 0x00, 0x10, //0000 LXI H,1000h
+    0xdb, 0x10, //0003 IN 10h
+    0xe6, 0x01, //0005 ANI 1
+    0xc2, 0x0e, 0x00, //0007 JNZ 000e
+    0x03, //000a INX B
+    0xc3, 0x03, 0x00, //000b JMP 0003
+    0xdb, 0x11, //000e IN 11h
+    0x81, //0010 ADD C
+    0x77, //0011 MOV M,A
+    0x23, //0012 INX H
+    0xd3, 0x11, //0013 OUT 11h
+    0xc3, 0x03, 0x00, //0015 JMP 0003
+];
+
+fn movie_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("i8080-movie-{}-{}.txt", name, std::process::id()))
+}
+
+//Runs ECHO on an Altair until the console closes, typing keys while
+//recording or the movie's keys when replaying
+fn altair(session: &Session, path: &Path, keys: &[(u64, u8)]) -> Result<String, String> {
+    let typist = Typist { keys: keys.iter().copied().collect(), clock: session.clock.clone() };
+    let mut machine = Altair::new(MovieConsole::new(typist, Some(session)), SerialCard::TwoSio);
+    machine.load(ECHO, 0, false).unwrap();
+    let mut runner = Runner::new(vec![StopCondition::Cycles(10_000_000)]);
+    runner.set_clock(Some(session.clock.clone()));
+    assert_eq!(machine.run(&mut runner), None, "the console never closed");
+    session.finish(path, &machine.state, Some(&machine.bus))
+}
+
+#[test]
+fn console_replay() {
+    let path = movie_path("console");
+    let keys = [(1000, b'h'), (1000, b'i'), (25_000, b'!'), (60_000, b'\r')];
+    let recorded = altair(&Session::record("altair", ECHO), &path, &keys).unwrap();
+    assert!(recorded.starts_with("Recorded movie"), "{}", recorded);
+
+    //The movie types the keys, none come from the console
+    let replayed = altair(&Session::replay(&path, "altair", ECHO).unwrap(), &path, &[]);
+    assert!(replayed.as_ref().is_ok_and(|s| s.starts_with("Replay matches")), "{:?}", replayed);
+
+    //Another key leaves a different byte in memory
+    let text = fs::read_to_string(&path).unwrap();
+    let edited = text.replacen(" key 21", " key 22", 1);
+    assert_ne!(edited, text);
+    fs::write(&path, edited).unwrap();
+    let diverged = altair(&Session::replay(&path, "altair", ECHO).unwrap(), &path, &[]);
+    assert!(diverged.as_ref().is_err_and(|e| e.starts_with("Replay diverged")), "{:?}", diverged);
+    fs::remove_file(&path).unwrap();
+}
+
+//Mixes input ports 1 and 2 into 2100h-21ffh with interrupts on, both
+//handlers return right away
+const PORTS: &[u8] = &[
+    0xc3, 0x20, 0x00, //0000 JMP 0020
+    0x00, 0x00, 0x00, 0x00, 0x00, //0003
+    0xfb, //0008 RST 1: EI
+    0xc9, //0009 RET
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //000a
+    0xfb, //0010 RST 2: EI
+    0xc9, //0011 RET
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //0012
+    0x31, 0x00, 0x24, //0020 LXI SP,2400h
+    0x21, 0x00, 0x21, //0023 LXI H,2100h
+    0xfb, //0026 EI
+    0xdb, 0x01, //0027 IN 1
+    0x47, //0029 MOV B,A
+    0xdb, 0x02, //002a IN 2
+    0xa8, //002c XRA B
+    0x86, //002d ADD M
+    0x77, //002e MOV M,A
+    0x2c, //002f INR L
+    0xc3, 0x27, 0x00, //0030 JMP 0027
+];
+
+//Runs PORTS for 20 frames. While recording, coin and fire are pressed in
+//some frames, a replay takes the ports from the movie.
+fn invaders(session: &Session, path: &Path) -> Result<String, String> {
+    let mut machine = SpaceInvaders::new(PORTS).unwrap();
+    let mut runner = Runner::new(vec![StopCondition::Cycles(20 * CYCLES_PER_FRAME)]);
+    runner.set_clock(Some(session.clock.clone()));
+    loop {
+        machine.bus.set_input(invaders::Input::Coin, machine.frame == 3);
+        machine.bus.set_input(invaders::Input::P1Fire, machine.frame % 5 < 2);
+        let ports = session.ports(machine.state.cycles, machine.bus.ports());
+        machine.bus.set_ports(ports);
+        if machine.run_frame(&mut runner).is_some() {
+            break;
+        }
+    }
+    session.finish(path, &machine.state, Some(&machine))
+}
+
+#[test]
+fn port_replay() {
+    let path = movie_path("ports");
+    invaders(&Session::record("invaders", PORTS), &path).unwrap();
+    let replayed = invaders(&Session::replay(&path, "invaders", PORTS).unwrap(), &path);
+    assert!(replayed.as_ref().is_ok_and(|s| s.starts_with("Replay matches")), "{:?}", replayed);
+
+    //Player 2 start pressed with the coin
+    let text = fs::read_to_string(&path).unwrap();
+    let edited = text.replacen(" ports 09 00", " ports 0b 00", 1);
+    assert_ne!(edited, text);
+    fs::write(&path, edited).unwrap();
+    let diverged = invaders(&Session::replay(&path, "invaders", PORTS).unwrap(), &path);
+    assert!(diverged.as_ref().is_err_and(|e| e.starts_with("Replay diverged")), "{:?}", diverged);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,18 @@
+use std::path::Path;
+use std::process::Command;
+
+//Checks tests/no_std, a #![no_std] crate using the cpu core with the std
+//feature turned off
+#[test]
+fn core_builds_without_std() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let output = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .args(["check", "--offline", "--quiet", "--manifest-path"])
+        .arg(root.join("tests/no_std/Cargo.toml"))
+        .arg("--target-dir")
+        .arg(root.join("target/no_std"))
+        .output()
+        .expect("failed to run cargo");
+    assert!(output.status.success(), "the no_std crate did not build:\n{}",
+            String::from_utf8_lossy(&output.stderr));
+}
@@ -0,0 +1,36 @@
+#![no_std]
+
+//Embeds the cpu core the way firmware would: the state lives in a static,
+//port writes go to a fixed buffer and nothing is allocated
+
+use i8080_emu::{Bus, State8080};
+
+static mut STATE: State8080 = State8080::new();
+
+struct Ports {
+    last: [u8; 256],
+}
+
+impl Bus for Ports {
+    fn input(&mut self, port: u8) -> u8 {
+        self.last[port as usize]
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.last[port as usize] = value;
+    }
+}
+
+//Runs the program at 0 for up to steps instructions, returns the cycles taken
+pub fn run(program: &[u8], steps: usize) -> u64 {
+    let state = unsafe { &mut *core::ptr::addr_of_mut!(STATE) };
+    state.memory[..program.len()].copy_from_slice(program);
+    let mut ports = Ports { last: [0; 256] };
+    for _ in 0..steps {
+        if state.halted {
+            break;
+        }
+        i8080_emu::emulate_8080_op(state, &mut ports);
+    }
+    state.cycles
+}
@@ -0,0 +1,173 @@
+use i8080_emu::breakpoints::{Access, AccessKind, Kind};
+use i8080_emu::difftest::{random_state, Rng};
+use i8080_emu::history::{History, Registers};
+use i8080_emu::invaders::InvadersBus;
+use i8080_emu::{NullBus, Runner, State8080, StopCondition, StopReason};
+
+//The runner skips the sentinel when nothing watches the bus. Turning on the
+//history forces it back in, both have to end up in the same state.
+#[test]
+fn unwatched_matches_watched() {
+    let mut rng = Rng::new(49);
+    for _ in 0..50 {
+        let start = random_state(&mut rng);
+        let conditions = || vec![StopCondition::Halt, StopCondition::Address(0x0000), StopCondition::Instructions(2000)];
+
+        let mut fast = start.clone();
+        let mut runner = Runner::new(conditions());
+        let fast_reason = runner.run(&mut fast, &mut NullBus);
+        let fast_counts = (runner.instructions, runner.cycles);
+
+        let mut watched = start.clone();
+        let mut runner = Runner::new(conditions());
+        runner.set_history(Some(History::new(1)));
+        let watched_reason = runner.run(&mut watched, &mut NullBus);
+
+        assert_eq!(fast_reason, watched_reason);
+        assert_eq!(fast_counts, (runner.instructions, runner.cycles));
+        assert_eq!(Registers::save(&fast), Registers::save(&watched));
+        assert!(fast.memory[..] == watched.memory[..], "memory differs");
+    }
+}
+
+//When several conditions hold after the same instruction the first listed wins
+#[test]
+fn first_condition_wins() {
+    let mut state = State8080::new();
+    state.memory[0] = 0x76; //HLT
+    let mut runner = Runner::new(vec![StopCondition::Instructions(1), StopCondition::Halt]);
+    assert_eq!(runner.run(&mut state, &mut NullBus), StopReason::Instructions(1));
+
+    let mut state = State8080::new();
+    state.memory[0] = 0x76;
+    let mut runner = Runner::new(vec![StopCondition::Halt, StopCondition::Instructions(1)]);
+    assert_eq!(runner.run(&mut state, &mut NullBus), StopReason::Halted);
+}
+
+//Fills 2000-2002 with 1, 2, 3 and then writes 2001 again
+const STORES: &[u8] = &[
+    0x21, 0x00, 0x20, //0000 LXI H,2000h
+    0x3e, 0x01, //0003 MVI A,1
+    0x77, //0005 MOV M,A
+    0x23, //0006 INX H
+    0x3c, //0007 INR A
+    0xfe, 0x04, //0008 CPI 4
+    0xc2, 0x05, 0x00, //000a JNZ 0005
+    0x32, 0x01, 0x20, //000d STA 2001h
+    0x76, //0010 HLT
+];
+
+fn recorded(program: &[u8]) -> (Box<State8080>, Runner) {
+    let mut state = Box::new(State8080::new());
+    state.memory[..program.len()].copy_from_slice(program);
+    let mut runner = Runner::new(vec![StopCondition::Halt]);
+    runner.set_history(Some(History::new(100)));
+    (state, runner)
+}
+
+//Stepping back over every instruction ends where the run started
+#[test]
+fn reverse_step() {
+    let (mut state, mut runner) = recorded(STORES);
+    let start = state.clone();
+    assert_eq!(runner.run(&mut state, &mut NullBus), StopReason::Halted);
+    assert_eq!(&state.memory[0x2000..0x2003], &[1, 4, 3]);
+
+    let instructions = runner.instructions;
+    let last = runner.reverse_step(&mut state).unwrap();
+    assert_eq!(last.instruction, instructions);
+    assert_eq!(last.before.pc, 0x0010);
+    let sta = runner.reverse_step(&mut state).unwrap();
+    assert_eq!(sta.before.pc, 0x000d);
+    assert_eq!(state.pc, 0x000d);
+    assert_eq!(&state.memory[0x2000..0x2003], &[1, 2, 3]);
+
+    while runner.reverse_step(&mut state).is_some() {}
+    assert_eq!((runner.instructions, runner.cycles), (0, 0));
+    assert_eq!(Registers::save(&state), Registers::save(&start));
+    assert!(state.memory[..] == start.memory[..], "memory differs");
+}
+
+//Goes back to the last write to a watched address or the last breakpoint
+#[test]
+fn reverse_continue() {
+    let (mut state, mut runner) = recorded(STORES);
+    runner.run(&mut state, &mut NullBus);
+    let watch = runner.breakpoints_mut().add(Kind::Write(0x2000..=0x2000), None);
+    let access = Access { kind: AccessKind::Write, addr: 0x2000, value: 1 };
+    assert_eq!(runner.reverse_continue(&mut state), Some(StopReason::Watchpoint { id: watch, access }));
+    //Stopped before the write, as if it was about to happen
+    assert_eq!(state.pc, 0x0005);
+    assert_eq!(state.memory[0x2000], 0);
+
+    assert!(runner.breakpoints_mut().remove(watch));
+    let start = runner.breakpoints_mut().add(Kind::Exec(0x0003), None);
+    assert_eq!(runner.reverse_continue(&mut state), Some(StopReason::Breakpoint(start)));
+    assert_eq!(runner.reverse_continue(&mut state), None);
+    assert_eq!(state.pc, 0x0000);
+}
+
+#[test]
+fn last_write() {
+    let (mut state, mut runner) = recorded(STORES);
+    runner.run(&mut state, &mut NullBus);
+    let history = runner.history().unwrap();
+    let (record, write) = history.last_write(0x2001).unwrap();
+    assert_eq!(record.before.pc, 0x000d);
+    assert_eq!((write.old, write.value), (2, 4));
+    let (record, write) = history.last_write(0x2002).unwrap();
+    assert_eq!(record.before.pc, 0x0005);
+    assert_eq!((write.old, write.value), (0, 3));
+    assert!(history.last_write(0x2003).is_none());
+}
+
+//Writes through the RAM mirror and to the ROM of Space Invaders
+const MIRRORED: &[u8] = &[
+    0x21, 0x00, 0x64, //0000 LXI H,6400h
+    0x36, 0x11, //0003 MVI M,11h
+    0x32, 0x00, 0x00, //0005 STA 0000
+    0x76, //0008 HLT
+];
+
+//The history keeps where a write landed, undo restores the RAM behind the
+//mirror and the ROM write that never happened is not recorded
+#[test]
+fn history_follows_the_bus() {
+    let (mut state, mut runner) = recorded(MIRRORED);
+    state.memory[0x2400] = 0xaa;
+    let start = state.clone();
+    let mut bus = InvadersBus::new();
+    assert_eq!(runner.run(&mut state, &mut bus), StopReason::Halted);
+    assert_eq!(state.memory[0x2400], 0x11);
+    assert_eq!(state.memory[0x0000], 0x21);
+
+    let history = runner.history().unwrap();
+    let (record, write) = history.last_write(0x2400).unwrap();
+    assert_eq!(record.before.pc, 0x0003);
+    assert_eq!((write.addr, write.old, write.value), (0x6400, 0xaa, 0x11));
+    assert!(history.last_write(0x0000).is_none());
+
+    while runner.reverse_step(&mut state).is_some() {}
+    assert!(state.memory[..] == start.memory[..], "memory differs");
+}
+
+//Only the newest records and their writes are kept
+#[test]
+fn history_capacity() {
+    let (mut state, mut runner) = recorded(STORES);
+    runner.set_history(Some(History::new(3)));
+    runner.run(&mut state, &mut NullBus);
+    let history = runner.history_mut().unwrap();
+    assert_eq!(history.len(), 3);
+    assert!(history.last_write(0x2001).is_some());
+    assert!(history.last_write(0x2002).is_none());
+    history.set_capacity(1);
+    assert_eq!(history.len(), 1);
+    assert!(history.last_write(0x2001).is_none());
+
+    runner.history_mut().unwrap().set_capacity(3);
+    runner.reverse_step(&mut state).unwrap();
+    assert!(runner.reverse_step(&mut state).is_none());
+    assert_eq!(state.pc, 0x0010);
+    assert_eq!(state.memory[0x2001], 4);
+}
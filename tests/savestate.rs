@@ -0,0 +1,236 @@
+use std::fs;
+use std::path::PathBuf;
+
+use i8080_emu::altair::{AltairBus, SerialCard};
+use i8080_emu::console::Console;
+use i8080_emu::cpm::Cpm;
+use i8080_emu::cpm22::Cpm22;
+use i8080_emu::disk::{Dcdd, DiskImage, Geometry};
+use i8080_emu::history::Registers;
+use i8080_emu::invaders::SpaceInvaders;
+use i8080_emu::savestate::{self, Device, Reader, SaveState, Writer, MAGIC, VERSION};
+use i8080_emu::State8080;
+
+struct Silent;
+
+impl Console for Silent {
+    fn write(&mut self, _byte: u8) {}
+
+    fn read(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn status(&mut self) -> bool {
+        false
+    }
+}
+
+//Writes contents to a file of its own for the test
+fn file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("i8080-savestate-{}-{}", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn read(name: &str, contents: &[u8]) -> Result<SaveState, String> {
+    let path = file(name, contents);
+    let saved = SaveState::read(&path);
+    fs::remove_file(&path).unwrap();
+    saved
+}
+
+//A cpu with every register, flag and some memory away from the defaults
+fn busy_state() -> Box<State8080> {
+    let mut state = Box::new(State8080::new());
+    state.a = 0x12;
+    state.b = 0x34;
+    state.c = 0x56;
+    state.d = 0x78;
+    state.e = 0x9a;
+    state.h = 0xbc;
+    state.l = 0xde;
+    state.sp = 0xf000;
+    state.pc = 0x1234;
+    state.cc.set_byte(0x93);
+    state.int_enable = true;
+    state.halted = true;
+    state.cycles = 0x0123_4567_89ab;
+    for (i, byte) in state.memory.iter_mut().enumerate() {
+        *byte = (i * 7 + i / 256) as u8;
+    }
+    state
+}
+
+fn device_data(write: impl FnOnce(&mut Writer)) -> Vec<u8> {
+    let mut out = Writer::new();
+    write(&mut out);
+    out.data
+}
+
+//Loads data into saved, saves it and loads the file into fresh. Both have
+//to save the same file.
+fn round_trip(name: &str, saved: &mut dyn Device, fresh: &mut dyn Device, data: &[u8]) {
+    saved.load(&mut Reader::new(data)).unwrap();
+    let state = busy_state();
+    let encoded = savestate::encode(&state, Some(&*saved));
+
+    let loaded = read(name, &encoded).unwrap();
+    assert_eq!(loaded.device, Some((saved.tag(), data.to_vec())));
+    let mut restored = Box::new(State8080::new());
+    loaded.load_device(Some(&mut *fresh)).unwrap();
+    loaded.load_cpu(&mut restored);
+    assert_eq!(Registers::save(&restored), Registers::save(&state));
+    assert!(savestate::encode(&restored, Some(&*fresh)) == encoded, "{} saves differently", name);
+}
+
+#[test]
+fn round_trip_without_device() {
+    let state = busy_state();
+    let encoded = savestate::encode(&state, None);
+    let path = file("bare", &encoded);
+    let mut restored = Box::new(State8080::new());
+    savestate::load(&path, &mut restored, None).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(Registers::save(&restored), Registers::save(&state));
+    assert!(restored.memory[..] == state.memory[..], "memory differs");
+    //PSW bit 1 has no flag behind it but survives
+    assert_eq!(restored.cc.to_byte(), 0x93);
+}
+
+#[test]
+fn round_trip_invaders() {
+    let data = device_data(|out| {
+        out.u8(0x09); //port 1
+        out.u8(0x83); //port 2
+        out.u16(0xbeef); //shift register
+        out.u8(5); //shift offset
+        out.u8(0x02); //sound latches
+        out.u8(0x10);
+        out.u64(1234); //frame
+        out.u64(40_000_000); //next interrupt
+        out.u8(2); //next RST
+    });
+    let mut saved = SpaceInvaders::new(&[]).unwrap();
+    let mut fresh = SpaceInvaders::new(&[]).unwrap();
+    round_trip("invaders", &mut saved, &mut fresh, &data);
+    assert_eq!(fresh.frame, 1234);
+}
+
+#[test]
+fn round_trip_cpm() {
+    let data = device_data(|out| {
+        out.u16(0x2000); //DMA
+        out.u8(1); //drive B
+    });
+    let dir = std::env::temp_dir();
+    round_trip("cpm", &mut Cpm::new(Silent, dir.clone()), &mut Cpm::new(Silent, dir), &data);
+}
+
+#[test]
+fn round_trip_altair() {
+    let mut dcdd = Writer::new();
+    Dcdd::new().save(&mut dcdd);
+    let data = device_data(|out| {
+        out.u8(0xa5); //sense switches
+        out.u8(0x15); //ACIA control
+        out.u8(1); //88-DCDD
+        out.data.extend_from_slice(&dcdd.data);
+    });
+    let altair = || {
+        let mut bus = AltairBus::new(Silent, SerialCard::TwoSio);
+        bus.dcdd = Some(Dcdd::new());
+        bus
+    };
+    let mut fresh = altair();
+    round_trip("altair", &mut altair(), &mut fresh, &data);
+    assert_eq!(fresh.sense_switches, 0xa5);
+}
+
+//A CP/M 2.2 machine with a blank IBM 3740 disk in drive A
+fn cpm22(ccp: u16) -> Cpm22<Silent> {
+    let path = file("drive-a", &[]);
+    let image = DiskImage::open(&path, Geometry::IBM_3740, true).unwrap();
+    fs::remove_file(&path).unwrap();
+    Cpm22::new(Silent, image, Some(ccp)).unwrap()
+}
+
+#[test]
+fn round_trip_cpm22() {
+    let data = device_data(|out| {
+        out.u16(0xe400); //CCP base
+        out.u8(2); //disk C
+        out.u16(40); //track
+        out.u16(17); //sector
+        out.u16(0x3000); //DMA
+    });
+    round_trip("cpm22", &mut cpm22(0xe400), &mut cpm22(0xe400), &data);
+
+    //The system on the disk has to be the one the state was saved with
+    let mut other = cpm22(0xdc00);
+    assert!(other.load(&mut Reader::new(&data)).is_err());
+}
+
+//Chunks a reader doesn't know are skipped
+#[test]
+fn unknown_chunks() {
+    let mut encoded = savestate::encode(&busy_state(), None);
+    encoded.extend_from_slice(b"NEW \x03\x00\x00\x00abc");
+    let loaded = read("unknown", &encoded).unwrap();
+    assert_eq!(loaded.registers, Registers::save(&busy_state()));
+    assert_eq!(loaded.device, None);
+}
+
+fn error(name: &str, contents: &[u8]) -> String {
+    match read(name, contents) {
+        Ok(_) => panic!("{} loaded", name),
+        Err(e) => e,
+    }
+}
+
+#[test]
+fn rejects_malformed_files() {
+    let encoded = savestate::encode(&busy_state(), None);
+
+    let mut magic = encoded.clone();
+    magic[0] = b'X';
+    assert!(error("magic", &magic).ends_with("is not a save state"));
+    assert!(error("short", &MAGIC[..]).ends_with("is not a save state"));
+
+    let mut version = encoded.clone();
+    version[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+    assert_eq!(error("version", &version), format!("Unsupported save state version {}", VERSION + 1));
+
+    assert_eq!(error("truncated", &encoded[..encoded.len() - 1]), "Save state is truncated");
+    assert_eq!(error("header", &encoded[..encoded.len() - 0x10000 - 2]), "Save state is truncated");
+
+    let mut memory = encoded[..10].to_vec();
+    let cpu_len = 8 + 22;
+    memory.extend_from_slice(&encoded[10..10 + cpu_len]);
+    memory.extend_from_slice(b"MEM \x00\x01\x00\x00");
+    memory.extend_from_slice(&[0; 0x100]);
+    assert_eq!(error("memory", &memory), "Save state memory has the wrong size");
+    assert_eq!(error("no-memory", &encoded[..10 + cpu_len]), "Save state has no memory chunk");
+}
+
+//A device chunk only loads into the machine it was saved from
+#[test]
+fn rejects_other_machines() {
+    let state = busy_state();
+    let invaders = SpaceInvaders::new(&[]).unwrap();
+    let with_device = read("invaders-device", &savestate::encode(&state, Some(&invaders))).unwrap();
+    let bare = read("no-device", &savestate::encode(&state, None)).unwrap();
+
+    let mut cpm = Cpm::new(Silent, std::env::temp_dir());
+    let different = Err("Save state is for a different machine".to_string());
+    assert_eq!(with_device.load_device(Some(&mut cpm)), different);
+    assert_eq!(with_device.load_device(None), different);
+    assert_eq!(bare.load_device(Some(&mut cpm)), different);
+    assert_eq!(with_device.load_device(Some(&mut SpaceInvaders::new(&[]).unwrap())), Ok(()));
+
+    //The Altair's disk controller has to match too
+    let mut altair = AltairBus::new(Silent, SerialCard::TwoSio);
+    altair.dcdd = Some(Dcdd::new());
+    let saved = read("altair-dcdd", &savestate::encode(&state, Some(&altair))).unwrap();
+    let mut without = AltairBus::new(Silent, SerialCard::TwoSio);
+    assert_eq!(saved.load_device(Some(&mut without)), Err("Save state has a different disk controller".to_string()));
+}
@@ -0,0 +1,58 @@
+use std::path::Path;
+use std::process::Command;
+
+use i8080_emu::{emulate_8080_op, NullBus, State8080};
+
+//Runs the sst command on a directory of vectors and fails with its report
+fn run_vectors(dir: &Path) {
+    let output = Command::new(env!("CARGO_BIN_EXE_i8080-emu"))
+        .arg("sst")
+        .arg(dir)
+        .output()
+        .expect("failed to run i8080-emu");
+    let report = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "single step tests in {} failed:\n{}", dir.display(), report);
+}
+
+//Hand checked cases for a few opcodes of each group, kept in the repo. They
+//leave out the cycles, those are checked by fixture_cycles.
+#[test]
+fn fixture_vectors() {
+    run_vectors(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sst"));
+}
+
+//Cycle counts of the fixture opcodes, CNZ with zero clear and set
+#[test]
+fn fixture_cycles() {
+    for (code, zero, cycles) in [
+        (&[0x00][..], false, 4), //NOP
+        (&[0x1f], false, 4), //RAR
+        (&[0x27], false, 4), //DAA
+        (&[0x3c], false, 5), //INR A
+        (&[0xc3, 0x34, 0x12], false, 10), //JMP 1234h
+        (&[0xc4, 0x00, 0x30], false, 17), //CNZ 3000h
+        (&[0xc4, 0x00, 0x30], true, 11),
+        (&[0xc5], false, 11), //PUSH B
+        (&[0xc6, 0x42], false, 7), //ADI 42h
+        (&[0xd3, 0x10], false, 10), //OUT 10h
+        (&[0xdb, 0x20], false, 10), //IN 20h
+    ].iter() {
+        let mut state = State8080::new();
+        state.pc = 0x0100;
+        state.sp = 0x2000;
+        state.cc.z = *zero;
+        state.memory[0x0100..0x0100 + code.len()].copy_from_slice(code);
+        assert_eq!(emulate_8080_op(&mut state, &mut NullBus), *cycles, "{:02x}", code[0]);
+    }
+}
+
+//The full per-opcode suite is too large for the repo, point I8080_SST_DIR at
+//its directory of 00.json to ff.json and run the ignored tests
+#[test]
+#[ignore = "needs I8080_SST_DIR"]
+fn full_suite() {
+    let dir = std::env::var_os("I8080_SST_DIR").expect("I8080_SST_DIR is not set");
+    let dir = Path::new(&dir);
+    assert!(dir.is_dir(), "I8080_SST_DIR {} is not a directory", dir.display());
+    run_vectors(dir);
+}
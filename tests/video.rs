@@ -0,0 +1,182 @@
+use i8080_emu::video::{self, Framebuffer, Overlay, RenderConfig, Rotation};
+
+fn pixel(fb: &Framebuffer, x: usize, y: usize) -> [u8; 3] {
+    let i = (y * fb.width + x) * 3;
+    [fb.pixels[i], fb.pixels[i + 1], fb.pixels[i + 2]]
+}
+
+//Coordinates of every pixel that isn't black
+fn lit(fb: &Framebuffer) -> Vec<(usize, usize)> {
+    let mut lit = Vec::new();
+    for y in 0..fb.height {
+        for x in 0..fb.width {
+            if pixel(fb, x, y) != [0, 0, 0] {
+                lit.push((x, y));
+            }
+        }
+    }
+    lit
+}
+
+//16x8 bitmap at 0100h in white on black
+fn small(rotation: Rotation) -> RenderConfig {
+    RenderConfig {
+        base: 0x0100,
+        width: 16,
+        height: 8,
+        rotation,
+        foreground: [0xff, 0xff, 0xff],
+        background: [0x00, 0x00, 0x00],
+        overlays: Vec::new(),
+        scale: 1,
+    }
+}
+
+//Bit 1 of the first byte is pixel 1 of row 0 and ends up in each corner's
+//neighbourhood as the monitor turns
+#[test]
+fn rotation() {
+    let mut memory = vec![0; 0x10000];
+    memory[0x0100] = 0x02;
+    let cases = [
+        (Rotation::None, (16, 8), (1, 0)),
+        (Rotation::Cw, (8, 16), (7, 1)),
+        (Rotation::Half, (16, 8), (14, 7)),
+        (Rotation::Ccw, (8, 16), (0, 14)),
+    ];
+    for (rotation, size, at) in cases {
+        let fb = video::render(&memory, &small(rotation));
+        assert_eq!((fb.width, fb.height), size, "{:?}", rotation);
+        assert_eq!(lit(&fb), [at], "{:?}", rotation);
+    }
+
+    //Rows are width / 8 bytes, bits least significant first
+    memory[0x0100] = 0;
+    memory[0x0100 + 2 * 5 + 1] = 0x80;
+    assert_eq!(lit(&video::render(&memory, &small(Rotation::None))), [(15, 5)]);
+
+    //Scaling repeats each pixel in a square
+    let mut config = small(Rotation::None);
+    config.scale = 2;
+    let fb = video::render(&memory, &config);
+    assert_eq!((fb.width, fb.height), (32, 16));
+    assert_eq!(lit(&fb), [(30, 10), (31, 10), (30, 11), (31, 11)]);
+}
+
+//Sets the bit behind pixel x, y of the upright Space Invaders picture
+fn set(memory: &mut [u8], x: usize, y: usize) {
+    let bit = x * 256 + 255 - y;
+    memory[0x2400 + bit / 8] |= 1 << (bit % 8);
+}
+
+#[test]
+fn invaders_overlays() {
+    const WHITE: [u8; 3] = [0xff, 0xff, 0xff];
+    const RED: [u8; 3] = [0xff, 0x20, 0x20];
+    const GREEN: [u8; 3] = [0x20, 0xff, 0x20];
+    let mut memory = vec![0; 0x10000];
+    let pixels = [
+        ((10, 10), WHITE),
+        ((10, 31), WHITE),
+        ((10, 32), RED),
+        ((223, 63), RED),
+        ((10, 64), WHITE),
+        ((10, 183), WHITE),
+        ((10, 184), GREEN),
+        ((223, 239), GREEN),
+        ((15, 245), WHITE),
+        ((16, 240), GREEN),
+        ((133, 255), GREEN),
+        ((134, 245), WHITE),
+    ];
+    for ((x, y), _) in pixels {
+        set(&mut memory, x, y);
+    }
+    let fb = video::render(&memory, &RenderConfig::invaders());
+    assert_eq!((fb.width, fb.height), (224, 256));
+    for ((x, y), color) in pixels {
+        assert_eq!(pixel(&fb, x, y), color, "pixel {}, {}", x, y);
+    }
+    //The gel only colours lit pixels
+    assert_eq!(pixel(&fb, 11, 40), [0, 0, 0]);
+    assert_eq!(lit(&fb).len(), pixels.len());
+}
+
+//8x2 bitmap with three pixels on in custom colours
+fn tiny() -> Framebuffer {
+    let mut memory = vec![0; 0x10000];
+    memory[0x0100] = 0x05;
+    memory[0x0101] = 0x80;
+    let config = RenderConfig {
+        base: 0x0100,
+        width: 8,
+        height: 2,
+        rotation: Rotation::None,
+        foreground: [1, 2, 3],
+        background: [4, 5, 6],
+        overlays: vec![Overlay { x: 2, y: 0, width: 1, height: 1, color: [7, 8, 9] }],
+        scale: 1,
+    };
+    video::render(&memory, &config)
+}
+
+#[test]
+fn ppm() {
+    let mut out = Vec::new();
+    tiny().write_ppm(&mut out).unwrap();
+    let mut expected = b"P6\n8 2\n255\n".to_vec();
+    //Pixel 2 is under the overlay
+    for color in [1, 4, 7, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 1].iter() {
+        expected.extend_from_slice(&[*color, color + 1, color + 2]);
+    }
+    assert_eq!(out, expected);
+}
+
+fn be32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+//Signature, IHDR, one IDAT of stored zlib blocks and IEND, each chunk with a
+//matching CRC, and the rows behind filter type 0
+#[test]
+fn png() {
+    let fb = tiny();
+    let mut out = Vec::new();
+    fb.write_png(&mut out).unwrap();
+    assert_eq!(out[..8], *b"\x89PNG\r\n\x1a\n");
+    //Well known CRC of the empty IEND chunk
+    assert_eq!(video::crc32(0, b"IEND"), 0xae42_6082);
+
+    let mut chunks = Vec::new();
+    let mut rest = &out[8..];
+    while !rest.is_empty() {
+        let len = be32(&rest[..4]) as usize;
+        let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+        let crc = be32(&rest[8 + len..]);
+        assert_eq!(video::crc32(video::crc32(0, kind), data), crc);
+        chunks.push((kind.to_vec(), data.to_vec()));
+        rest = &rest[12 + len..];
+    }
+    let kinds: Vec<&[u8]> = chunks.iter().map(|(kind, _)| &kind[..]).collect();
+    assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+    assert_eq!(chunks[0].1, [0, 0, 0, 8, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+
+    let zlib = &chunks[1].1;
+    let mut raw = Vec::new();
+    for row in fb.pixels.chunks(8 * 3) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+    //A single final stored block
+    let mut expected = vec![0x78, 0x01, 0x01];
+    expected.extend_from_slice(&(raw.len() as u16).to_le_bytes());
+    expected.extend_from_slice(&(!(raw.len() as u16)).to_le_bytes());
+    expected.extend_from_slice(&raw);
+    let (a, b) = raw.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    expected.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    assert_eq!(*zlib, expected);
+    assert!(chunks[2].1.is_empty());
+}
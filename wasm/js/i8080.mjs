@@ -0,0 +1,126 @@
+// JS API over the exports of i8080_wasm.wasm, see src/lib.rs. Works in
+// browsers and Node, neither needs more than WebAssembly.instantiate.
+
+// Register names in the order of i8080_get_register and i8080_set_register
+export const REGISTERS = ['a', 'b', 'c', 'd', 'e', 'h', 'l', 'f', 'sp', 'pc', 'inte', 'halted'];
+
+// Instantiates the module from its bytes (ArrayBuffer or typed array) or a
+// fetch Response and returns its exports, shared by all machines
+export async function instantiate(source) {
+  const { instance } = source instanceof Response
+    ? await WebAssembly.instantiateStreaming(source, {})
+    : await WebAssembly.instantiate(source, {});
+  return instance.exports;
+}
+
+export class I8080 {
+  constructor(exports) {
+    this.exports = exports;
+    this.ptr = exports.i8080_new();
+  }
+
+  // Releases the machine's wasm memory, it can't be used afterwards
+  free() {
+    this.exports.i8080_free(this.ptr);
+    this.ptr = 0;
+  }
+
+  // Views into wasm memory are made on each access, growing the memory
+  // detaches older ones
+  bytes(ptr, length) {
+    return new Uint8Array(this.exports.memory.buffer, ptr, length);
+  }
+
+  // The 64 KiB address space, writes go straight to the machine
+  get memory() {
+    return this.bytes(this.exports.i8080_memory(this.ptr), 0x10000);
+  }
+
+  // Copies program into memory at addr and jumps there
+  load(program, addr = 0) {
+    if (addr + program.length > 0x10000) {
+      throw new RangeError(`program of ${program.length} bytes does not fit at ${addr}`);
+    }
+    this.memory.set(program, addr);
+    this.setRegister('pc', addr);
+  }
+
+  step() {
+    return this.exports.i8080_step(this.ptr);
+  }
+
+  // Runs until at least cycles have passed, returns the cycles run
+  run(cycles) {
+    return this.exports.i8080_run(this.ptr, cycles);
+  }
+
+  // Executes RST num if interrupts are enabled, returns whether it did
+  interrupt(num) {
+    return this.exports.i8080_interrupt(this.ptr, num) !== 0;
+  }
+
+  get cycles() {
+    return this.exports.i8080_cycles(this.ptr);
+  }
+
+  getRegister(name) {
+    return this.exports.i8080_get_register(this.ptr, registerIndex(name));
+  }
+
+  setRegister(name, value) {
+    this.exports.i8080_set_register(this.ptr, registerIndex(name), value);
+  }
+
+  // All registers as an object, inte and halted as booleans
+  get registers() {
+    const registers = {};
+    REGISTERS.forEach((name, i) => {
+      registers[name] = this.exports.i8080_get_register(this.ptr, i);
+    });
+    registers.inte = registers.inte !== 0;
+    registers.halted = registers.halted !== 0;
+    return registers;
+  }
+
+  // Sets the registers present in values
+  set registers(values) {
+    for (const [name, value] of Object.entries(values)) {
+      this.setRegister(name, Number(value));
+    }
+  }
+
+  // Value IN reads from port until it is set again
+  setInput(port, value) {
+    this.bytes(this.exports.i8080_inputs(this.ptr), 256)[port] = value;
+  }
+
+  // OUT writes since the last call as [port, value] pairs
+  takeOutputs() {
+    const count = this.exports.i8080_output_count(this.ptr);
+    const bytes = this.bytes(this.exports.i8080_outputs(this.ptr), count * 2);
+    const outputs = [];
+    for (let i = 0; i < count; i++) {
+      outputs.push([bytes[2 * i], bytes[2 * i + 1]]);
+    }
+    this.exports.i8080_clear_outputs(this.ptr);
+    return outputs;
+  }
+
+  // Video memory at 0x2400 as RGBA pixels, the shape of a canvas ImageData.
+  // data is a copy and stays valid.
+  framebuffer() {
+    const width = this.exports.i8080_frame_width();
+    const height = this.exports.i8080_frame_height();
+    const ptr = this.exports.i8080_framebuffer(this.ptr);
+    const data = new Uint8ClampedArray(this.bytes(ptr, width * height * 4));
+    return { width, height, data };
+  }
+}
+
+function registerIndex(name) {
+  const index = REGISTERS.indexOf(name);
+  if (index < 0) {
+    throw new RangeError(`unknown register '${name}'`);
+  }
+  return index;
+}
@@ -0,0 +1,195 @@
+use i8080_emu::i8080cpu::{self, Bus, State8080};
+use i8080_emu::video::{self, RenderConfig};
+
+//Flat exports for a wasm32-unknown-unknown build, wrapped into a class by
+//js/i8080.mjs. Everything is numbers: a machine is a pointer from i8080_new,
+//memory, port inputs, port outputs and the framebuffer are pointers into the
+//wasm memory that JS views as Uint8Arrays. Passed a null machine, functions
+//do nothing and return 0.
+
+//Register numbers of i8080_get_register and i8080_set_register, flags as the
+//low byte of PSW, inte and halted as 0 or 1
+pub const REGISTERS: [&str; 12] = ["a", "b", "c", "d", "e", "h", "l", "f", "sp", "pc", "inte", "halted"];
+
+//Width and height of the framebuffer, the Space Invaders video RAM at 0x2400
+//on its rotated monitor
+pub const FRAME_WIDTH: u32 = 224;
+pub const FRAME_HEIGHT: u32 = 256;
+
+//Port reads return the value JS last stored for the port, writes are queued
+//as port, value byte pairs until JS clears them
+struct Ports {
+    inputs: [u8; 256],
+    outputs: Vec<u8>,
+}
+
+impl Bus for Ports {
+    fn input(&mut self, port: u8) -> u8 {
+        self.inputs[port as usize]
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.outputs.push(port);
+        self.outputs.push(value);
+    }
+}
+
+pub struct Machine {
+    state: State8080,
+    ports: Ports,
+    config: RenderConfig,
+    //RGBA, for canvas ImageData
+    frame: Vec<u8>,
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_new() -> Box<Machine> {
+    Box::new(Machine {
+        state: State8080::new(),
+        ports: Ports { inputs: [0; 256], outputs: Vec::new() },
+        config: RenderConfig::invaders(),
+        frame: vec![0; (FRAME_WIDTH * FRAME_HEIGHT * 4) as usize],
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_free(machine: Option<Box<Machine>>) {
+    drop(machine);
+}
+
+//The 64 KiB of memory, programs are loaded by writing here
+#[no_mangle]
+pub extern "C" fn i8080_memory(machine: Option<&mut Machine>) -> *mut u8 {
+    machine.map_or(std::ptr::null_mut(), |m| m.state.memory.as_mut_ptr())
+}
+
+//256 bytes, the values IN reads from each port
+#[no_mangle]
+pub extern "C" fn i8080_inputs(machine: Option<&mut Machine>) -> *mut u8 {
+    machine.map_or(std::ptr::null_mut(), |m| m.ports.inputs.as_mut_ptr())
+}
+
+//Port, value pairs written by OUT since the last i8080_clear_outputs.
+//The pointer is only valid until the next instruction.
+#[no_mangle]
+pub extern "C" fn i8080_outputs(machine: Option<&Machine>) -> *const u8 {
+    machine.map_or(std::ptr::null(), |m| m.ports.outputs.as_ptr())
+}
+
+//Number of pairs at i8080_outputs
+#[no_mangle]
+pub extern "C" fn i8080_output_count(machine: Option<&Machine>) -> u32 {
+    machine.map_or(0, |m| (m.ports.outputs.len() / 2) as u32)
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_clear_outputs(machine: Option<&mut Machine>) {
+    if let Some(m) = machine {
+        m.ports.outputs.clear();
+    }
+}
+
+//Executes one instruction, returns its cycles. A halted cpu idles for 4.
+#[no_mangle]
+pub extern "C" fn i8080_step(machine: Option<&mut Machine>) -> u32 {
+    machine.map_or(0, |m| i8080cpu::emulate_8080_op(&mut m.state, &mut m.ports))
+}
+
+//Executes instructions until at least cycles have passed, returns how many did
+#[no_mangle]
+pub extern "C" fn i8080_run(machine: Option<&mut Machine>, cycles: u32) -> u32 {
+    let m = match machine {
+        Some(m) => m,
+        None => return 0,
+    };
+    let mut ran = 0;
+    while ran < cycles {
+        ran += i8080cpu::emulate_8080_op(&mut m.state, &mut m.ports);
+    }
+    ran
+}
+
+//Executes RST num when interrupts are enabled, returns 1 when it was taken
+#[no_mangle]
+pub extern "C" fn i8080_interrupt(machine: Option<&mut Machine>, num: u32) -> u32 {
+    machine.map_or(0, |m| i8080cpu::generate_interrupt(&mut m.state, &mut m.ports, num as u8) as u32)
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_get_register(machine: Option<&Machine>, register: u32) -> u32 {
+    let state = match machine {
+        Some(m) => &m.state,
+        None => return 0,
+    };
+    match register {
+        0 => state.a as u32,
+        1 => state.b as u32,
+        2 => state.c as u32,
+        3 => state.d as u32,
+        4 => state.e as u32,
+        5 => state.h as u32,
+        6 => state.l as u32,
+        7 => state.cc.to_byte() as u32,
+        8 => state.sp as u32,
+        9 => state.pc as u32,
+        10 => state.int_enable as u32,
+        11 => state.halted as u32,
+        _ => 0,
+    }
+}
+
+//Values are truncated to the register's width, unknown registers are ignored
+#[no_mangle]
+pub extern "C" fn i8080_set_register(machine: Option<&mut Machine>, register: u32, value: u32) {
+    let state = match machine {
+        Some(m) => &mut m.state,
+        None => return,
+    };
+    match register {
+        0 => state.a = value as u8,
+        1 => state.b = value as u8,
+        2 => state.c = value as u8,
+        3 => state.d = value as u8,
+        4 => state.e = value as u8,
+        5 => state.h = value as u8,
+        6 => state.l = value as u8,
+        7 => state.cc.set_byte(value as u8),
+        8 => state.sp = value as u16,
+        9 => state.pc = value as u16,
+        10 => state.int_enable = value & 1 != 0,
+        11 => state.halted = value & 1 != 0,
+        _ => {}
+    }
+}
+
+//Cycles since the machine was created, as a double so JS gets a plain number
+#[no_mangle]
+pub extern "C" fn i8080_cycles(machine: Option<&Machine>) -> f64 {
+    machine.map_or(0.0, |m| m.state.cycles as f64)
+}
+
+//Renders video memory and returns FRAME_WIDTH x FRAME_HEIGHT RGBA pixels,
+//valid until the next call
+#[no_mangle]
+pub extern "C" fn i8080_framebuffer(machine: Option<&mut Machine>) -> *const u8 {
+    let m = match machine {
+        Some(m) => m,
+        None => return std::ptr::null(),
+    };
+    let rgb = video::render(&m.state.memory, &m.config);
+    for (rgba, rgb) in m.frame.chunks_exact_mut(4).zip(rgb.pixels.chunks_exact(3)) {
+        rgba[..3].copy_from_slice(rgb);
+        rgba[3] = 0xff;
+    }
+    m.frame.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_frame_width() -> u32 {
+    FRAME_WIDTH
+}
+
+#[no_mangle]
+pub extern "C" fn i8080_frame_height() -> u32 {
+    FRAME_HEIGHT
+}
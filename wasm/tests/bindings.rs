@@ -0,0 +1,71 @@
+use std::slice;
+
+use i8080_wasm::*;
+
+//The exports called natively, the way js/i8080.mjs calls them in wasm
+
+fn load(machine: &mut Machine, program: &[u8], addr: u16) {
+    let memory = unsafe { slice::from_raw_parts_mut(i8080_memory(Some(machine)), 0x10000) };
+    memory[addr as usize..addr as usize + program.len()].copy_from_slice(program);
+    i8080_set_register(Some(machine), 9, addr as u32);
+}
+
+#[test]
+fn ports_and_registers() {
+    let mut machine = i8080_new();
+    load(&mut machine, &[0xdb, 0x10, 0x3c, 0xd3, 0x11, 0x76], 0x100);
+    unsafe { *i8080_inputs(Some(&mut machine)).add(0x10) = 0x29 };
+    while i8080_get_register(Some(&machine), 11) == 0 {
+        i8080_step(Some(&mut machine));
+    }
+
+    assert_eq!(i8080_output_count(Some(&machine)), 1);
+    let outputs = unsafe { slice::from_raw_parts(i8080_outputs(Some(&machine)), 2) };
+    assert_eq!(outputs, [0x11, 0x2a]);
+    i8080_clear_outputs(Some(&mut machine));
+    assert_eq!(i8080_output_count(Some(&machine)), 0);
+    assert_eq!(i8080_get_register(Some(&machine), 9), 0x106);
+    assert_eq!(i8080_cycles(Some(&machine)), 32.0);
+
+    i8080_set_register(Some(&mut machine), 8, 0x12345);
+    assert_eq!(i8080_get_register(Some(&machine), 8), 0x2345);
+    i8080_set_register(Some(&mut machine), 7, 0xd7);
+    assert_eq!(i8080_get_register(Some(&machine), 7), 0xd7);
+    assert_eq!(i8080_get_register(Some(&machine), REGISTERS.len() as u32), 0);
+    i8080_free(Some(machine));
+}
+
+#[test]
+fn interrupt_and_run() {
+    let mut machine = i8080_new();
+    load(&mut machine, &[0x3e, 0x42, 0xc9], 0x38);
+    load(&mut machine, &[0x76], 0);
+    i8080_set_register(Some(&mut machine), 8, 0x100);
+    i8080_step(Some(&mut machine));
+    assert_eq!(i8080_interrupt(Some(&mut machine), 7), 1);
+    assert!(i8080_run(Some(&mut machine), 17) >= 17);
+    assert_eq!(i8080_get_register(Some(&machine), 0), 0x42);
+    assert_eq!(i8080_interrupt(Some(&mut machine), 7), 0);
+    i8080_free(Some(machine));
+}
+
+#[test]
+fn framebuffer() {
+    let mut machine = i8080_new();
+    load(&mut machine, &[0x01], 0x2400);
+    let size = (i8080_frame_width() * i8080_frame_height() * 4) as usize;
+    let frame = unsafe { slice::from_raw_parts(i8080_framebuffer(Some(&mut machine)), size) };
+    let corner = (255 * 224) * 4;
+    assert_eq!(frame[corner..corner + 4], [0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(frame.iter().filter(|v| **v == 0xff).count(), 224 * 256 + 3);
+    i8080_free(Some(machine));
+}
+
+#[test]
+fn null_machine() {
+    assert!(i8080_memory(None).is_null());
+    assert_eq!(i8080_step(None), 0);
+    assert_eq!(i8080_run(None, 100), 0);
+    assert_eq!(i8080_get_register(None, 0), 0);
+    i8080_free(None);
+}
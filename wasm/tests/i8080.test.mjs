@@ -0,0 +1,79 @@
+// Tests js/i8080.mjs against the wasm build, run by tests/node.rs or with
+//   I8080_WASM=<path to i8080_wasm.wasm> node --test tests/i8080.test.mjs
+
+import { test } from 'node:test';
+import assert from 'node:assert/strict';
+import { readFile } from 'node:fs/promises';
+
+import { I8080, REGISTERS, instantiate } from '../js/i8080.mjs';
+
+const exports = await instantiate(await readFile(process.env.I8080_WASM));
+
+test('runs a program with port input and output', () => {
+  const cpu = new I8080(exports);
+  cpu.load([
+    0xdb, 0x10, // 0100 IN 10h
+    0x3c,       // 0102 INR A
+    0xd3, 0x11, // 0103 OUT 11h
+    0x76,       // 0105 HLT
+  ], 0x100);
+  cpu.setInput(0x10, 0x29);
+  while (!cpu.registers.halted) {
+    cpu.step();
+  }
+  assert.deepEqual(cpu.takeOutputs(), [[0x11, 0x2a]]);
+  assert.deepEqual(cpu.takeOutputs(), []);
+  assert.equal(cpu.registers.pc, 0x106);
+  assert.equal(cpu.cycles, 10 + 5 + 10 + 7);
+  cpu.free();
+});
+
+test('registers round trip', () => {
+  const cpu = new I8080(exports);
+  cpu.registers = { a: 0x12, b: 0x34, f: 0xd7, sp: 0xfff0, pc: 0x1234, inte: 0 };
+  const registers = cpu.registers;
+  assert.equal(registers.a, 0x12);
+  assert.equal(registers.b, 0x34);
+  assert.equal(registers.f, 0xd7);
+  assert.equal(registers.sp, 0xfff0);
+  assert.equal(registers.pc, 0x1234);
+  assert.equal(registers.inte, false);
+  assert.deepEqual(Object.keys(registers), REGISTERS);
+  assert.throws(() => cpu.setRegister('x', 1), RangeError);
+  cpu.free();
+});
+
+test('interrupts run the restart handler', () => {
+  const cpu = new I8080(exports);
+  cpu.load([0x76], 0x0000);                   // HLT
+  cpu.memory.set([0x3e, 0x42, 0xc9], 0x0038); // RST 7: MVI A,42h; RET
+  cpu.setRegister('sp', 0x100);
+  cpu.step();
+  assert.equal(cpu.interrupt(7), true);
+  assert.ok(cpu.run(7 + 10) >= 17);
+  assert.equal(cpu.registers.a, 0x42);
+  assert.equal(cpu.interrupt(7), false);
+  cpu.free();
+});
+
+test('framebuffer shows video memory', () => {
+  const cpu = new I8080(exports);
+  let frame = cpu.framebuffer();
+  assert.equal(frame.width, 224);
+  assert.equal(frame.height, 256);
+  assert.equal(frame.data.length, 224 * 256 * 4);
+  assert.ok(frame.data.every((v, i) => i % 4 === 3 ? v === 255 : v === 0));
+
+  // The first byte of video RAM is the bottom left corner of the rotated screen
+  cpu.memory[0x2400] = 0x01;
+  frame = cpu.framebuffer();
+  const i = (255 * 224 + 0) * 4;
+  assert.deepEqual([...frame.data.slice(i, i + 4)], [0xff, 0xff, 0xff, 0xff]);
+  cpu.free();
+});
+
+test('programs past the end of memory are rejected', () => {
+  const cpu = new I8080(exports);
+  assert.throws(() => cpu.load([0, 0], 0xffff), RangeError);
+  cpu.free();
+});
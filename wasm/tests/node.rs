@@ -0,0 +1,41 @@
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+//Builds the crate for wasm32-unknown-unknown and runs tests/i8080.test.mjs on
+//it with Node. Fails when the target or node is not installed.
+#[test]
+#[ignore = "needs the wasm32-unknown-unknown target and node"]
+fn node_tests() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let installed = Command::new("rustc")
+        .args(["--print", "target-libdir", "--target", "wasm32-unknown-unknown"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+        .is_some_and(|dir| dir.exists());
+    assert!(installed, "wasm32-unknown-unknown is not installed, rustup target add wasm32-unknown-unknown");
+    assert!(Command::new("node").arg("--version").output().is_ok(), "node is not installed");
+
+    let target = root.parent().unwrap().join("target/wasm");
+    let built = Command::new(cargo)
+        .args(["build", "--offline", "--quiet", "--release", "--lib", "--target", "wasm32-unknown-unknown"])
+        .arg("--manifest-path")
+        .arg(root.join("Cargo.toml"))
+        .arg("--target-dir")
+        .arg(&target)
+        .output()
+        .expect("failed to run cargo");
+    assert!(built.status.success(), "building the wasm module failed:\n{}",
+            String::from_utf8_lossy(&built.stderr));
+
+    let output = Command::new("node")
+        .arg("--test")
+        .arg(root.join("tests/i8080.test.mjs"))
+        .env("I8080_WASM", target.join("wasm32-unknown-unknown/release/i8080_wasm.wasm"))
+        .output()
+        .expect("failed to run node");
+    assert!(output.status.success(), "node tests failed:\n{}", String::from_utf8_lossy(&output.stdout));
+}